@@ -0,0 +1,361 @@
+//
+// Copyright (c) 2021 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::File,
+    io::BufWriter,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_std::{channel::bounded, task};
+use clap::Parser;
+use futures::prelude::*;
+use futures::select;
+use mcap::{records::MessageHeader, Channel, Schema};
+use serde_json::json;
+use zenoh::{config::WhatAmI, key_expr::KeyExpr, sample::Sample, Config};
+
+/// The metadata of the MCAP channels holding the key the publications were recorded from.
+const ZENOH_KEY_METADATA: &str = "zenoh_key";
+
+/// A ROS2 message type, with the definition of its MCAP schema.
+struct MessageType {
+    name: &'static str,
+    definition: &'static str,
+}
+
+const TWIST: MessageType = MessageType {
+    name: "geometry_msgs/msg/Twist",
+    definition: "geometry_msgs/Vector3 linear
+geometry_msgs/Vector3 angular
+================================================================================
+MSG: geometry_msgs/Vector3
+float64 x
+float64 y
+float64 z
+",
+};
+
+const LASER_SCAN: MessageType = MessageType {
+    name: "sensor_msgs/msg/LaserScan",
+    definition: "std_msgs/Header header
+float32 angle_min
+float32 angle_max
+float32 angle_increment
+float32 time_increment
+float32 scan_time
+float32 range_min
+float32 range_max
+float32[] ranges
+float32[] intensities
+================================================================================
+MSG: std_msgs/Header
+builtin_interfaces/Time stamp
+string frame_id
+================================================================================
+MSG: builtin_interfaces/Time
+int32 sec
+uint32 nanosec
+",
+};
+
+const LOG: MessageType = MessageType {
+    name: "rcl_interfaces/msg/Log",
+    definition: "byte DEBUG=10
+byte INFO=20
+byte WARN=30
+byte ERROR=40
+byte FATAL=50
+builtin_interfaces/Time stamp
+uint8 level
+string name
+string msg
+string file
+string function
+uint32 line
+================================================================================
+MSG: builtin_interfaces/Time
+int32 sec
+uint32 nanosec
+",
+};
+
+#[async_std::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    let args = Args::parse();
+    let config: Config = (&args).into();
+    // The topics of each message type with a schema
+    let typed_topics = [
+        (&args.twist_topics, &TWIST),
+        (&args.laser_scan_topics, &LASER_SCAN),
+        (&args.log_topics, &LOG),
+    ]
+    .map(|(topics, message_type)| (KeyExpr::try_from(topics.clone()).unwrap(), message_type));
+
+    println!("Opening session...");
+    let session = zenoh::open(config).await.unwrap();
+
+    let file = File::create(&args.output).unwrap_or_else(|e| {
+        eprintln!("Failed to create '{}': {}", args.output, e);
+        std::process::exit(-1);
+    });
+    let mut writer = mcap::Writer::new(BufWriter::new(file)).unwrap();
+
+    // Declare subscribers for the recorded keys
+    let mut subscribers = Vec::new();
+    for key in &args.key {
+        println!("Subscriber on '{}'", key);
+        subscribers.push(session.declare_subscriber(key).await.unwrap());
+    }
+    let mut samples = stream::select_all(subscribers.iter().map(|s| s.stream()));
+
+    // Stop recording on CTRL+C, or after --duration
+    let (stop_sender, stop_receiver) = bounded::<()>(1);
+    ctrlc::set_handler(move || {
+        let _ = stop_sender.try_send(());
+    })
+    .unwrap();
+    let deadline = args.duration.map(|duration| Instant::now() + duration);
+    println!("Recording to '{}'... Press CTRL+C to stop.", args.output);
+
+    let mut channels = HashMap::new();
+    let mut count = 0u32;
+    loop {
+        let sample: Sample = select!(
+            sample = samples.next().fuse() => match sample {
+                Some(sample) => sample,
+                None => break,
+            },
+            _ = stop_receiver.recv().fuse() => break,
+            _ = sleep_until(deadline).fuse() => break,
+        );
+
+        // Each key is recorded on a channel, with the schema of its message type if known
+        let channel_id = match channels.entry(sample.key_expr().to_string()) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let message_type = typed_topics
+                    .iter()
+                    .find(|(topics, _)| topics.intersects(sample.key_expr()))
+                    .map(|(_, message_type)| *message_type);
+                let topic = ros2_topic(entry.key());
+                match message_type {
+                    Some(message_type) => println!(
+                        "Record '{}' on topic '{}' ({})",
+                        entry.key(),
+                        topic,
+                        message_type.name
+                    ),
+                    // Without schema, the standard ROS2 tools can't play the channel back
+                    None => println!(
+                        "Record '{}' on topic '{}' (unknown type - only replayable with ros2-replay)",
+                        entry.key(),
+                        topic
+                    ),
+                }
+                // The writer records each schema once, whatever the number of its channels
+                let channel = Channel {
+                    topic,
+                    schema: message_type.map(|message_type| {
+                        Arc::new(Schema {
+                            name: message_type.name.to_string(),
+                            encoding: "ros2msg".to_string(),
+                            data: Cow::Borrowed(message_type.definition.as_bytes()),
+                        })
+                    }),
+                    message_encoding: "cdr".to_string(),
+                    // The topic is not enough to get the key back if it was not under "rt/"
+                    metadata: BTreeMap::from([(
+                        ZENOH_KEY_METADATA.to_string(),
+                        entry.key().clone(),
+                    )]),
+                };
+                *entry.insert(writer.add_channel(&channel).unwrap())
+            }
+        };
+
+        // Publications without timestamp are recorded at their reception time
+        let time = sample
+            .timestamp()
+            .map(|ts| ts.get_time().to_system_time())
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let header = MessageHeader {
+            channel_id,
+            sequence: count,
+            log_time: time,
+            publish_time: time,
+        };
+        if let Err(e) = writer.write_to_known_channel(&header, &sample.payload().to_bytes()) {
+            log::warn!(
+                "Failed to record publication on '{}': {}",
+                sample.key_expr(),
+                e
+            );
+        }
+        count += 1;
+    }
+
+    writer.finish().unwrap();
+    println!("Recorded {} publications in '{}'", count, args.output);
+}
+
+/// Sleeps until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => task::sleep(deadline.saturating_duration_since(Instant::now())).await,
+        None => future::pending().await,
+    }
+}
+
+/// Parses a duration in seconds.
+fn parse_duration(secs: &str) -> Result<Duration, String> {
+    let secs = secs.parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string())
+}
+
+/// The ROS2 topic of a key of the zenoh/DDS bridge, e.g. "/turtle1/cmd_vel" for "rt/turtle1/cmd_vel".
+fn ros2_topic(key: &str) -> String {
+    format!("/{}", key.strip_prefix("rt/").unwrap_or(key))
+}
+
+#[derive(clap::Parser, Clone, Debug)]
+pub struct Args {
+    #[arg(short, long)]
+    /// A configuration file.
+    config: Option<String>,
+    #[arg(long)]
+    /// Allows arbitrary configuration changes as column-separated KEY:VALUE pairs, where:
+    ///   - KEY must be a valid config path.
+    ///   - VALUE must be a valid JSON5 string that can be deserialized to the expected type for the KEY field.
+    ///
+    /// Example: `--cfg='transport/unicast/max_links:2'`
+    #[arg(long)]
+    cfg: Vec<String>,
+    #[arg(short, long)]
+    /// The Zenoh session mode [default: peer].
+    mode: Option<WhatAmI>,
+    #[arg(short = 'e', long)]
+    /// Endpoints to connect to.
+    connect: Vec<String>,
+    #[arg(short, long)]
+    /// Endpoints to listen on.
+    listen: Vec<String>,
+    #[arg(long)]
+    /// Disable the multicast-based scouting mechanism.
+    no_multicast_scouting: bool,
+    #[arg(long)]
+    /// Enable shared-memory feature.
+    enable_shm: bool,
+
+    #[arg(short, long, default_value = "rt/**")]
+    // The keys to record, possibly with wildcards. Can be repeated.
+    key: Vec<String>,
+    #[arg(short, long, default_value = "recording.mcap")]
+    // The MCAP file to write.
+    output: String,
+    #[arg(short, long, value_parser = parse_duration)]
+    // Stop recording after this duration (in seconds), instead of on CTRL+C.
+    duration: Option<Duration>,
+    #[arg(long, default_value = "**/cmd_vel")]
+    // The topics of the Twists.
+    twist_topics: String,
+    #[arg(long, default_value = "**/scan")]
+    // The topics of the LaserScans.
+    laser_scan_topics: String,
+    #[arg(long, default_value = "**/rosout")]
+    // The topics of the Logs.
+    log_topics: String,
+}
+
+impl From<&Args> for Config {
+    fn from(args: &Args) -> Self {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path).unwrap(),
+            None => Config::default(),
+        };
+        if let Some(mode) = args.mode {
+            config
+                .insert_json5("mode", &json!(mode.to_str()).to_string())
+                .unwrap();
+        }
+
+        if !args.connect.is_empty() {
+            config
+                .insert_json5("connect/endpoints", &json!(args.connect).to_string())
+                .unwrap();
+        }
+        if !args.listen.is_empty() {
+            config
+                .insert_json5("listen/endpoints", &json!(args.listen).to_string())
+                .unwrap();
+        }
+        if args.no_multicast_scouting {
+            config
+                .insert_json5("scouting/multicast/enabled", &json!(false).to_string())
+                .unwrap();
+        }
+        if args.enable_shm {
+            #[cfg(feature = "shared-memory")]
+            config
+                .insert_json5("transport/shared_memory/enabled", &json!(true).to_string())
+                .unwrap();
+            #[cfg(not(feature = "shared-memory"))]
+            {
+                eprintln!("`--enable-shm` argument: SHM cannot be enabled, because Zenoh is compiled without shared-memory feature!");
+                std::process::exit(-1);
+            }
+        }
+        for json in &args.cfg {
+            if let Some((key, value)) = json.split_once(':') {
+                if let Err(err) = config.insert_json5(key, value) {
+                    eprintln!("`--cfg` argument: could not parse `{json}`: {err}");
+                    std::process::exit(-1);
+                }
+            } else {
+                eprintln!("`--cfg` argument: expected KEY:VALUE pair, got {json}");
+                std::process::exit(-1);
+            }
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_rejects_invalid_durations() {
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+        for secs in ["", "abc", "-1", "inf", "NaN", "1e300"] {
+            assert!(parse_duration(secs).is_err(), "'{}' was accepted", secs);
+        }
+    }
+
+    #[test]
+    fn ros2_topic_strips_bridge_prefix_only() {
+        assert_eq!(ros2_topic("rt/turtle1/cmd_vel"), "/turtle1/cmd_vel");
+        assert_eq!(ros2_topic("demo/x"), "/demo/x");
+    }
+}
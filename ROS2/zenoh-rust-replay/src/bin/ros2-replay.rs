@@ -11,14 +11,47 @@
 // Contributors:
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
-use async_std::task;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    convert::TryFrom,
+    fmt,
+    fs::File,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_std::{
+    channel::bounded,
+    task::{self, JoinHandle},
+};
 use cdr::CdrLe;
 use cdr::Infinite;
 use clap::Parser;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use futures::prelude::*;
+use futures::select;
+use memmap2::Mmap;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::json;
-use zenoh::{bytes::ZBytes, config::WhatAmI, query::ConsolidationMode, Config};
+use zenoh::{
+    bytes::ZBytes,
+    config::WhatAmI,
+    key_expr::KeyExpr,
+    pubsub::Publisher,
+    query::ConsolidationMode,
+    time::{Timestamp, NTP64},
+    Config, Session,
+};
+
+/// The seek step of the Left and Right keys, in seconds.
+const SEEK_STEP: f64 = 5.0;
+
+/// The metadata of the MCAP channels recorded by ros2-record holding their key.
+const ZENOH_KEY_METADATA: &str = "zenoh_key";
+
+/// The bounds of the playback speed.
+const MIN_SPEED: f64 = 1.0 / 64.0;
+const MAX_SPEED: f64 = 64.0;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 struct Vector3 {
@@ -40,84 +73,886 @@ async fn main() {
 
     let args = Args::parse();
     let config: Config = (&args).into();
+    let twist_topics = KeyExpr::try_from(args.twist_topics.clone()).unwrap();
+    if !(MIN_SPEED..=MAX_SPEED).contains(&args.time_scale) {
+        eprintln!(
+            "`--time-scale` argument: must be between {} and {}, got {}",
+            MIN_SPEED, MAX_SPEED, args.time_scale
+        );
+        std::process::exit(-1);
+    }
 
     println!("Opening session...");
     let session = zenoh::open(config).await.unwrap();
-    let publisher = session.declare_publisher(args.output_topic).await.unwrap();
-
-    let mut query_selector = args.input_topic;
-    if !args.filter.is_empty() {
-        query_selector.push('?');
-        query_selector.push_str(&args.filter);
-    }
-
-    // Get stored publications
-    println!("Sending Query '{}'...", query_selector);
-    let mut replies = session
-        .get(&query_selector)
-        .consolidation(ConsolidationMode::None)
-        .await
-        .unwrap()
-        .stream()
-        .filter_map(|r| async move { r.into_result().ok() })
-        .collect::<Vec<_>>()
-        .await;
-
-    // Sort publications by timestamps
-    replies.sort_by(|a, b| a.timestamp().partial_cmp(&b.timestamp()).unwrap());
 
-    if replies.is_empty() {
-        println!("No publications found - nothing to replay.");
-        return;
-    }
-
-    let first_ts = replies.first().unwrap().timestamp().unwrap();
-    let last_ts = replies.last().unwrap().timestamp().unwrap();
-    println!(
-        "Replay {} publications that were made between {} and {} ",
-        replies.len(),
-        first_ts.get_time(),
-        last_ts.get_time(),
-    );
+    // Get stored or MCAP recorded publications of all the input topics, merged in a single timeline:
+    // all at once, or with --window by pages of the storage buffered ahead of the playback
+    let (mut player, storage) = match args.window {
+        None => {
+            let publications = match &args.mcap {
+                Some(path) => read_mcap(&session, path, &args.input_topic).unwrap_or_else(|e| {
+                    eprintln!("Failed to read '{}': {}", path, e);
+                    std::process::exit(-1);
+                }),
+                None => query_publications(&session, &args.input_topic, &args.filter).await,
+            };
+            let (Some((first_ts, _, _)), Some((last_ts, _, _))) =
+                (publications.first(), publications.last())
+            else {
+                println!("No publications found - nothing to replay.");
+                return;
+            };
+            let duration = last_ts.get_diff_duration(first_ts);
+            println!(
+                "Replay {} publications that were made between {} and {} ",
+                publications.len(),
+                first_ts.get_time(),
+                last_ts.get_time(),
+            );
+            let mut player = Player::new(
+                first_ts.get_time().to_system_time(),
+                duration,
+                args.time_scale,
+                args.loop_replay,
+            );
+            player.load(Duration::ZERO, duration, publications);
+            (player, None)
+        }
+        Some(_) if args.mcap.is_some() => {
+            eprintln!("`--window` argument: only a storage can be streamed, not a MCAP file");
+            std::process::exit(-1);
+        }
+        Some(window) => {
+            let storage = Storage::new(&session, &args, window).unwrap_or_else(|e| {
+                eprintln!("{e}");
+                std::process::exit(-1);
+            });
+            println!(
+                "Stream the publications that were made between {} and {}, by windows of {} seconds",
+                humantime::format_rfc3339_nanos(storage.start),
+                humantime::format_rfc3339_nanos(storage.start + storage.duration),
+                storage.window.as_secs_f32(),
+            );
+            let player = Player::new(
+                storage.start,
+                storage.duration,
+                args.time_scale,
+                args.loop_replay,
+            );
+            (player, Some(storage))
+        }
+    };
     println!(
         "Initial duration: {} seconds => with time-scale={}, new duration: {} seconds",
-        last_ts.get_diff_duration(first_ts).as_secs_f32(),
+        player.duration.as_secs_f32(),
         args.time_scale,
-        last_ts
-            .get_diff_duration(first_ts)
-            .mul_f64(args.time_scale)
-            .as_secs_f32(),
+        player.duration.mul_f64(args.time_scale).as_secs_f32(),
     );
 
-    let mut ts = None;
-    for s in replies {
-        // compute time difference and sleep (*time_scale)
-        let now = match (ts, s.timestamp()) {
-            (Some(t1), Some(t2)) => {
-                task::sleep(t2.get_diff_duration(&t1).mul_f64(args.time_scale)).await;
-                t2
+    // Declare subscriber for the playback commands, and queryable for the playback status
+    println!("Playback commands on '{}'", args.control_key);
+    let control = session.declare_subscriber(&args.control_key).await.unwrap();
+    let status_key = format!("{}/status", args.control_key);
+    let status = session.declare_queryable(&status_key).await.unwrap();
+
+    // Keyboard event read loop, sending each to an async_std channel, as in ros2-teleop.
+    // Note: in raw mode new line characters are not processed on println!().
+    // Thus print!("...\r\n") is used instead.
+    let (key_sender, key_receiver) = bounded::<Event>(10);
+    let _key_sender = key_sender.clone();
+    if args.interactive {
+        crossterm::terminal::enable_raw_mode().unwrap();
+        task::spawn(async move {
+            loop {
+                match crossterm::event::read() {
+                    Ok(ev) => {
+                        if let Err(e) = key_sender.send(ev).await {
+                            log::warn!("Failed to push Key Event: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("Input error: {}", e);
+                    }
+                }
+            }
+        });
+        print!("SPACE: pause/resume, 's': step, LEFT/RIGHT: seek -/+{}s, HOME: restart, '+'/'-': speed x2 / x0.5, '1': normal speed, 'l': loop, ESC/'q': quit\r\n", SEEK_STEP);
+    }
+
+    let mut output = Output {
+        session: &session,
+        publishers: HashMap::new(),
+        twist_topics,
+        args: &args,
+    };
+    if args.paused {
+        player.pause();
+    }
+    // Once controlled, the player pauses at the end of the recording instead of exiting
+    let mut controlled = args.interactive || args.paused;
+    let mut fetch = None;
+
+    loop {
+        // Page through the storage ahead of the playback cursor
+        if let Some(storage) = &storage {
+            player.drop_played();
+            if matches!(&fetch, Some(Fetch { from, .. }) if *from != player.loaded_until) {
+                // The playback was sought away from this window: stop fetching it
+                if let Some(stale) = fetch.take() {
+                    stale.handle.cancel().await;
+                }
+            }
+            if fetch.is_none() {
+                fetch = player
+                    .next_window(storage.window * args.prefetch)
+                    .map(|from| storage.fetch(from));
+            }
+        }
+
+        if player.at_end() && player.is_playing() {
+            if player.looping {
+                print!("End of the recording - looping\r\n");
+                player.seek(Duration::ZERO);
+            } else if controlled {
+                player.pause();
+                print!("End of the recording - paused\r\n");
+            } else {
+                break;
+            }
+        }
+
+        let command = select!(
+            // On the time of the next publication to replay
+            _ = sleep(player.next_delay()).fuse() => {
+                let publication = player.next();
+                output.replay(publication).await;
+                None
+            },
+
+            // On a window of publications fetched from the storage
+            (from, to, publications) = fetched(&mut fetch).fuse() => {
+                player.load(from, to, publications);
+                None
+            },
+
+            // On keyboard event received from the async_std channel
+            event = key_receiver.recv().fuse() => match event {
+                Ok(Event::Key(key)) => key_command(key),
+                Ok(_) => None,
+                Err(e) => {
+                    log::warn!("Input error: {}", e);
+                    None
+                }
+            },
+
+            // On playback command received on the control key
+            sample = control.recv_async().fuse() => {
+                let sample = sample.unwrap();
+                let text = sample.payload().try_to_string().unwrap_or_default();
+                match text.parse::<Command>() {
+                    Ok(command) => {
+                        controlled = true;
+                        Some(command)
+                    }
+                    Err(e) => {
+                        log::warn!("Invalid playback command '{}': {}", text, e);
+                        None
+                    }
+                }
+            },
+
+            // On query of the playback status
+            query = status.recv_async().fuse() => {
+                let query = query.unwrap();
+                if let Err(e) = query.reply(&status_key, player.status().to_string()).await {
+                    log::warn!("Failed to reply playback status: {}", e);
+                }
+                None
+            }
+        );
+
+        match command {
+            Some(Command::Quit) => break,
+            Some(command) => {
+                if let Some(publication) = player.apply(command) {
+                    output.replay(publication).await;
+                }
+                print!("{}\r\n", player);
+            }
+            None => (),
+        }
+    }
+
+    if args.interactive {
+        crossterm::terminal::disable_raw_mode().unwrap();
+    }
+}
+
+/// Sleeps for `delay`, or forever if there is none.
+async fn sleep(delay: Option<Duration>) {
+    match delay {
+        Some(delay) => task::sleep(delay).await,
+        None => future::pending().await,
+    }
+}
+
+/// Republishes the replayed publications on their output topics.
+struct Output<'a> {
+    session: &'a Session,
+    publishers: HashMap<String, Publisher<'a>>,
+    twist_topics: KeyExpr<'a>,
+    args: &'a Args,
+}
+
+impl Output<'_> {
+    async fn replay(&mut self, publication: &Publication) {
+        let now = publication.timestamp;
+
+        // Each publication is replayed on its remapped topic
+        let Some(output_topic) = output_topic(self.args, &publication.key_expr) else {
+            print!(
+                "[{}] No remapping rule for '{}' - skipped\r\n",
+                now.get_time(),
+                publication.key_expr
+            );
+            return;
+        };
+        let publisher = match self.publishers.entry(output_topic) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let publisher = self
+                    .session
+                    .declare_publisher(entry.key().clone())
+                    .await
+                    .unwrap();
+                entry.insert(publisher)
             }
-            (None, Some(t2)) => t2,
-            _ => panic!(),
         };
-        ts = s.timestamp().copied();
 
-        println!(
-            "[{}] Replay publication from '{}' to '{}'",
+        print!(
+            "[{}] Replay publication from '{}' to '{}'\r\n",
             now.get_time(),
-            s.key_expr(),
+            publication.key_expr,
             publisher.key_expr()
         );
-        println!("   {:?}", s.payload());
+        print!("   {:?}\r\n", publication.payload);
 
-        if args.twist {
+        if self.args.twist && self.twist_topics.intersects(&publication.key_expr) {
             // payload is a Twist, apply scales and replay
-            let new_payload = transform_twist(s.payload(), args.linear_scale, args.angular_scale);
-            println!(" ! {:?} ", new_payload);
+            let new_payload = transform_twist(
+                &publication.payload,
+                self.args.linear_scale,
+                self.args.angular_scale,
+            );
+            print!(" ! {:?} \r\n", new_payload);
             publisher.put(new_payload).await.unwrap();
         } else {
             // replay payload unchanged
-            publisher.put(s.payload().clone()).await.unwrap();
+            publisher.put(publication.payload.clone()).await.unwrap();
+        }
+    }
+}
+
+/// A playback command, from the keyboard or the control key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Play,
+    Pause,
+    TogglePause,
+    /// Replays the next publication, and pauses
+    Step,
+    /// Seeks to a time of the recording, in seconds since its start
+    Seek(f64),
+    /// Seeks forward or backward, in seconds
+    SeekBy(f64),
+    /// Sets the playback speed (i.e. 1 / time scale)
+    Speed(f64),
+    /// Multiplies the playback speed
+    SpeedBy(f64),
+    /// Enables, disables or toggles looping
+    Loop(Option<bool>),
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    /// Parses "play", "pause", "toggle", "step", "seek SECS" (relative if signed, e.g. "seek -5"),
+    /// "speed FACTOR", "loop [on|off]" or "quit".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("play"), None) | (Some("resume"), None) => Command::Play,
+            (Some("pause"), None) => Command::Pause,
+            (Some("toggle"), None) => Command::TogglePause,
+            (Some("step"), None) => Command::Step,
+            (Some("seek"), Some(secs)) => {
+                let value = match secs.parse::<f64>() {
+                    Ok(value) if value.is_finite() => value,
+                    _ => return Err(format!("expected a number of seconds, got '{secs}'")),
+                };
+                if secs.starts_with(['+', '-']) {
+                    Command::SeekBy(value)
+                } else {
+                    Command::Seek(value)
+                }
+            }
+            (Some("speed"), Some(factor)) => match factor.parse::<f64>() {
+                Ok(factor) if factor.is_finite() && factor > 0.0 => Command::Speed(factor),
+                _ => return Err(format!("expected a positive speed, got '{factor}'")),
+            },
+            (Some("loop"), None) => Command::Loop(None),
+            (Some("loop"), Some("on")) => Command::Loop(Some(true)),
+            (Some("loop"), Some("off")) => Command::Loop(Some(false)),
+            (Some("quit"), None) => Command::Quit,
+            _ => return Err("unknown command".into()),
+        };
+        match words.next() {
+            None => Ok(command),
+            Some(_) => Err("too many arguments".into()),
+        }
+    }
+}
+
+/// The playback command of a key pressed in the terminal.
+fn key_command(key: KeyEvent) -> Option<Command> {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Command::Quit),
+        KeyCode::Esc | KeyCode::Char('q') => Some(Command::Quit),
+        KeyCode::Char(' ') => Some(Command::TogglePause),
+        KeyCode::Char('s') | KeyCode::Char('.') => Some(Command::Step),
+        KeyCode::Right => Some(Command::SeekBy(SEEK_STEP)),
+        KeyCode::Left => Some(Command::SeekBy(-SEEK_STEP)),
+        KeyCode::Home | KeyCode::Char('0') => Some(Command::Seek(0.0)),
+        KeyCode::Char('+') => Some(Command::SpeedBy(2.0)),
+        KeyCode::Char('-') => Some(Command::SpeedBy(0.5)),
+        KeyCode::Char('1') => Some(Command::Speed(1.0)),
+        KeyCode::Char('l') => Some(Command::Loop(None)),
+        _ => None,
+    }
+}
+
+/// A recorded publication: its timestamp, key and payload.
+type Recorded = (Timestamp, KeyExpr<'static>, ZBytes);
+
+/// A publication to replay, at its time since the start of the recording.
+struct Publication {
+    offset: Duration,
+    timestamp: Timestamp,
+    key_expr: KeyExpr<'static>,
+    payload: ZBytes,
+}
+
+/// The playback of the replayed publications: a cursor in their buffered timeline, and a clock of
+/// the recording running at `1 / time_scale` the wall-clock speed while playing.
+struct Player {
+    // Buffered publications, in timestamp order
+    timeline: Vec<Publication>,
+    // Index of the next publication to replay
+    cursor: usize,
+    // Time range of the recording covered by the buffered publications
+    loaded_from: Duration,
+    loaded_until: Duration,
+    start: SystemTime,
+    duration: Duration,
+    // Time of the recording when the playback was last paused, sought or changed speed
+    position: Duration,
+    // Wall-clock time since which the playback is running, if playing
+    playing_since: Option<Instant>,
+    time_scale: f64,
+    looping: bool,
+}
+
+impl Player {
+    /// A player of the recording made from `start` for `duration`, with no publication loaded yet.
+    fn new(start: SystemTime, duration: Duration, time_scale: f64, looping: bool) -> Self {
+        Player {
+            timeline: Vec::new(),
+            cursor: 0,
+            loaded_from: Duration::ZERO,
+            loaded_until: Duration::ZERO,
+            start,
+            duration,
+            position: Duration::ZERO,
+            playing_since: Some(Instant::now()),
+            time_scale,
+            looping,
+        }
+    }
+
+    /// Appends the `publications` made between `from` and `to` since the start of the recording,
+    /// unless the playback was sought away from `from` meanwhile.
+    fn load(&mut self, from: Duration, to: Duration, publications: Vec<Recorded>) {
+        if from != self.loaded_until {
+            return;
+        }
+        // The clock stalled at the end of the buffered publications: restart it from there
+        self.rebase();
+        let start = self.start;
+        self.timeline.extend(
+            publications
+                .into_iter()
+                .map(|(timestamp, key_expr, payload)| Publication {
+                    offset: timestamp
+                        .get_time()
+                        .to_system_time()
+                        .duration_since(start)
+                        .unwrap_or_default(),
+                    timestamp,
+                    key_expr,
+                    payload,
+                }),
+        );
+        self.loaded_until = to.min(self.duration);
+    }
+
+    /// Drops the publications already replayed, to bound the memory of a streamed replay.
+    fn drop_played(&mut self) {
+        if self.cursor > 0 {
+            self.loaded_from = self.timeline[self.cursor - 1].offset + Duration::from_nanos(1);
+            self.timeline.drain(..self.cursor);
+            self.cursor = 0;
+        }
+    }
+
+    /// The start of the next window of publications to fetch, if the buffered ones end less than
+    /// `ahead` after the current time of the recording.
+    fn next_window(&self, ahead: Duration) -> Option<Duration> {
+        if self.loaded_until < self.duration && self.loaded_until < self.position() + ahead {
+            Some(self.loaded_until)
+        } else {
+            None
+        }
+    }
+
+    /// The current time of the recording, since its start. The clock doesn't run past the
+    /// buffered publications.
+    fn position(&self) -> Duration {
+        match self.playing_since {
+            Some(since) => {
+                (self.position + since.elapsed().div_f64(self.time_scale)).min(self.loaded_until)
+            }
+            None => self.position,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        self.playing_since.is_some()
+    }
+
+    fn at_end(&self) -> bool {
+        self.cursor == self.timeline.len() && self.loaded_until >= self.duration
+    }
+
+    /// The wall-clock time until the next publication is to be replayed, if playing and buffered.
+    fn next_delay(&self) -> Option<Duration> {
+        self.playing_since?;
+        let publication = self.timeline.get(self.cursor)?;
+        Some(
+            publication
+                .offset
+                .saturating_sub(self.position())
+                .mul_f64(self.time_scale),
+        )
+    }
+
+    /// Returns the next publication, moving the cursor past it.
+    fn next(&mut self) -> &Publication {
+        self.cursor += 1;
+        &self.timeline[self.cursor - 1]
+    }
+
+    /// Restarts the clock from the current time of the recording.
+    fn rebase(&mut self) {
+        self.position = self.position();
+        if self.playing_since.is_some() {
+            self.playing_since = Some(Instant::now());
+        }
+    }
+
+    fn play(&mut self) {
+        if self.playing_since.is_none() {
+            self.playing_since = Some(Instant::now());
+        }
+    }
+
+    fn pause(&mut self) {
+        self.position = self.position();
+        self.playing_since = None;
+    }
+
+    /// Moves the cursor to the first publication at or after `to` (bounded by the recording duration).
+    fn seek(&mut self, to: Duration) {
+        let to = to.min(self.duration);
+        if (self.loaded_from..=self.loaded_until).contains(&to) {
+            self.cursor = self.timeline.partition_point(|p| p.offset < to);
+        } else {
+            // Out of the buffered publications: they are to be fetched again from there
+            self.timeline.clear();
+            self.cursor = 0;
+            self.loaded_from = to;
+            self.loaded_until = to;
+        }
+        self.position = to;
+        if self.playing_since.is_some() {
+            self.playing_since = Some(Instant::now());
+        }
+    }
+
+    /// Seeks to `secs` since the start of the recording, ignoring the times that are out of range.
+    fn seek_secs(&mut self, secs: f64) {
+        // NaN is not clamped, for it to be rejected
+        let to = if secs < 0.0 { 0.0 } else { secs };
+        match Duration::try_from_secs_f64(to) {
+            Ok(to) => self.seek(to),
+            Err(e) => log::warn!("Cannot seek to {}s: {}", secs, e),
+        }
+    }
+
+    /// Sets the playback speed, clamped between `MIN_SPEED` and `MAX_SPEED`.
+    fn set_speed(&mut self, speed: f64) {
+        if speed > 0.0 {
+            self.rebase();
+            self.time_scale = 1.0 / speed.clamp(MIN_SPEED, MAX_SPEED);
+        }
+    }
+
+    /// Applies `command`, returning the publication to replay for a step.
+    fn apply(&mut self, command: Command) -> Option<&Publication> {
+        match command {
+            Command::Play => self.play(),
+            Command::Pause => self.pause(),
+            Command::TogglePause if self.is_playing() => self.pause(),
+            Command::TogglePause => self.play(),
+            Command::Step => {
+                self.pause();
+                if self.cursor < self.timeline.len() {
+                    self.position = self.timeline[self.cursor].offset;
+                    return Some(self.next());
+                }
+            }
+            Command::Seek(secs) => self.seek_secs(secs),
+            Command::SeekBy(secs) => self.seek_secs(self.position().as_secs_f64() + secs),
+            Command::Speed(speed) => self.set_speed(speed),
+            Command::SpeedBy(factor) => self.set_speed(factor / self.time_scale),
+            Command::Loop(looping) => self.looping = looping.unwrap_or(!self.looping),
+            Command::Quit => (),
+        }
+        None
+    }
+
+    /// The playback status, as JSON.
+    fn status(&self) -> serde_json::Value {
+        json!({
+            "position": self.position().as_secs_f64(),
+            "duration": self.duration.as_secs_f64(),
+            "start": humantime::format_rfc3339_nanos(self.start).to_string(),
+            "buffered": self.timeline.len() - self.cursor,
+            "playing": self.is_playing(),
+            "speed": 1.0 / self.time_scale,
+            "loop": self.looping,
+        })
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:.3}s / {:.3}s] {} at x{}{}",
+            self.position().as_secs_f64(),
+            self.duration.as_secs_f64(),
+            if self.is_playing() {
+                "playing"
+            } else {
+                "paused"
+            },
+            1.0 / self.time_scale,
+            if self.looping { " (loop)" } else { "" },
+        )
+    }
+}
+
+/// The storage of the recording, paged through in time windows.
+struct Storage {
+    session: Session,
+    topics: Vec<String>,
+    // The filter parameters other than the time range
+    parameters: String,
+    start: SystemTime,
+    duration: Duration,
+    window: Duration,
+}
+
+/// A window of publications being fetched from the storage.
+struct Fetch {
+    from: Duration,
+    to: Duration,
+    handle: JoinHandle<Vec<Recorded>>,
+}
+
+impl Storage {
+    fn new(session: &Session, args: &Args, window: f64) -> Result<Self, String> {
+        let window = Duration::try_from_secs_f64(window)
+            .ok()
+            .filter(|window| !window.is_zero())
+            .ok_or_else(|| format!("`--window` argument: must be positive, got {window}"))?;
+        let (start, end, parameters) =
+            split_time_range(&args.filter).map_err(|e| format!("`--filter` argument: {e}"))?;
+        Ok(Storage {
+            session: session.clone(),
+            topics: args.input_topic.clone(),
+            parameters,
+            start,
+            duration: end.duration_since(start).unwrap_or_default(),
+            window,
+        })
+    }
+
+    /// Starts querying the publications of the window starting at `from` since the start of the recording.
+    fn fetch(&self, from: Duration) -> Fetch {
+        let to = (from + self.window).min(self.duration);
+        // The end of the last window is included
+        let mut filter = format!(
+            "_time=[{}..{}{}",
+            humantime::format_rfc3339_nanos(self.start + from),
+            humantime::format_rfc3339_nanos(self.start + to),
+            if to == self.duration { ']' } else { '[' },
+        );
+        if !self.parameters.is_empty() {
+            filter.push(';');
+            filter.push_str(&self.parameters);
+        }
+        let session = self.session.clone();
+        let topics = self.topics.clone();
+        let handle =
+            task::spawn(async move { query_publications(&session, &topics, &filter).await });
+        Fetch { from, to, handle }
+    }
+}
+
+/// Waits for the pending `fetch` to complete, or forever if there is none.
+async fn fetched(fetch: &mut Option<Fetch>) -> (Duration, Duration, Vec<Recorded>) {
+    match fetch {
+        Some(pending) => {
+            let publications = (&mut pending.handle).await;
+            let Fetch { from, to, .. } = fetch.take().unwrap();
+            (from, to, publications)
+        }
+        None => future::pending().await,
+    }
+}
+
+/// Splits the `_time=[FROM..TO]` range out of a query `filter`, returning its bounds (TO being now
+/// if open) and the other parameters.
+fn split_time_range(filter: &str) -> Result<(SystemTime, SystemTime, String), String> {
+    let now = SystemTime::now();
+    let mut range = None;
+    let mut parameters = Vec::new();
+    for parameter in filter.split(';').filter(|p| !p.is_empty()) {
+        match parameter.strip_prefix("_time=") {
+            Some(time_range) => range = Some(time_range),
+            None => parameters.push(parameter),
+        }
+    }
+    let range = range.ok_or("a `_time=[FROM..TO]` range is required to stream the replay")?;
+    let (from, to) = range
+        .trim_start_matches(['[', ']'])
+        .trim_end_matches(['[', ']'])
+        .split_once("..")
+        .ok_or_else(|| format!("invalid time range '{range}'"))?;
+    if from.is_empty() {
+        return Err(format!(
+            "the time range '{range}' must have a start to stream the replay"
+        ));
+    }
+    let from = time_bound(from, now)?;
+    let to = match to {
+        "" => now,
+        to => time_bound(to, now)?,
+    };
+    if to <= from {
+        return Err(format!("empty time range '{range}'"));
+    }
+    Ok((from, to, parameters.join(";")))
+}
+
+/// Parses a bound of a time range: `now()`, `now(-1h)` (with u, ms, s, m, h, d or w units) or a RFC3339 time.
+fn time_bound(bound: &str, now: SystemTime) -> Result<SystemTime, String> {
+    let Some(offset) = bound
+        .strip_prefix("now(")
+        .and_then(|offset| offset.strip_suffix(')'))
+    else {
+        return humantime::parse_rfc3339_weak(bound)
+            .map_err(|e| format!("invalid time '{bound}': {e}"));
+    };
+    if offset.is_empty() {
+        return Ok(now);
+    }
+    let (negative, offset) = match offset.strip_prefix('-') {
+        Some(offset) => (true, offset),
+        None => (false, offset.trim_start_matches('+')),
+    };
+    let (value, unit) = offset.split_at(
+        offset
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(offset.len()),
+    );
+    let unit = match unit {
+        "u" => 1e-6,
+        "ms" => 1e-3,
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 604800.0,
+        _ => return Err(format!("invalid duration unit in '{bound}'")),
+    };
+    let offset = value
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * unit).ok())
+        .ok_or_else(|| format!("invalid duration in '{bound}'"))?;
+    if negative {
+        now.checked_sub(offset)
+    } else {
+        now.checked_add(offset)
+    }
+    .ok_or_else(|| format!("time out of range in '{bound}'"))
+}
+
+/// Queries the stored publications of each of the `input_topics` (that may contain wildcards) with `filter`,
+/// and returns them all with their timestamp, sorted, without the duplicates of overlapping topics.
+/// Publications without timestamp can't be placed in the timeline and are skipped.
+async fn query_publications(
+    session: &Session,
+    input_topics: &[String],
+    filter: &str,
+) -> Vec<Recorded> {
+    let mut replies = Vec::new();
+    let mut untimed = 0;
+    for input_topic in input_topics {
+        let mut query_selector = input_topic.clone();
+        if !filter.is_empty() {
+            query_selector.push('?');
+            query_selector.push_str(filter);
+        }
+
+        print!("Sending Query '{}'...\r\n", query_selector);
+        let topic_replies = session
+            .get(&query_selector)
+            .consolidation(ConsolidationMode::None)
+            .await
+            .unwrap();
+        while let Ok(reply) = topic_replies.recv_async().await {
+            match reply.into_result() {
+                Ok(sample) => match sample.timestamp() {
+                    Some(ts) => {
+                        replies.push((*ts, sample.key_expr().clone(), sample.payload().clone()))
+                    }
+                    None => untimed += 1,
+                },
+                Err(e) => log::warn!("Error reply to '{}': {:?}", query_selector, e),
+            }
+        }
+    }
+    if untimed > 0 {
+        print!("{} publications without timestamp - skipped\r\n", untimed);
+    }
+
+    // Sort publications by timestamps, and keys for the duplicates of several storages to be adjacent
+    replies.sort_by(|(ts_a, key_a, _), (ts_b, key_b, _)| {
+        (ts_a, key_a.as_str()).cmp(&(ts_b, key_b.as_str()))
+    });
+    replies.dedup_by(|(ts_a, key_a, _), (ts_b, key_b, _)| ts_a == ts_b && key_a == key_b);
+    replies
+}
+
+/// Reads the publications of the `input_topics` (that may contain wildcards) from the MCAP file at `path`,
+/// with their ROS2 topics converted back to keys of the zenoh/DDS bridge, and returns them sorted by timestamp.
+fn read_mcap(
+    session: &Session,
+    path: &str,
+    input_topics: &[String],
+) -> Result<Vec<Recorded>, String> {
+    let input_topics = input_topics
+        .iter()
+        .map(|topic| KeyExpr::try_from(topic.as_str()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mapped = unsafe { Mmap::map(&file) }.map_err(|e| e.to_string())?;
+    // The timestamps of the recorded publications are made with the id of this session
+    let id = *session.new_timestamp().get_id();
+
+    let mut publications = Vec::new();
+    for message in mcap::MessageStream::new(&mapped).map_err(|e| e.to_string())? {
+        let message = message.map_err(|e| e.to_string())?;
+        // The key recorded by ros2-record, or the one of the topic on the zenoh/DDS bridge
+        let key = match message.channel.metadata.get(ZENOH_KEY_METADATA) {
+            Some(key) => key.clone(),
+            None => zenoh_key(&message.channel.topic),
+        };
+        let Ok(key_expr) = KeyExpr::try_from(key) else {
+            print!(
+                "Invalid key for topic '{}' - skipped\r\n",
+                message.channel.topic
+            );
+            continue;
+        };
+        if input_topics.iter().any(|topic| topic.intersects(&key_expr)) {
+            let time = NTP64::from(Duration::from_nanos(message.log_time));
+            let payload = ZBytes::from(message.data.into_owned());
+            publications.push((Timestamp::new(time, id), key_expr, payload));
+        }
+    }
+
+    // Sort publications by timestamps
+    publications.sort_by_key(|(ts, _, _)| *ts);
+    Ok(publications)
+}
+
+/// The key of a ROS2 topic on the zenoh/DDS bridge, e.g. "rt/turtle1/cmd_vel" for "/turtle1/cmd_vel".
+fn zenoh_key(topic: &str) -> String {
+    format!("rt/{}", topic.trim_start_matches('/'))
+}
+
+/// The topic to replay a publication of `input_topic` on: `--output-topic` if given, or the first
+/// matching remapping rule applied to `input_topic`.
+fn output_topic(args: &Args, input_topic: &KeyExpr) -> Option<String> {
+    if let Some(output_topic) = &args.output_topic {
+        return Some(output_topic.clone());
+    }
+    args.remap
+        .iter()
+        .find_map(|remap| remap.apply(input_topic.as_str()))
+}
+
+/// A remapping rule of the replayed topics, replacing the `from` prefix with `to`.
+#[derive(Clone, Debug)]
+struct Remap {
+    from: String,
+    to: String,
+}
+
+impl Remap {
+    fn apply(&self, topic: &str) -> Option<String> {
+        topic
+            .strip_prefix(&self.from)
+            .map(|rest| format!("{}{}", self.to, rest))
+    }
+}
+
+impl FromStr for Remap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !to.is_empty() => Ok(Remap {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            _ => Err(format!("expected FROM=TO prefixes, got '{s}'")),
         }
     }
 }
@@ -130,7 +965,7 @@ fn transform_twist(payload: &ZBytes, linear_scale: f64, angular_scale: f64) -> V
     twist.angular.x *= angular_scale;
     twist.angular.y *= angular_scale;
     twist.angular.z *= angular_scale;
-    println!("   '{:?}'", twist);
+    print!("   '{:?}'\r\n", twist);
     cdr::serialize::<_, _, CdrLe>(&twist, Infinite).unwrap()
 }
 
@@ -166,24 +1001,51 @@ pub struct Args {
     #[arg(short, long, default_value = "_time=[now(-10m)..]")]
     // The 'filter' for querying. E.g. "_time=[now(-1h)..]"
     filter: String,
+    #[arg(long)]
+    // A MCAP file (e.g. recorded with ros2-record, or a rosbag2) to replay instead of querying a storage.
+    mcap: Option<String>,
     #[arg(short, long, default_value = "rt/turtle1/cmd_vel")]
-    // The recorded topic to query from storage.
-    input_topic: String,
-    #[arg(short, long, default_value = "replay/rt/turtle1/cmd_vel")]
-    // The topic to replay on.
-    output_topic: String,
+    // The recorded topics to query from storage, possibly with wildcards (e.g. "rt/**"). Can be repeated.
+    input_topic: Vec<String>,
+    #[arg(short, long)]
+    // The topic to replay all the publications on, instead of remapping their topics.
+    output_topic: Option<String>,
+    #[arg(short, long, default_value = "rt/=replay/rt/")]
+    // A rule remapping the topics of the replayed publications, as FROM=TO prefixes. Can be repeated, the first matching rule applies.
+    remap: Vec<Remap>,
     #[arg(short, long, default_value = "1.0")]
-    // The time scale (i.e. multiplier of time interval between each re-publication).
+    // The time scale (i.e. multiplier of time interval between each re-publication), between 1/64 and 64.
     time_scale: f64,
     #[arg(short = 'w')]
-    // The time scale (i.e. multiplier of time interval between each re-publication).
+    // Replayed publications are Twists to scale with the linear and angular scales.
     twist: bool,
+    #[arg(long, default_value = "**/cmd_vel")]
+    // The topics of the Twists to scale.
+    twist_topics: String,
     #[arg(short = 'a', long, default_value = "1.0")]
-    // The time scale (i.e. multiplier of time interval between each re-publication).
+    // The multiplier applied to the angular component of the replayed Twists.
     angular_scale: f64,
     #[arg(short = 'x', long, default_value = "1.0")]
-    // The time scale (i.e. multiplier of time interval between each re-publication).
+    // The multiplier applied to the linear component of the replayed Twists.
     linear_scale: f64,
+    #[arg(long)]
+    // Control the playback from the terminal keyboard.
+    interactive: bool,
+    #[arg(long, default_value = "replay/control")]
+    // The key receiving playback commands ("play", "pause", "step", "seek SECS", "speed FACTOR", "loop [on|off]", "quit"). The status is queryable on <KEY>/status.
+    control_key: String,
+    #[arg(long = "loop")]
+    // Restart the replay at the end of the recording.
+    loop_replay: bool,
+    #[arg(long)]
+    // Start paused, waiting for a playback command.
+    paused: bool,
+    #[arg(long)]
+    // Stream the replay by querying the storage in time windows of this duration (in seconds), instead of all at once. The 'filter' must have a '_time' range with a start.
+    window: Option<f64>,
+    #[arg(long, default_value = "2")]
+    // The number of windows to buffer ahead of the playback when streaming.
+    prefetch: u32,
 }
 
 impl From<&Args> for Config {
@@ -238,3 +1100,200 @@ impl From<&Args> for Config {
         config
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn remap_parses_prefixes() {
+        let remap = "rt/=replay/rt/".parse::<Remap>().unwrap();
+        assert_eq!(remap.from, "rt/");
+        assert_eq!(remap.to, "replay/rt/");
+        // Only the first '=' separates the prefixes
+        let remap = "a=b=c".parse::<Remap>().unwrap();
+        assert_eq!((remap.from.as_str(), remap.to.as_str()), ("a", "b=c"));
+    }
+
+    #[test]
+    fn remap_rejects_invalid_rules() {
+        assert!("rt/".parse::<Remap>().is_err());
+        assert!("rt/=".parse::<Remap>().is_err());
+        assert!("".parse::<Remap>().is_err());
+    }
+
+    #[test]
+    fn remap_applies_to_matching_prefix_only() {
+        let remap = "rt/=replay/rt/".parse::<Remap>().unwrap();
+        assert_eq!(
+            remap.apply("rt/turtle1/cmd_vel").as_deref(),
+            Some("replay/rt/turtle1/cmd_vel")
+        );
+        assert_eq!(remap.apply("demo/rt/x"), None);
+    }
+
+    #[test]
+    fn command_parses_playback_commands() {
+        assert_eq!("play".parse(), Ok(Command::Play));
+        assert_eq!("resume".parse(), Ok(Command::Play));
+        assert_eq!(" pause ".parse(), Ok(Command::Pause));
+        assert_eq!("toggle".parse(), Ok(Command::TogglePause));
+        assert_eq!("step".parse(), Ok(Command::Step));
+        assert_eq!("seek 42.5".parse(), Ok(Command::Seek(42.5)));
+        assert_eq!("seek -5".parse(), Ok(Command::SeekBy(-5.0)));
+        assert_eq!("seek +5".parse(), Ok(Command::SeekBy(5.0)));
+        assert_eq!("speed 0.5".parse(), Ok(Command::Speed(0.5)));
+        assert_eq!("loop".parse(), Ok(Command::Loop(None)));
+        assert_eq!("loop on".parse(), Ok(Command::Loop(Some(true))));
+        assert_eq!("loop off".parse(), Ok(Command::Loop(Some(false))));
+        assert_eq!("quit".parse(), Ok(Command::Quit));
+    }
+
+    #[test]
+    fn command_rejects_invalid_commands() {
+        for command in [
+            "",
+            "rewind",
+            "play now",
+            "seek",
+            "seek abc",
+            "seek inf",
+            "seek -inf",
+            "seek NaN",
+            "speed 0",
+            "speed -1",
+            "speed inf",
+            "speed NaN",
+            "loop maybe",
+        ] {
+            assert!(
+                command.parse::<Command>().is_err(),
+                "'{}' was accepted",
+                command
+            );
+        }
+    }
+
+    fn player() -> Player {
+        let mut player = Player::new(UNIX_EPOCH, Duration::from_secs(60), 1.0, false);
+        player.pause();
+        player
+    }
+
+    #[test]
+    fn player_ignores_out_of_range_seeks() {
+        let mut player = player();
+        player.apply(Command::Seek(10.0));
+        assert_eq!(player.position(), Duration::from_secs(10));
+        // Too far to be a Duration: ignored instead of panicking
+        player.apply(Command::Seek(1e300));
+        player.apply(Command::SeekBy(1e300));
+        player.apply(Command::Seek(f64::INFINITY));
+        player.apply(Command::Seek(f64::NAN));
+        assert_eq!(player.position(), Duration::from_secs(10));
+        // Before the start or after the end of the recording: clamped
+        player.apply(Command::SeekBy(-20.0));
+        assert_eq!(player.position(), Duration::ZERO);
+        player.apply(Command::Seek(100.0));
+        assert_eq!(player.position(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn player_clamps_speed() {
+        let mut player = player();
+        player.apply(Command::Speed(2.0));
+        assert_eq!(player.time_scale, 0.5);
+        player.apply(Command::Speed(1e300));
+        assert_eq!(player.time_scale, 1.0 / MAX_SPEED);
+        player.apply(Command::Speed(1e-300));
+        assert_eq!(player.time_scale, 1.0 / MIN_SPEED);
+        for _ in 0..100 {
+            player.apply(Command::SpeedBy(2.0));
+        }
+        assert_eq!(player.time_scale, 1.0 / MAX_SPEED);
+        // Invalid speeds are ignored
+        player.apply(Command::Speed(0.0));
+        player.apply(Command::Speed(-1.0));
+        player.apply(Command::Speed(f64::NAN));
+        assert_eq!(player.time_scale, 1.0 / MAX_SPEED);
+    }
+
+    #[test]
+    fn time_bound_parses_relative_and_absolute_times() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(time_bound("now()", now), Ok(now));
+        assert_eq!(
+            time_bound("now(-1h)", now),
+            Ok(now - Duration::from_secs(3600))
+        );
+        assert_eq!(
+            time_bound("now(+30s)", now),
+            Ok(now + Duration::from_secs(30))
+        );
+        assert_eq!(
+            time_bound("now(1.5ms)", now),
+            Ok(now + Duration::from_micros(1500))
+        );
+        assert_eq!(
+            time_bound("1970-01-02T00:00:00Z", now),
+            Ok(UNIX_EPOCH + Duration::from_secs(86400))
+        );
+    }
+
+    #[test]
+    fn time_bound_rejects_invalid_times() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for bound in [
+            "now(-1y)",
+            "now(abc)",
+            "now(-1)",
+            "now(1e300s)",
+            "now(infs)",
+            "now(NaNs)",
+            "yesterday",
+        ] {
+            assert!(time_bound(bound, now).is_err(), "'{}' was accepted", bound);
+        }
+    }
+
+    #[test]
+    fn time_bound_rejects_overflowing_offsets() {
+        // Valid durations, out of the range of SystemTime once added to now
+        assert!(time_bound("now(-100000000000000w)", SystemTime::now()).is_err());
+        assert!(time_bound("now(100000000000000w)", SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn split_time_range_separates_parameters() {
+        let (from, to, parameters) =
+            split_time_range("_time=[2024-01-01T00:00:00Z..2024-01-01T00:01:00Z];foo=bar").unwrap();
+        assert_eq!(to.duration_since(from).unwrap(), Duration::from_secs(60));
+        assert_eq!(parameters, "foo=bar");
+
+        // An open end is now
+        let (from, to, parameters) = split_time_range("a=1;_time=[now(-5m)..];b=2").unwrap();
+        let length = to.duration_since(from).unwrap();
+        assert_eq!(length, Duration::from_secs(300));
+        assert_eq!(parameters, "a=1;b=2");
+    }
+
+    #[test]
+    fn split_time_range_rejects_invalid_ranges() {
+        for filter in [
+            "",
+            "foo=bar",
+            "_time=[now(-5m)]",
+            "_time=[..now()]",
+            "_time=[now()..now(-5m)]",
+            "_time=[now()..now()]",
+            "_time=[now(-100000000000000w)..]",
+        ] {
+            assert!(
+                split_time_range(filter).is_err(),
+                "'{}' was accepted",
+                filter
+            );
+        }
+    }
+}
@@ -4,6 +4,7 @@ import time
 import cv2
 import zenoh
 import json
+import zlib
 
 CAMERA_ID                   = 0
 
@@ -24,6 +25,10 @@ parser.add_argument('-q', '--quality', type=int, default=95,
                     help='quality of the published frames (0 - 100)')
 parser.add_argument('-d', '--delay', type=float, default=0.05,
                     help='delay between each frame in seconds')
+parser.add_argument('-r', '--raw', type=str, choices=['BGR', 'RGB', 'GRAY'],
+                    help='publish raw frames of the given pixel format, for zcam-rust stages, instead of jpeg.')
+parser.add_argument('--checksum', action='store_true',
+                    help='attach the CRC32 of raw frames, checked by zcam-rust stages.')
 parser.add_argument('-k', '--key', type=str, default='demo/zcam',
                     help='key expression')
 parser.add_argument('-c', '--config', type=str, metavar='FILE',
@@ -64,6 +69,27 @@ else:
 
 time.sleep(1.0)
 
+RAW_CONVERSIONS = {'BGR': None, 'RGB': cv2.COLOR_BGR2RGB, 'GRAY': cv2.COLOR_BGR2GRAY}
+
+
+def raw_frame_meta(frame, payload, seq):
+    # Frame metadata understood by zcam-rust stages (see RawFrameMeta), as JSON
+    rows, cols = frame.shape[:2]
+    channels = frame.shape[2] if frame.ndim == 3 else 1
+    # The payload is frame.tobytes(), whose rows are always packed
+    stride = cols * channels * frame.itemsize
+    return json.dumps({'Raw': {
+        'rows': rows,
+        'cols': cols,
+        'format': args.raw,
+        'stride': stride,
+        'size': stride * rows,
+        'trace': {'seq': seq, 'capture_timestamp': int(time.time() * 1000000), 'hops': []},
+        'checksum': zlib.crc32(payload) if args.checksum else None,
+    }})
+
+
+seq = 0
 while True:
     if picamera:
         raw = vs.capture_array()
@@ -71,7 +97,17 @@ while True:
         raw = vs.read()
     if raw is not None:
         frame = imutils.resize(raw, width=args.width)
-        _, jpeg = cv2.imencode('.jpg', frame, jpeg_opts)
-        z.put(args.key, jpeg.tobytes())
+        if args.raw is not None:
+            if frame.ndim == 3 and frame.shape[2] == 4:
+                # picamera frames are XRGB8888, i.e. BGRA in memory
+                frame = cv2.cvtColor(frame, cv2.COLOR_BGRA2BGR)
+            if RAW_CONVERSIONS[args.raw] is not None:
+                frame = cv2.cvtColor(frame, RAW_CONVERSIONS[args.raw])
+            payload = frame.tobytes()
+            z.put(args.key, payload, attachment=raw_frame_meta(frame, payload, seq).encode())
+            seq += 1
+        else:
+            _, jpeg = cv2.imencode('.jpg', frame, jpeg_opts)
+            z.put(args.key, jpeg.tobytes())
 
     time.sleep(args.delay)
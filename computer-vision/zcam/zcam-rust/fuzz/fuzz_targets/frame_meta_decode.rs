@@ -0,0 +1,45 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zcam::FrameMeta;
+
+// The input is the length of the attachment on 2 bytes, the attachment, then the payload.
+// Whatever metadata decodes and matches its payload must be safe to map and read a frame from.
+fuzz_target!(|data: &[u8]| {
+    let Some((len, data)) = data.split_first_chunk::<2>() else {
+        return;
+    };
+    let len = (u16::from_le_bytes(*len) as usize).min(data.len());
+    let (attachment, payload) = data.split_at(len);
+
+    let Ok(meta) = FrameMeta::from_attachment(attachment) else {
+        return;
+    };
+
+    // Valid metadata must survive an encoding round trip
+    let encoded = meta.encode().unwrap().to_bytes().to_vec();
+    let decoded = FrameMeta::from_attachment(&encoded).expect("re-encoded metadata must decode");
+    assert_eq!(decoded.raw().size(), meta.raw().size());
+
+    if meta.check_payload(payload).is_err() {
+        return;
+    }
+    if let FrameMeta::Raw(raw_meta) = &meta {
+        // Read every pixel of the frame
+        let frame = unsafe { raw_meta.mat(payload.as_ptr()) };
+        raw_meta.to_bgr(&frame).unwrap();
+    }
+});
@@ -11,16 +11,24 @@
 // Contributors:
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
+use std::time::Duration;
+
 use clap::Parser;
-use opencv::{prelude::*, videoio};
-use rkyv::rancor::Error;
+use opencv::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::select;
-use zcam::{config_update_loop, FrameMeta, RawFrameMeta};
+use zcam::{
+    now_us,
+    params::{params_loop, Params, StageParams},
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    source::{FrameSource, SourceSpec},
+    FrameMeta, RawFrameMeta,
+};
 use zenoh::{
     config::Config,
+    pubsub::Publisher,
     qos::{CongestionControl, Reliability},
-    shm::*,
     Session,
 };
 
@@ -30,16 +38,21 @@ async fn main() {
     zenoh::init_log_from_env_or("error");
 
     // Parse command line arguments
-    let (config, key_pub, delay, reliability, congestion_ctrl) = parse_args();
+    let (config, key_pub, source, looping, delay, checksum, shm_args, reliability, congestion_ctrl) =
+        parse_args();
+    let params = Params::new(CaptureParams { delay }).expect("Invalid parameters");
 
     println!("Opening session...");
     let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &shm_args).await;
 
     select!(
         // Processing loop
-        _ = process_loop(&z, key_pub.clone(), delay, reliability, congestion_ctrl) => {}
+        _ = process_loop(&z, key_pub.clone(), source, looping, checksum, params.clone(), shm_pool.clone(), reliability, congestion_ctrl) => {}
         // Config update loop
-        _ = config_update_loop(&z, format!("{}/zcapture/conf/**", key_pub)) => {},
+        _ = params_loop(&z, format!("{}/zcapture/conf", key_pub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/zcapture/metrics", key_pub), shm_pool, Duration::from_secs(1)) => {},
     );
 }
 
@@ -57,9 +70,27 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    /// Frame source: a device index or path (e.g. `0`, `/dev/video2`), a video file, a directory of images,
+    /// or a synthetic test pattern (`pattern` or `pattern:<width>x<height>`).
+    /// Prefixes `device:`, `video:` and `images:` can be used to disambiguate.
+    /// Given several times, the sources are captured together and published on `<key>/<index>`.
+    #[arg(short, long, default_value = "0")]
+    source: Vec<SourceSpec>,
+
+    /// Restart video files and image directories from the beginning once exhausted
+    #[arg(long("loop"), default_value = "false")]
+    looping: bool,
+
     #[arg(short, long, default_value = "40")]
     delay: u64,
 
+    /// Attach the CRC32 of each frame, checked by the stages receiving it
+    #[arg(long, default_value = "false")]
+    checksum: bool,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
     #[arg(long, default_value = "false")]
     best_effort: bool,
 
@@ -70,7 +101,11 @@ struct Args {
 fn parse_args() -> (
     Config,
     String,
+    Vec<SourceSpec>,
+    bool,
     u64,
+    bool,
+    ShmArgs,
     zenoh::qos::Reliability,
     zenoh::qos::CongestionControl,
 ) {
@@ -87,6 +122,7 @@ fn parse_args() -> (
     if let Some(m) = args.mode {
         let _ = c.insert_json5("mode", &json!(m).to_string());
     }
+    args.shm.apply(&mut c);
 
     let congestion_control = if args.block_on_congestion {
         zenoh::qos::CongestionControl::Block
@@ -99,84 +135,177 @@ fn parse_args() -> (
         zenoh::qos::Reliability::Reliable
     };
 
-    (c, args.key, args.delay, reliability, congestion_control)
+    (
+        c,
+        args.key,
+        args.source,
+        args.looping,
+        args.delay,
+        args.checksum,
+        args.shm,
+        reliability,
+        congestion_control,
+    )
+}
+
+/// Parameters of `zcapture` that can be changed while it runs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CaptureParams {
+    /// Delay between two frames, in milliseconds
+    delay: u64,
+}
+
+impl StageParams for CaptureParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.delay > 10_000 {
+            return Err("delay must be at most 10000 ms".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A frame source and the publisher of its frames.
+struct Camera {
+    spec: SourceSpec,
+    source: FrameSource,
+    publ: Publisher<'static>,
+    raw_meta: RawFrameMeta,
 }
 
-/// Processing loop that captures frames from camera directly into Zenoh SHM and publishes them without copying.
+/// Processing loop that captures frames from the sources directly into Zenoh SHM and publishes them without copying.
+/// With several sources, a frame is grabbed from each one before any is retrieved, so that they are taken as close
+/// in time as possible, and the frames of the same round are stamped with the same sequence number and capture time.
+/// When the SHM pool is full with the drop-newest policy, the frames of the round are dropped.
+/// Returns once a source is exhausted.
+#[allow(clippy::too_many_arguments)]
 async fn process_loop(
     session: &Session,
     key_pub: String,
-    delay: u64,
+    source_specs: Vec<SourceSpec>,
+    looping: bool,
+    checksum: bool,
+    params: Params<CaptureParams>,
+    shm_pool: ShmPool,
     reliability: Reliability,
     congestion_ctrl: CongestionControl,
 ) {
-    // Declare publisher for camera frames
-    let publ = session
-        .declare_publisher(key_pub)
-        .reliability(reliability)
-        .congestion_control(congestion_ctrl)
-        .await
-        .unwrap();
-
-    // Open camera
-    let mut cam = videoio::VideoCapture::new(0, videoio::CAP_ANY).unwrap();
-    if !videoio::VideoCapture::is_opened(&cam).unwrap() {
-        panic!("Unable to open default camera!");
-    }
+    let mut cameras = Vec::new();
+    for (i, spec) in source_specs.iter().enumerate() {
+        // Frames of source `i` are published on `<key_pub>/<i>` when there are several sources
+        let key = match source_specs.len() {
+            1 => key_pub.clone(),
+            _ => format!("{key_pub}/{i}"),
+        };
 
-    // Query the layout of the frame to use for SHM buffers allocation
-    let (raw_meta, meta, encoded_meta) = {
-        let mut frame = Mat::default();
-        cam.read(&mut frame)
-            .expect("Failed to read camera frame for detecting layout!");
+        // Declare publisher for camera frames
+        let publ = session
+            .declare_publisher(key.clone())
+            .reliability(reliability)
+            .congestion_control(congestion_ctrl)
+            .await
+            .unwrap();
 
-        if frame.empty() {
-            panic!("Camera returned empty frame!");
-        }
+        // Open frame source
+        let mut source = match FrameSource::open(spec, looping) {
+            Ok(source) => source,
+            Err(e) => {
+                tracing::error!("Unable to open source {spec}: {e}");
+                return;
+            }
+        };
 
-        let raw_meta = RawFrameMeta::new(&frame).unwrap();
-        let meta = FrameMeta::Raw(raw_meta.clone());
-        let encoded_meta = rkyv::to_bytes::<Error>(&meta).unwrap().to_vec();
+        // Query the layout of the frame to use for SHM buffers allocation
+        let raw_meta = {
+            let mut frame = Mat::default();
+            let read = source
+                .read(&mut frame)
+                .expect("Failed to read frame for detecting layout!");
 
-        (raw_meta, meta, encoded_meta)
-    };
+            if !read || frame.empty() {
+                tracing::error!("Source {spec} returned no frame!");
+                return;
+            }
 
-    // Obtain SHM provider from the session to allocate SHM buffers for frames
-    let shm_provider = session
-        .get_shm_provider()
-        .await
-        .expect("Failed to get transport SHM provider");
+            RawFrameMeta::new(&frame).unwrap()
+        };
 
-    tracing::info!("Will publish frames: {meta}...");
+        tracing::info!(
+            "Will publish frames of {spec} on {key}: {}...",
+            FrameMeta::Raw(raw_meta.clone())
+        );
+        cameras.push(Camera {
+            spec: spec.clone(),
+            source,
+            publ,
+            raw_meta,
+        });
+    }
+
+    let mut seq: u64 = 0;
     loop {
-        // Allocate SHM buffer for decoded frames with layout that is taken from the frame metadata
-        let mut shm_buf = unsafe {
-            shm_provider
+        // Grab a frame from every source, all at once
+        for camera in cameras.iter_mut() {
+            let grabbed = match camera.source.grab() {
+                Ok(grabbed) => grabbed,
+                Err(e) => {
+                    tracing::error!("Failed to grab frame from {}: {e}", camera.spec);
+                    false
+                }
+            };
+            if !grabbed {
+                tracing::info!("Source {} is exhausted", camera.spec);
+                return;
+            }
+        }
+        let capture_timestamp = now_us();
+
+        for camera in cameras.iter_mut() {
+            let raw_meta = &camera.raw_meta;
+
+            // Allocate SHM buffer for decoded frames with layout that is taken from the frame metadata
+            let Some(mut shm_buf) = shm_pool
                 .alloc(raw_meta.size())
-                .with_unsafe_policy::<BlockOn<GarbageCollect>>()
                 .await
                 .expect("Failed to allocate SHM buffer")
-        };
+            else {
+                continue;
+            };
 
-        // Map opencv Mat into shared memory
-        let mut frame = unsafe { raw_meta.mat_mut(shm_buf.as_mut_ptr()) };
+            // Map opencv Mat into shared memory
+            let mut frame = unsafe { raw_meta.mat_mut(shm_buf.as_mut_ptr()) };
 
-        // Capture frame directly into SHM buffer using shm-backed Mat
-        cam.read(&mut frame).expect("Failed to read camera frame!");
+            // Retrieve frame directly into SHM buffer using shm-backed Mat
+            if let Err(e) = camera.source.retrieve(&mut frame) {
+                tracing::error!("Failed to retrieve frame from {}: {e}", camera.spec);
+                continue;
+            }
 
-        if !frame.empty() {
-            // Publish the frame with the encoded meta as attachment
-            publ.put(shm_buf)
-                .attachment(&encoded_meta)
-                .await
-                .expect("Failed to publish camera frame!");
-        } else {
-            tracing::error!("Reading empty buffer from camera... Waiting some more....");
+            if !frame.empty() {
+                // Stamp the frame metadata with its sequence number and capture time
+                let mut frame_meta = raw_meta.clone();
+                frame_meta.set_capture(seq, capture_timestamp);
+                if checksum {
+                    frame_meta.set_checksum(Some(&shm_buf));
+                }
+                let attachment = FrameMeta::Raw(frame_meta).encode().unwrap();
+
+                // Publish the frame with the encoded meta as attachment
+                camera
+                    .publ
+                    .put(shm_buf)
+                    .attachment(attachment)
+                    .await
+                    .expect("Failed to publish camera frame!");
+            } else {
+                tracing::error!("Reading empty buffer from source... Waiting some more....");
+            }
         }
+        seq += 1;
 
         // Wait before capturing next frame to maintin the desired frame rate
+        let delay = params.get().delay;
         if delay > 0 {
-            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+            tokio::time::sleep(Duration::from_millis(delay)).await;
         }
     }
 }
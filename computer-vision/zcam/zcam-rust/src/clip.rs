@@ -0,0 +1,309 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::path::PathBuf;
+
+use clap::Parser;
+use serde_json::json;
+
+use opencv::core::{MatTraitConst, ToInputArray, Vector};
+use tokio::select;
+use zcam::{
+    detection::MotionEvent,
+    now_us,
+    params::{params_loop, Params},
+    recording::{ClipFrame, ClipParams, ClipRecorder},
+    BadFrames, FrameMeta, PixelFormat, RawFrameMeta,
+};
+use zenoh::{config::Config, qos::CongestionControl, Session};
+
+const STAGE: &str = "zclip";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, congestion_ctrl) = parse_args(&args);
+
+    let sink = match (&args.output, &args.key_storage) {
+        (Some(dir), None) => ClipSink::Dir(dir.clone()),
+        (None, Some(key)) => ClipSink::Storage(key.clone()),
+        _ => unreachable!("clap ensures exactly one of --output and --key-storage is given"),
+    };
+
+    let params = Params::new(ClipParams {
+        pre_roll: args.pre_roll,
+        post_roll: args.post_roll,
+        jpeg_quality: args.jpeg_quality,
+    })
+    .expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, &args, sink, congestion_ctrl, params.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+#[command(group(clap::ArgGroup::new("sink").required(true).args(["output", "key_storage"])))]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the frames to record
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    /// Key of the motion events triggering the recording
+    #[arg(long, default_value = "demo/zcam/motion")]
+    key_events: String,
+
+    /// Directory to write the clips in, one sub-directory of Jpeg images per clip
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Key prefix to put the clips on, for a Zenoh storage, as `<key_storage>/<clip>/<seq>` samples
+    #[arg(long)]
+    key_storage: Option<String>,
+
+    /// Duration recorded before the first motion event, in milliseconds
+    #[arg(long, default_value = "2000")]
+    pre_roll: u64,
+
+    /// Duration recorded after the last motion event, in milliseconds
+    #[arg(long, default_value = "3000")]
+    post_roll: u64,
+
+    /// Quality of the Jpeg encoding of raw frames
+    #[arg(long, default_value = "90")]
+    jpeg_quality: i32,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(args: &Args) -> (Config, zenoh::qos::CongestionControl) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+
+    (c, congestion_control)
+}
+
+/// A Jpeg encoded frame, waiting to be written to a clip.
+struct JpegFrame {
+    meta: RawFrameMeta,
+    jpeg: Vec<u8>,
+}
+
+impl JpegFrame {
+    /// Time of the frame in microseconds, taken from its capture timestamp when it has one.
+    fn timestamp(&self) -> u64 {
+        match self.meta.trace().capture_timestamp {
+            0 => now_us(),
+            timestamp => timestamp,
+        }
+    }
+}
+
+/// Encodes the raw `frame` as Jpeg into `buf`, converting it to packed BGR if needed, and returns the metadata
+/// of the Jpeg frame.
+fn encode_jpeg(
+    frame: &(impl MatTraitConst + ToInputArray),
+    raw_meta: &RawFrameMeta,
+    buf: &mut Vector<u8>,
+    jpeg_options: &Vector<i32>,
+) -> zenoh::Result<RawFrameMeta> {
+    if raw_meta.format() == PixelFormat::Bgr && raw_meta.is_packed() {
+        opencv::imgcodecs::imencode(".jpeg", frame, buf, jpeg_options)?;
+        // The checksum was the one of the raw frame
+        let mut jpeg_meta = raw_meta.clone();
+        jpeg_meta.set_checksum(None);
+        Ok(jpeg_meta)
+    } else {
+        let bgr = raw_meta.to_bgr(frame)?;
+        opencv::imgcodecs::imencode(".jpeg", &bgr, buf, jpeg_options)?;
+        raw_meta.to_bgr_meta(&bgr)
+    }
+}
+
+/// Where the clips are written.
+enum ClipSink {
+    Dir(PathBuf),
+    Storage(String),
+}
+
+impl ClipSink {
+    async fn write(
+        &self,
+        session: &Session,
+        congestion_ctrl: CongestionControl,
+        clip: &str,
+        frame: &JpegFrame,
+    ) -> zenoh::Result<()> {
+        let seq = frame.meta.trace().seq;
+        match self {
+            ClipSink::Dir(dir) => {
+                // Clip directories can be played back with `zcapture --source images:<dir>`
+                let dir = dir.join(clip);
+                std::fs::create_dir_all(&dir)?;
+                std::fs::write(dir.join(format!("{seq:010}.jpg")), &frame.jpeg)?;
+            }
+            ClipSink::Storage(key) => {
+                let attachment = FrameMeta::Jpeg(frame.meta.clone()).encode()?;
+                session
+                    .put(format!("{key}/{clip}/{seq:010}"), frame.jpeg.clone())
+                    .attachment(attachment)
+                    .congestion_control(congestion_ctrl)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Processing loop that keeps the last `pre_roll` of frames in memory and, on a motion event, writes them
+/// to a new clip followed by the frames received until `post_roll` after the last motion event.
+async fn process_loop(
+    session: &Session,
+    args: &Args,
+    sink: ClipSink,
+    congestion_ctrl: CongestionControl,
+    params: Params<ClipParams>,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&args.key_sub).await.unwrap();
+
+    // Declare subscriber for motion events
+    let events_sub = session.declare_subscriber(&args.key_events).await.unwrap();
+
+    let mut recorder = ClipRecorder::<JpegFrame>::default();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        select!(
+            // Receive motion event
+            sample = events_sub.recv_async() => {
+                let sample = sample.unwrap();
+                let event = match MotionEvent::decode(sample.payload()) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::error!("Unable to decode motion event: {e}");
+                        continue;
+                    }
+                };
+                let timestamp = match event.trace.capture_timestamp {
+                    0 => now_us(),
+                    timestamp => timestamp,
+                };
+                let post_roll = params.get().post_roll * 1000;
+
+                // Start a new clip with the pre-roll, or extend the clip being recorded
+                if let Some((clip, pre_roll)) = recorder.on_event(timestamp, post_roll) {
+                    println!("Recording {clip}...");
+                    for frame in pre_roll {
+                        if let Err(e) = sink.write(session, congestion_ctrl, &clip, &frame).await {
+                            tracing::error!("Failed to write frame to {clip}: {e}");
+                        }
+                    }
+                }
+            }
+
+            // Receive sample with frame
+            sample = sub.recv_async() => {
+                let sample = sample.unwrap();
+
+                // Decode frame metadata
+                let meta = match FrameMeta::decode(&sample) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        bad_frames.skip(e);
+                        continue;
+                    }
+                };
+
+                // Encode raw frames as Jpeg, keep Jpeg frames as they are
+                let clip_params = params.get();
+                let frame = match meta {
+                    FrameMeta::Raw(raw_meta) => {
+                        let mut jpeg_options = opencv::core::Vector::<i32>::new();
+                        jpeg_options.push(opencv::imgcodecs::IMWRITE_JPEG_QUALITY);
+                        jpeg_options.push(clip_params.jpeg_quality);
+                        let mut buf = opencv::core::Vector::<u8>::new();
+                        let encoded = {
+                            let contiguous_bytes = sample.payload().to_bytes();
+                            let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+                            encode_jpeg(&frame, &raw_meta, &mut buf, &jpeg_options)
+                        };
+                        match encoded {
+                            Ok(jpeg_meta) => JpegFrame { meta: jpeg_meta, jpeg: buf.to_vec() },
+                            Err(e) => {
+                                tracing::error!("Failed to encode frame: {e}");
+                                continue;
+                            }
+                        }
+                    }
+                    FrameMeta::Jpeg(raw_meta) => JpegFrame {
+                        meta: raw_meta,
+                        jpeg: sample.payload().to_bytes().to_vec(),
+                    },
+                    other_meta => {
+                        tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                        continue;
+                    }
+                };
+
+                // Write the frame to the clip being recorded, or keep it for the pre-roll of the next clip
+                match recorder.on_frame(frame.timestamp(), frame, clip_params.pre_roll * 1000) {
+                    ClipFrame::Record { clip, frame } => {
+                        if let Err(e) = sink.write(session, congestion_ctrl, &clip, &frame).await {
+                            tracing::error!("Failed to write frame to {clip}: {e}");
+                        }
+                    }
+                    ClipFrame::Buffered { ended: Some(clip) } => println!("Recorded {clip}"),
+                    ClipFrame::Buffered { ended: None } => {}
+                }
+            }
+        );
+    }
+}
@@ -12,34 +12,62 @@
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
 use clap::Parser;
+use opencv::{imgproc, prelude::*};
+use openh264::{decoder::Decoder, formats::YUVSource};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::ops::Deref;
+use std::{
+    ops::Deref,
+    time::{Duration, Instant},
+};
 
 use tokio::select;
-use zcam::{config_update_loop, FrameMeta};
+use zcam::{
+    keyframe_request_keyexpr,
+    params::{params_loop, Params, StageParams},
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta,
+};
 use zenoh::{
     config::Config,
     qos::{CongestionControl, Reliability},
-    shm::*,
     Session, Wait,
 };
 
+const STAGE: &str = "zdecode";
+
 #[tokio::main]
 async fn main() {
     // Initiate logging
     zenoh::init_log_from_env_or("error");
 
     // Parse command line arguments
-    let (config, key_sub, key_pub, reliability, congestion_ctrl) = parse_args();
+    let (
+        config,
+        key_sub,
+        key_pub,
+        shm_args,
+        reliability,
+        congestion_ctrl,
+        keyframe_request_interval,
+    ) = parse_args();
+
+    let params = Params::new(DecodeParams {
+        keyframe_request_interval,
+    })
+    .expect("Invalid parameters");
 
     println!("Opening session...");
     let z = zenoh::open(config).wait().unwrap();
+    let shm_pool = ShmPool::new(&z, &shm_args).await;
 
     select!(
         // Processing loop
-        _ = process_loop(&z,key_sub.clone(), key_pub, reliability, congestion_ctrl) => {}
+        _ = process_loop(&z,key_sub.clone(), key_pub, shm_pool.clone(), reliability, congestion_ctrl, params.clone()) => {}
         // Config update loop
-        _ = config_update_loop(&z, format!("{}/zdecode/conf/**", key_sub)) => {},
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", key_sub), shm_pool, Duration::from_secs(1)) => {},
     );
 }
 
@@ -60,19 +88,28 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    #[command(flatten)]
+    shm: ShmArgs,
+
     #[arg(long, default_value = "false")]
     best_effort: bool,
 
     #[arg(long, default_value = "false")]
     block_on_congestion: bool,
+
+    /// Minimum interval between two keyframe requests to the H.264 encoder, in milliseconds
+    #[arg(long, default_value = "1000")]
+    keyframe_request_interval: u64,
 }
 
 fn parse_args() -> (
     Config,
     String,
     String,
+    ShmArgs,
     zenoh::qos::Reliability,
     zenoh::qos::CongestionControl,
+    u64,
 ) {
     let args = Args::parse();
     let mut c = if let Some(f) = args.config {
@@ -87,6 +124,7 @@ fn parse_args() -> (
     if let Some(m) = args.mode {
         let _ = c.insert_json5("mode", &json!(m).to_string());
     }
+    args.shm.apply(&mut c);
 
     let congestion_control = if args.block_on_congestion {
         zenoh::qos::CongestionControl::Block
@@ -103,19 +141,33 @@ fn parse_args() -> (
         c,
         args.key_sub,
         args.key_pub,
+        args.shm,
         reliability,
         congestion_control,
+        args.keyframe_request_interval,
     )
 }
 
+/// Runtime parameters of `zdecode`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct DecodeParams {
+    /// Minimum interval between two keyframe requests to the H.264 encoder, in milliseconds
+    keyframe_request_interval: u64,
+}
+
+impl StageParams for DecodeParams {}
+
 /// Processing loop that subscribes to encoded frames, decodes them and republishes decoded frames.
-/// Already decoded frames are republished as they are.
+/// Already decoded frames are republished as they are. Decoded frames are dropped when the SHM pool is full
+/// with the drop-newest policy.
 async fn process_loop(
     session: &Session,
     key_sub: String,
     key_pub: String,
+    shm_pool: ShmPool,
     reliability: Reliability,
     congestion_ctrl: CongestionControl,
+    params: Params<DecodeParams>,
 ) {
     // Declare subscriber for frames
     let sub = session.declare_subscriber(&key_sub).await.unwrap();
@@ -128,19 +180,26 @@ async fn process_loop(
         .await
         .unwrap();
 
-    // Obtain SHM provider from the session to allocate SHM buffers for decoded frames
-    let shm_provider = session
-        .get_shm_provider()
-        .await
-        .expect("Failed to get transport SHM provider");
+    // H.264 decoding state: the decoder is created with the first H.264 frame and can only
+    // produce frames once it received a keyframe.
+    let mut h264_decoder: Option<Decoder> = None;
+    let mut h264_synced = false;
+    let mut last_keyframe_request: Option<Instant> = None;
+
+    let mut bad_frames = BadFrames::default();
 
     loop {
         // Receive sample with frame
         let sample = sub.recv_async().await.unwrap();
 
         // Decode frame metadata
-        let meta = FrameMeta::decode(&sample)
-            .expect("Unable to decode frame metadata: probably wrong data format");
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
 
         match meta {
             FrameMeta::Raw(_) => {
@@ -162,12 +221,12 @@ async fn process_loop(
                 // Encoded as Jpeg - decode it directly to Zenoh SHM and publish
 
                 // Allocate SHM buffer for decoded frames with layout that is taken from the frame metadata.
-                let mut shmbuf = unsafe {
-                    shm_provider
-                        .alloc(jpeg_meta.size())
-                        .with_unsafe_policy::<BlockOn<Defragment<GarbageCollect>>>()
-                        .await
-                        .expect("Failed to allocate SHM buffer")
+                let Some(mut shmbuf) = shm_pool
+                    .alloc(jpeg_meta.size())
+                    .await
+                    .expect("Failed to allocate SHM buffer")
+                else {
+                    continue;
                 };
 
                 // Map opencv Mat into allocated shared memory
@@ -178,15 +237,29 @@ async fn process_loop(
                 let contiguous_bytes = sample.payload().to_bytes();
 
                 // Decode frame into SHM buffer using shm-backed Mat
-                opencv::imgcodecs::imdecode_to(
+                match opencv::imgcodecs::imdecode_to(
                     &contiguous_bytes.deref(),
                     opencv::imgcodecs::IMREAD_COLOR,
                     &mut decoded_frame,
-                )
-                .expect("Failed to decode frame, maybe not Jpeg data?");
+                ) {
+                    // An image of another size would have been decoded out of SHM
+                    Ok(())
+                        if decoded_frame.rows() == jpeg_meta.rows()
+                            && decoded_frame.cols() == jpeg_meta.cols() => {}
+                    Ok(()) => {
+                        bad_frames.skip(format!("Jpeg image does not match {jpeg_meta}"));
+                        continue;
+                    }
+                    Err(e) => {
+                        bad_frames.skip(e);
+                        continue;
+                    }
+                }
 
                 // Encode frame metadata
-                let attachment = FrameMeta::Raw(jpeg_meta).encode().unwrap();
+                let mut raw_meta = jpeg_meta;
+                raw_meta.add_hop(STAGE);
+                let attachment = FrameMeta::Raw(raw_meta).encode().unwrap();
 
                 // Publish decoded frame as SHM
                 // NOTE:
@@ -194,6 +267,95 @@ async fn process_loop(
                 //      - will be published as Raw payload in other cases
                 publ.put(shmbuf).attachment(attachment).await.unwrap();
             }
+            FrameMeta::H264(video_meta) => {
+                // Encoded as H.264 - decode it, convert it directly to Zenoh SHM and publish
+                if !h264_synced && !video_meta.keyframe {
+                    // Joined the stream in the middle of a group of pictures: ask for a keyframe
+                    // instead of waiting for the next periodic one.
+                    request_keyframe(session, &key_sub, &params, &mut last_keyframe_request).await;
+                    continue;
+                }
+
+                let decoder = match &mut h264_decoder {
+                    Some(decoder) => decoder,
+                    None => {
+                        h264_decoder.insert(Decoder::new().expect("Failed to create H.264 decoder"))
+                    }
+                };
+
+                // This Cow accessor provides immutable access to contained data.
+                // Access will be zero-copy if data is contiguous (including SHM case).
+                let contiguous_bytes = sample.payload().to_bytes();
+
+                let yuv = match decoder.decode(&contiguous_bytes) {
+                    Ok(Some(yuv)) => yuv,
+                    // The decoder needs more data before producing a frame
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::warn!("Failed to decode H.264 frame: {e}");
+                        h264_synced = false;
+                        request_keyframe(session, &key_sub, &params, &mut last_keyframe_request)
+                            .await;
+                        continue;
+                    }
+                };
+                h264_synced = true;
+
+                let mut raw_meta = video_meta.raw;
+                let (cols, rows) = yuv.dimensions();
+                if (rows, cols) != (raw_meta.rows() as usize, raw_meta.cols() as usize) {
+                    bad_frames.skip(format!(
+                        "H.264 frame of {cols}x{rows} does not match {raw_meta}"
+                    ));
+                    continue;
+                }
+
+                // OpenH264 produces RGB while OpenCV frames are BGR
+                let mut rgb = vec![0u8; raw_meta.size()];
+                yuv.write_rgb8(&mut rgb);
+                let rgb_frame = unsafe { raw_meta.mat(rgb.as_ptr()) };
+
+                // Allocate SHM buffer for decoded frames with layout that is taken from the frame metadata.
+                let Some(mut shmbuf) = shm_pool
+                    .alloc(raw_meta.size())
+                    .await
+                    .expect("Failed to allocate SHM buffer")
+                else {
+                    continue;
+                };
+
+                // Map opencv Mat into allocated shared memory and convert the frame into it
+                let mut decoded_frame = unsafe { raw_meta.mat_mut(shmbuf.as_mut_ptr()) };
+                imgproc::cvt_color_def(&rgb_frame, &mut decoded_frame, imgproc::COLOR_RGB2BGR)
+                    .expect("Failed to convert decoded frame");
+
+                // Encode frame metadata
+                raw_meta.add_hop(STAGE);
+                let attachment = FrameMeta::Raw(raw_meta).encode().unwrap();
+
+                // Publish decoded frame as SHM
+                publ.put(shmbuf).attachment(attachment).await.unwrap();
+            }
         }
     }
 }
+
+/// Asks the encoder publishing on `key_sub` for a keyframe, at most once per `keyframe_request_interval`.
+async fn request_keyframe(
+    session: &Session,
+    key_sub: &str,
+    params: &Params<DecodeParams>,
+    last_request: &mut Option<Instant>,
+) {
+    let interval = Duration::from_millis(params.get().keyframe_request_interval);
+    if last_request.is_some_and(|t| t.elapsed() < interval) {
+        return;
+    }
+    *last_request = Some(Instant::now());
+
+    tracing::debug!("Requesting H.264 keyframe...");
+    // Replies are not needed: the keyframe will be received through the subscriber
+    if let Err(e) = session.get(keyframe_request_keyexpr(key_sub)).await {
+        tracing::warn!("Failed to request keyframe: {e}");
+    }
+}
@@ -0,0 +1,343 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use clap::Parser;
+use serde_json::json;
+use std::time::Duration;
+
+use opencv::{
+    core::{Rect, Scalar, Size, ToInputArray, Vector},
+    dnn::{self, DetectionModel},
+    prelude::*,
+};
+use tokio::select;
+use zcam::{
+    detection::{class_label, draw_detections, parse_labels, DetectParams, Detection, Detections},
+    params::{params_loop, Params},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    Session,
+};
+
+const STAGE: &str = "zdetect";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+
+    // Load model
+    let detector = Detector::new(&args).unwrap();
+    let params = detector.params.clone();
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    let key_detections = args
+        .key_detections
+        .clone()
+        .unwrap_or_else(|| format!("{}/detections", args.key_pub));
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_pub.clone(), key_detections, args.detections_only, reliability, congestion_ctrl, detector, shm_pool.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key_sub), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    /// Model file (e.g. `yolov4-tiny.weights`, `ssd_mobilenet.onnx`)
+    #[arg(long)]
+    model: String,
+
+    /// Network configuration file, if the framework needs one (e.g. `yolov4-tiny.cfg` for Darknet)
+    #[arg(long, default_value = "")]
+    model_config: String,
+
+    /// File with the label of each class, one per line
+    #[arg(long)]
+    labels: Option<String>,
+
+    /// Width of the network input
+    #[arg(long, default_value = "416")]
+    input_width: i32,
+
+    /// Height of the network input
+    #[arg(long, default_value = "416")]
+    input_height: i32,
+
+    /// Multiplier applied to the pixel values
+    #[arg(long, default_value = "0.00392156862745098")]
+    input_scale: f64,
+
+    /// Mean value subtracted from each channel
+    #[arg(long, default_value = "0")]
+    input_mean: f64,
+
+    /// Whether the network expects RGB input
+    #[arg(long, default_value = "true", action = clap::ArgAction::Set)]
+    swap_rb: bool,
+
+    #[arg(long, default_value = "0.5")]
+    min_score: f32,
+
+    /// Non-maximum suppression threshold
+    #[arg(long, default_value = "0.4")]
+    nms_threshold: f32,
+
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    #[arg(long, default_value = "demo/zcam/detect")]
+    key_pub: String,
+
+    /// Key to publish the detections on [default: <key_pub>/detections]
+    #[arg(long)]
+    key_detections: Option<String>,
+
+    /// Only publish the detections, not the annotated frames
+    #[arg(long, default_value = "false")]
+    detections_only: bool,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Object detector running a DNN model on CPU with OpenCV's dnn module.
+/// Any model supported by `cv::dnn::DetectionModel` can be used (e.g. Darknet YOLO, SSD).
+struct Detector {
+    model: DetectionModel,
+    labels: Vec<String>,
+    params: Params<DetectParams>,
+}
+
+impl Detector {
+    fn new(args: &Args) -> zenoh::Result<Self> {
+        let mut model = DetectionModel::new(&args.model, &args.model_config)?;
+        model.set_preferable_backend(dnn::DNN_BACKEND_OPENCV)?;
+        model.set_preferable_target(dnn::DNN_TARGET_CPU)?;
+        model.set_input_params(
+            args.input_scale,
+            Size::new(args.input_width, args.input_height),
+            Scalar::all(args.input_mean),
+            args.swap_rb,
+            false,
+        )?;
+
+        let labels = match &args.labels {
+            Some(path) => parse_labels(&std::fs::read_to_string(path)?),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            model,
+            labels,
+            params: Params::new(DetectParams {
+                min_score: args.min_score,
+                nms_threshold: args.nms_threshold,
+            })?,
+        })
+    }
+
+    fn detect(&mut self, frame: &impl ToInputArray) -> opencv::Result<Vec<Detection>> {
+        let params = self.params.get();
+        let mut class_ids = Vector::<i32>::new();
+        let mut scores = Vector::<f32>::new();
+        let mut boxes = Vector::<Rect>::new();
+        self.model.detect(
+            frame,
+            &mut class_ids,
+            &mut scores,
+            &mut boxes,
+            params.min_score,
+            params.nms_threshold,
+        )?;
+
+        Ok(class_ids
+            .iter()
+            .zip(scores.iter())
+            .zip(boxes.iter())
+            .map(|((class_id, score), rect)| Detection {
+                class_id,
+                label: class_label(&self.labels, class_id),
+                score,
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+            })
+            .collect())
+    }
+}
+
+/// Processing loop that subscribes to frames, detects objects with a DNN model, publishes the detections
+/// and, unless `detections_only` is set, republishes the frames with the detections drawn in-place in SHM.
+#[allow(clippy::too_many_arguments)]
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_pub: String,
+    key_detections: String,
+    detections_only: bool,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    mut detector: Detector,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    // Declare publisher for processed frames
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    // Declare publisher for detections
+    let detections_publ = session
+        .declare_publisher(&key_detections)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let mut sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let mut meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        let raw_meta = match &meta {
+            FrameMeta::Raw(raw_meta) => raw_meta.clone(),
+            other_meta => {
+                tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                continue;
+            }
+        };
+
+        let detections = if detections_only {
+            // No need to modify the frame: detect directly on the payload
+            let contiguous_bytes = sample.payload().to_bytes();
+            let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+            detector.detect(&frame).map_err(zenoh::Error::from)
+        } else {
+            match process_in_shm(&mut sample, &raw_meta, &shm_pool, |frame| {
+                let detections = detector.detect(&*frame)?;
+                draw_detections(frame, &detections, Scalar::new(0.0, 255.0, 255.0, 0.0))?;
+                Ok(detections)
+            })
+            .await
+            {
+                Ok((processed_frame_in_shm, detections)) => {
+                    // Encode frame metadata
+                    meta.raw_mut().add_hop(STAGE);
+                    let attachment = meta.encode().unwrap();
+
+                    // Publish SHM frame
+                    publ.put(processed_frame_in_shm)
+                        .attachment(attachment)
+                        .await
+                        .unwrap();
+                    Ok(detections)
+                }
+                Err(e) => Err(e),
+            }
+        };
+
+        match detections {
+            Ok(detections) => {
+                let message = Detections::new(STAGE, &raw_meta, detections);
+                detections_publ
+                    .put(message.encode().unwrap())
+                    .await
+                    .unwrap();
+            }
+            Err(e) => tracing::error!("Failed to process frame: {e}"),
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Structured detections and events published by the detector stages next to the frames.
+
+use std::collections::BTreeMap;
+
+use opencv::{
+    core::{Point, Rect, Scalar, Size, ToInputArray, ToInputOutputArray, Vector},
+    imgproc,
+    objdetect::{self, CascadeClassifier},
+    prelude::*,
+};
+use rkyv::{Archive, Deserialize, Serialize};
+use zenoh::bytes::ZBytes;
+
+use crate::{params::StageParams, FrameTrace, RawFrameMeta};
+
+/// An object detected in a frame.
+#[derive(Archive, Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Detection {
+    /// Class of the object, as numbered by the detector
+    pub class_id: i32,
+    /// Human readable class of the object (may be empty)
+    pub label: String,
+    /// Confidence of the detector (meaning depends on the detector)
+    pub score: f32,
+    /// Bounding box, in pixels of the frame
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Detection {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+/// The detections of a frame.
+#[derive(Archive, Deserialize, Serialize, Debug, Clone)]
+pub struct Detections {
+    /// Name of the detector stage (e.g. "zhaar")
+    pub detector: String,
+    /// Trace of the frame the detections were made on
+    pub trace: FrameTrace,
+    /// Size of the frame the bounding boxes refer to
+    pub rows: i32,
+    pub cols: i32,
+    pub detections: Vec<Detection>,
+}
+
+impl Detections {
+    pub fn new(detector: &str, raw_meta: &RawFrameMeta, detections: Vec<Detection>) -> Self {
+        Self {
+            detector: detector.to_string(),
+            trace: raw_meta.trace().clone(),
+            rows: raw_meta.rows(),
+            cols: raw_meta.cols(),
+            detections,
+        }
+    }
+
+    pub fn decode(payload: &ZBytes) -> zenoh::Result<Self> {
+        let bytes = payload.to_bytes();
+        let detections = rkyv::access::<ArchivedDetections, rkyv::rancor::Error>(&bytes)?;
+        Ok(rkyv::deserialize::<Detections, rkyv::rancor::Error>(
+            detections,
+        )?)
+    }
+
+    pub fn encode(&self) -> zenoh::Result<ZBytes> {
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self)?;
+        Ok(bytes.as_slice().into())
+    }
+}
+
+/// Latest detections received from a detector, matched to the frames by sequence number.
+#[derive(Debug)]
+pub struct PendingDetections {
+    capacity: usize,
+    pending: BTreeMap<u64, Detections>,
+}
+
+impl PendingDetections {
+    /// Returns a buffer keeping the detections of the last `capacity` frames.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, detections: Detections) {
+        self.pending.insert(detections.trace.seq, detections);
+        while self.pending.len() > self.capacity {
+            self.pending.pop_first();
+        }
+    }
+
+    /// Returns the detections of the most recent frame not after `seq` and at most `max_lag` frames before it,
+    /// scaled to a frame of `rows` x `cols`.
+    pub fn lookup(&self, seq: u64, max_lag: u64, rows: i32, cols: i32) -> Option<Vec<Detection>> {
+        let (detections_seq, detections) = self.pending.range(..=seq).next_back()?;
+        if seq - detections_seq > max_lag {
+            return None;
+        }
+        if detections.rows == rows && detections.cols == cols {
+            return Some(detections.detections.clone());
+        }
+
+        // The detections were made on a frame of another size (e.g. a scaled down rendition)
+        let sx = cols as f64 / detections.cols.max(1) as f64;
+        let sy = rows as f64 / detections.rows.max(1) as f64;
+        Some(
+            detections
+                .detections
+                .iter()
+                .map(|d| Detection {
+                    x: (d.x as f64 * sx).round() as i32,
+                    y: (d.y as f64 * sy).round() as i32,
+                    width: (d.width as f64 * sx).round() as i32,
+                    height: (d.height as f64 * sy).round() as i32,
+                    ..d.clone()
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Parameters of `zdetect` that can be changed while it runs.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct DetectParams {
+    /// Minimum confidence score of the detections to keep
+    pub min_score: f32,
+    /// IoU threshold of the non-maximum suppression
+    pub nms_threshold: f32,
+}
+
+impl StageParams for DetectParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.min_score) {
+            return Err("min_score must be between 0 and 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.nms_threshold) {
+            return Err("nms_threshold must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Reads the label of each class from `text`, one per line.
+pub fn parse_labels(text: &str) -> Vec<String> {
+    text.lines().map(|line| line.trim().to_string()).collect()
+}
+
+/// Label of class `class_id`, empty if it has none.
+pub fn class_label(labels: &[String], class_id: i32) -> String {
+    usize::try_from(class_id)
+        .ok()
+        .and_then(|i| labels.get(i).cloned())
+        .unwrap_or_default()
+}
+
+/// Motion detected in a frame.
+#[derive(Archive, Deserialize, Serialize, Debug, Clone)]
+pub struct MotionEvent {
+    /// Trace of the frame the motion was detected on
+    pub trace: FrameTrace,
+    /// Size of the frame the region refers to
+    pub rows: i32,
+    pub cols: i32,
+    /// Bounding box of the moving areas, in pixels of the frame
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Fraction of the frame in motion, between 0 and 1
+    pub magnitude: f32,
+}
+
+impl MotionEvent {
+    /// Returns the motion of a frame of `raw_meta`, given the bounding box and area of each moving region
+    /// and the number of pixels in motion, or `None` if no region is large enough or too little of the
+    /// frame is in motion.
+    pub fn from_regions(
+        raw_meta: &RawFrameMeta,
+        regions: impl IntoIterator<Item = (Rect, f64)>,
+        moving_pixels: i32,
+        params: &MotionParams,
+    ) -> Option<Self> {
+        // Bounding box of the moving regions large enough
+        let region = regions
+            .into_iter()
+            .filter(|(_, area)| *area >= params.min_area)
+            .map(|(rect, _)| rect)
+            .reduce(|r, rect| {
+                let (x, y) = (r.x.min(rect.x), r.y.min(rect.y));
+                Rect::new(
+                    x,
+                    y,
+                    (r.x + r.width).max(rect.x + rect.width) - x,
+                    (r.y + r.height).max(rect.y + rect.height) - y,
+                )
+            })?;
+
+        let magnitude = moving_pixels as f32 / (raw_meta.rows() * raw_meta.cols()).max(1) as f32;
+        if magnitude < params.min_magnitude {
+            return None;
+        }
+
+        Some(MotionEvent {
+            trace: raw_meta.trace().clone(),
+            rows: raw_meta.rows(),
+            cols: raw_meta.cols(),
+            x: region.x,
+            y: region.y,
+            width: region.width,
+            height: region.height,
+            magnitude,
+        })
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    pub fn decode(payload: &ZBytes) -> zenoh::Result<Self> {
+        let bytes = payload.to_bytes();
+        let event = rkyv::access::<ArchivedMotionEvent, rkyv::rancor::Error>(&bytes)?;
+        Ok(rkyv::deserialize::<MotionEvent, rkyv::rancor::Error>(
+            event,
+        )?)
+    }
+
+    pub fn encode(&self) -> zenoh::Result<ZBytes> {
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self)?;
+        Ok(bytes.as_slice().into())
+    }
+}
+
+/// Parameters of `zmotion` that can be changed while it runs.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct MotionParams {
+    /// Rate at which the background model adapts, between 0 and 1 (negative for automatic)
+    pub learning_rate: f64,
+    /// Minimum area of a moving region, in pixels
+    pub min_area: f64,
+    /// Minimum fraction of the frame in motion to emit an event
+    pub min_magnitude: f32,
+}
+
+impl StageParams for MotionParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.learning_rate > 1.0 {
+            return Err("learning_rate must be at most 1".to_string());
+        }
+        if self.min_area < 0.0 {
+            return Err("min_area must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.min_magnitude) {
+            return Err("min_magnitude must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Draws the bounding boxes, labels and scores of `detections` on `frame`.
+pub fn draw_detections(
+    frame: &mut impl ToInputOutputArray,
+    detections: &[Detection],
+    color: Scalar,
+) -> opencv::Result<()> {
+    for detection in detections {
+        imgproc::rectangle(frame, detection.rect(), color, 2, imgproc::LINE_8, 0)?;
+
+        let caption = if detection.label.is_empty() {
+            format!("{} {:.2}", detection.class_id, detection.score)
+        } else {
+            format!("{} {:.2}", detection.label, detection.score)
+        };
+        imgproc::put_text(
+            frame,
+            &caption,
+            Point::new(detection.x, (detection.y - 6).max(12)),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.5,
+            color,
+            1,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// Loads the Haar cascade of `file`.
+pub fn load_cascade(file: &str) -> Result<CascadeClassifier, String> {
+    match CascadeClassifier::new(file) {
+        Ok(cascade) if !cascade.empty().unwrap_or(true) => Ok(cascade),
+        _ => Err(format!("Unable to load cascade {file}")),
+    }
+}
+
+/// Detects objects in `frame` with a Haar cascade, keeping the ones with a weight of at least `min_weight`.
+pub fn detect_cascade(
+    frame: &impl ToInputArray,
+    cascade: &mut CascadeClassifier,
+    min_weight: f64,
+) -> opencv::Result<Vec<Detection>> {
+    // Detect directly on the color Mat – no cvt_color needed
+    let mut objects = Vector::new();
+    let mut reject_levels = Vector::new();
+    let mut level_weights = Vector::new();
+    cascade.detect_multi_scale3(
+        frame, // color image here
+        &mut objects,
+        &mut reject_levels,
+        &mut level_weights,
+        1.1,
+        3,
+        objdetect::CASCADE_SCALE_IMAGE,
+        Size::new(30, 30),
+        Size::new(0, 0),
+        true,
+    )?;
+
+    // Keep objects found with enough confidence
+    Ok(objects
+        .iter()
+        .zip(level_weights.iter())
+        .filter(|(_, weight)| *weight >= min_weight)
+        .map(|(object, weight)| Detection {
+            class_id: 0,
+            label: String::new(),
+            score: weight as f32,
+            x: object.x,
+            y: object.y,
+            width: object.width,
+            height: object.height,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PixelFormat;
+
+    fn detection(x: i32, y: i32, width: i32, height: i32) -> Detection {
+        Detection {
+            class_id: 0,
+            label: "face".to_string(),
+            score: 1.0,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn detections(seq: u64, rows: i32, cols: i32, detections: Vec<Detection>) -> Detections {
+        Detections {
+            detector: "zhaar".to_string(),
+            trace: FrameTrace {
+                seq,
+                ..Default::default()
+            },
+            rows,
+            cols,
+            detections,
+        }
+    }
+
+    #[test]
+    fn detections_survive_encoding() {
+        let sent = detections(7, 480, 640, vec![detection(10, 20, 30, 40)]);
+        let received = Detections::decode(&sent.encode().unwrap()).unwrap();
+        assert_eq!(received.detector, "zhaar");
+        assert_eq!(received.trace.seq, 7);
+        assert_eq!((received.rows, received.cols), (480, 640));
+        assert_eq!(received.detections, sent.detections);
+        assert!(Detections::decode(&ZBytes::from(vec![1u8, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn detections_match_the_latest_frame_within_the_lag() {
+        let mut pending = PendingDetections::new(2);
+        assert_eq!(pending.lookup(10, 5, 480, 640), None);
+        pending.insert(detections(10, 480, 640, vec![detection(1, 2, 3, 4)]));
+        pending.insert(detections(12, 480, 640, vec![detection(5, 6, 7, 8)]));
+
+        assert_eq!(pending.lookup(9, 5, 480, 640), None);
+        assert_eq!(
+            pending.lookup(11, 5, 480, 640),
+            Some(vec![detection(1, 2, 3, 4)])
+        );
+        assert_eq!(
+            pending.lookup(14, 5, 480, 640),
+            Some(vec![detection(5, 6, 7, 8)])
+        );
+        assert_eq!(pending.lookup(18, 5, 480, 640), None);
+
+        // Only the detections of the last frames are kept
+        pending.insert(detections(13, 480, 640, Vec::new()));
+        assert_eq!(pending.lookup(11, 5, 480, 640), None);
+    }
+
+    #[test]
+    fn detections_are_scaled_to_the_frame() {
+        let mut pending = PendingDetections::new(4);
+        pending.insert(detections(0, 240, 320, vec![detection(10, 20, 31, 41)]));
+        assert_eq!(
+            pending.lookup(0, 0, 480, 640),
+            Some(vec![detection(20, 40, 62, 82)])
+        );
+        assert_eq!(
+            pending.lookup(0, 0, 120, 160),
+            Some(vec![detection(5, 10, 16, 21)])
+        );
+    }
+
+    fn motion_params(min_area: f64, min_magnitude: f32) -> MotionParams {
+        MotionParams {
+            learning_rate: -1.0,
+            min_area,
+            min_magnitude,
+        }
+    }
+
+    #[test]
+    fn motion_covers_the_regions_large_enough() {
+        let mut raw_meta = RawFrameMeta::with_format(100, 200, PixelFormat::Gray);
+        raw_meta.set_capture(3, 1_000);
+        let regions = [
+            (Rect::new(10, 20, 30, 40), 500.0),
+            (Rect::new(100, 5, 10, 10), 50.0),
+            (Rect::new(60, 50, 20, 30), 400.0),
+        ];
+        let event =
+            MotionEvent::from_regions(&raw_meta, regions, 2000, &motion_params(100.0, 0.05))
+                .unwrap();
+        assert_eq!(event.rect(), Rect::new(10, 20, 70, 60));
+        assert_eq!(event.magnitude, 0.1);
+        assert_eq!((event.rows, event.cols), (100, 200));
+        assert_eq!(event.trace.seq, 3);
+
+        // The small region is kept without threshold on the area
+        let event =
+            MotionEvent::from_regions(&raw_meta, regions, 2000, &motion_params(0.0, 0.05)).unwrap();
+        assert_eq!(event.rect(), Rect::new(10, 5, 100, 75));
+    }
+
+    #[test]
+    fn motion_below_the_thresholds_is_ignored() {
+        let raw_meta = RawFrameMeta::with_format(100, 200, PixelFormat::Gray);
+        let regions = [(Rect::new(10, 20, 30, 40), 500.0)];
+        let params = motion_params(1000.0, 0.0);
+        assert!(MotionEvent::from_regions(&raw_meta, regions, 2000, &params).is_none());
+        let params = motion_params(100.0, 0.2);
+        assert!(MotionEvent::from_regions(&raw_meta, regions, 2000, &params).is_none());
+        let params = motion_params(0.0, 0.0);
+        assert!(MotionEvent::from_regions(&raw_meta, Vec::new(), 2000, &params).is_none());
+    }
+
+    #[test]
+    fn motion_params_are_checked() {
+        assert!(motion_params(0.0, 1.0).validate().is_ok());
+        assert!(motion_params(-1.0, 0.5).validate().is_err());
+        assert!(motion_params(10.0, 1.5).validate().is_err());
+        let params = MotionParams {
+            learning_rate: 1.5,
+            ..motion_params(10.0, 0.5)
+        };
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn detect_params_are_fractions() {
+        let params = |min_score, nms_threshold| DetectParams {
+            min_score,
+            nms_threshold,
+        };
+        assert!(params(0.0, 1.0).validate().is_ok());
+        assert!(params(0.5, 0.4).validate().is_ok());
+        assert!(params(-0.1, 0.4).validate().is_err());
+        assert!(params(0.5, 1.1).validate().is_err());
+        assert!(params(f32::NAN, 0.4).validate().is_err());
+    }
+
+    #[test]
+    fn classes_are_labeled_by_line() {
+        let labels = parse_labels("person\r\n bicycle \ncar\n");
+        assert_eq!(labels, ["person", "bicycle", "car"]);
+        assert_eq!(class_label(&labels, 1), "bicycle");
+        assert_eq!(class_label(&labels, 3), "");
+        assert_eq!(class_label(&labels, -1), "");
+        assert_eq!(class_label(&[], 0), "");
+    }
+}
@@ -12,12 +12,29 @@
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
 use clap::Parser;
-use futures::StreamExt;
-use opencv::highgui;
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Size, CV_8UC3},
+    highgui, imgcodecs, imgproc,
+    prelude::*,
+    videoio,
+};
 use serde_json::json;
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use tokio::select;
-use zcam::{config_update_loop, FrameMeta};
-use zenoh::{config::Config, Session, Wait};
+use zcam::{
+    layout::{parse_size, DisplayParams, FrameRate, Grid},
+    now_us,
+    params::{params_loop, Params},
+    FrameMeta,
+};
+use zenoh::{config::Config, sample::Sample, Session, Wait};
+
+const STAGE: &str = "zdisplay";
 
 #[tokio::main]
 async fn main() {
@@ -25,27 +42,46 @@ async fn main() {
     zenoh::init_log_from_env_or("error");
 
     // Parse command line arguments
-    let (config, key_sub) = parse_args();
+    let (config, key_sub, output, fps, tile) = parse_args();
+
+    let params = Params::new(DisplayParams { fps }).expect("Invalid parameters");
 
     println!("Opening session...");
     let z = zenoh::open(config).wait().unwrap();
 
     select!(
         // Processing loop
-        _ = process_loop(&z, key_sub.clone()) => {}
+        _ = process_loop(&z, key_sub.clone(), output, tile, params.clone()) => {}
         // Config update loop
-        _ = config_update_loop(&z, format!("{}/zdisplay/conf/**", key_sub)) => {},
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
     );
 }
 
-#[derive(clap::Parser, Clone, PartialEq, Eq, Hash)]
+#[derive(clap::Parser, Clone, PartialEq)]
 struct Args {
     #[arg(short, long)]
     mode: Option<String>,
 
+    /// Key of the frames to display; with a wildcard, all the matching streams are tiled in a grid
     #[arg(short, long, default_value = "demo/zcam")]
     key: String,
 
+    /// Do not open a window, write the frames to `--output` instead
+    #[arg(long, default_value = "false", requires = "output")]
+    headless: bool,
+
+    /// Directory to write PNG images in, or video file to write (e.g. `out.avi`, `out.mp4`)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Rate at which the display is refreshed, or frames are written
+    #[arg(long, default_value = "30")]
+    fps: f64,
+
+    /// Size of a tile of the grid, when several streams are displayed
+    #[arg(long, default_value = "640x360")]
+    tile_size: String,
+
     #[arg(short('e'), long)]
     connect: Option<Vec<String>>,
 
@@ -53,7 +89,7 @@ struct Args {
     config: Option<String>,
 }
 
-fn parse_args() -> (Config, String) {
+fn parse_args() -> (Config, String, Output, f64, Size) {
     let args = Args::parse();
     let mut c = if let Some(f) = args.config {
         zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
@@ -68,44 +104,264 @@ fn parse_args() -> (Config, String) {
         let _ = c.insert_json5("mode", &json!(m).to_string());
     }
 
-    (c, args.key)
+    let output = match args.output {
+        Some(path) if args.headless && path.extension().is_some() => Output::Video {
+            path,
+            fps: args.fps,
+            writer: None,
+        },
+        Some(path) if args.headless => {
+            std::fs::create_dir_all(&path).expect("Unable to create the output directory");
+            Output::Images {
+                dir: path,
+                count: 0,
+            }
+        }
+        _ => Output::Window(args.key.clone()),
+    };
+
+    let tile = parse_size(&args.tile_size).expect("Invalid tile size, expected <width>x<height>");
+
+    (c, args.key, output, args.fps, tile)
+}
+
+/// Latest frame and statistics of a stream.
+struct Stream {
+    frame: Mat,
+    rate: FrameRate,
+    // Latency from capture to display of the last frame, in microseconds
+    latency: Option<u64>,
+}
+
+/// Where the composed frames go.
+enum Output {
+    Window(String),
+    Images {
+        dir: PathBuf,
+        count: u64,
+    },
+    Video {
+        path: PathBuf,
+        fps: f64,
+        writer: Option<(videoio::VideoWriter, Size)>,
+    },
+}
+
+impl Output {
+    /// Shows or writes `frame`. Returns `false` if the user asked to quit.
+    fn write(&mut self, frame: &Mat) -> zenoh::Result<bool> {
+        match self {
+            Output::Window(name) => {
+                highgui::imshow(name, frame)?;
+                // 'q' key
+                Ok(highgui::poll_key()? != 113)
+            }
+            Output::Images { dir, count } => {
+                let path = dir.join(format!("{count:08}.png"));
+                imgcodecs::imwrite_def(&path.to_string_lossy(), frame)?;
+                *count += 1;
+                Ok(true)
+            }
+            Output::Video { path, fps, writer } => {
+                if writer.is_none() {
+                    // The size of the video is the size of the first frame
+                    let fourcc = match path.extension().and_then(|ext| ext.to_str()) {
+                        Some("mp4") => videoio::VideoWriter::fourcc('m', 'p', '4', 'v')?,
+                        _ => videoio::VideoWriter::fourcc('M', 'J', 'P', 'G')?,
+                    };
+                    let size = frame.size()?;
+                    let video = videoio::VideoWriter::new(
+                        &path.to_string_lossy(),
+                        fourcc,
+                        *fps,
+                        size,
+                        true,
+                    )?;
+                    if !video.is_opened()? {
+                        return Err(format!("Unable to open {}", path.display()).into());
+                    }
+                    *writer = Some((video, size));
+                }
+                let (video, size) = writer.as_mut().unwrap();
+                if frame.size()? == *size {
+                    video.write(frame)?;
+                } else {
+                    let mut resized = Mat::default();
+                    imgproc::resize(frame, &mut resized, *size, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+                    video.write(&resized)?;
+                }
+                Ok(true)
+            }
+        }
+    }
 }
 
-/// Processing loop that subscribes to frames and displays them using OpenCV's highgui module.
-/// Frames are mapped into OpenCV Mats without copying using metadata information and Zenoh's
-/// zero-copy accessors, so the processing is efficient even for large frames.
-async fn process_loop(session: &Session, key_sub: String) {
+/// Decodes the frame of `sample` into an owned BGR Mat.
+fn decode_frame(sample: &Sample, meta: &FrameMeta) -> zenoh::Result<Mat> {
+    // This Cow accessor provides immutable access to contained data.
+    // Access will be zero-copy if data is contiguous (including SHM case).
+    let contiguous_bytes = sample.payload().to_bytes();
+
+    let mut frame = Mat::default();
+    match meta {
+        FrameMeta::Raw(raw_frame_meta) => {
+            // Map opencv Mat into contiguous payload bytes, and copy it as the payload is released afterwards
+            let mapped = unsafe { raw_frame_meta.mat(contiguous_bytes.as_ptr()) };
+            frame = raw_frame_meta.to_bgr(&mapped)?;
+        }
+        FrameMeta::Jpeg(_) => {
+            imgcodecs::imdecode_to(
+                &contiguous_bytes.deref(),
+                imgcodecs::IMREAD_COLOR,
+                &mut frame,
+            )?;
+        }
+        other_meta => return Err(format!("Unsupported frame meta: {:?}", other_meta).into()),
+    }
+    Ok(frame)
+}
+
+/// Draws the name and statistics of a stream at the top of `frame`.
+fn draw_stats(frame: &mut Mat, key: &str, stream: &Stream) -> opencv::Result<()> {
+    let fps = stream.rate.fps(Instant::now());
+    let stats = match stream.latency {
+        Some(latency) => format!("{fps} fps, {:.1} ms", latency as f64 / 1000.0),
+        None => format!("{fps} fps"),
+    };
+    let width = frame.cols();
+    imgproc::rectangle(
+        frame,
+        Rect::new(0, 0, width, 48),
+        Scalar::all(0.0),
+        imgproc::FILLED,
+        imgproc::LINE_8,
+        0,
+    )?;
+    for (line, text) in [key, stats.as_str()].iter().enumerate() {
+        imgproc::put_text(
+            frame,
+            text,
+            Point::new(8, 18 + 22 * line as i32),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.5,
+            Scalar::all(255.0),
+            1,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+/// Composes the latest frame of each stream: the frame itself if there is a single stream,
+/// a grid of `tile` sized frames otherwise.
+fn compose(streams: &BTreeMap<String, Stream>, tile: Size) -> opencv::Result<Mat> {
+    if streams.len() == 1 {
+        let (key, stream) = streams.iter().next().unwrap();
+        let mut frame = stream.frame.clone();
+        draw_stats(&mut frame, key, stream)?;
+        return Ok(frame);
+    }
+
+    let grid = Grid::new(streams.len(), tile);
+    let size = grid.size();
+    let mut frame =
+        Mat::new_rows_cols_with_default(size.height, size.width, CV_8UC3, Scalar::all(0.0))?;
+    for (i, (key, stream)) in streams.iter().enumerate() {
+        let mut cell = Mat::default();
+        imgproc::resize(
+            &stream.frame,
+            &mut cell,
+            tile,
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )?;
+        draw_stats(&mut cell, key, stream)?;
+        let mut roi = frame.roi_mut(grid.cell(i))?;
+        cell.copy_to(&mut *roi)?;
+    }
+    Ok(frame)
+}
+
+/// Processing loop that subscribes to frames, decodes them (raw or Jpeg), and periodically shows the
+/// latest frame of each matching stream in a window, or writes it to image or video files in headless mode.
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    mut output: Output,
+    tile: Size,
+    params: Params<DisplayParams>,
+) {
     // Declare subscriber for frames
     let sub = session.declare_subscriber(key_sub).await.unwrap();
 
-    // Process incoming frames: read frames from stream and display them
-    sub.stream()
-        .for_each(async |sample| {
-            // Decode frame metadata
-            let meta = FrameMeta::decode(&sample)
-                .expect("Unable to decode frame metadata: probably wrong data format");
-
-            match meta {
-                FrameMeta::Raw(raw_frame_meta) => {
-                    // This Cow accessor provides immutable access to contained data.
-                    // Access will be zero-copy if data is contiguous (including SHM case).
-                    let contiguous_bytes = sample.payload().to_bytes();
-
-                    // Map opencv Mat into contiguous payload bytes
-                    let frame = unsafe { raw_frame_meta.mat(contiguous_bytes.as_ptr()) };
-
-                    // Display the frame
-                    highgui::imshow(sample.key_expr().as_str(), &frame)
-                        .expect("Failed to display frame!");
-                    if highgui::poll_key().unwrap() == 113 {
-                        // 'q' key
-                        std::process::exit(0);
+    let mut streams: BTreeMap<String, Stream> = BTreeMap::new();
+    let mut fps = params.get().fps;
+    let mut refresh = tokio::time::interval(Duration::from_secs_f64(1.0 / fps));
+
+    loop {
+        select!(
+            // Receive sample with frame
+            sample = sub.recv_async() => {
+                let sample = sample.unwrap();
+
+                // Decode frame metadata
+                let meta = match FrameMeta::decode(&sample) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        tracing::error!("Unable to decode frame metadata on {}: {e}", sample.key_expr());
+                        continue;
                     }
+                };
+
+                let frame = match decode_frame(&sample, &meta) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        tracing::error!("Unable to decode frame on {}: {e}", sample.key_expr());
+                        continue;
+                    }
+                };
+
+                // Update the stream statistics
+                let now = Instant::now();
+                let stream = streams
+                    .entry(sample.key_expr().to_string())
+                    .or_insert_with(|| Stream {
+                        frame: Mat::default(),
+                        rate: FrameRate::default(),
+                        latency: None,
+                    });
+                stream.frame = frame;
+                stream.rate.record(now);
+                stream.latency = match meta.raw().trace().capture_timestamp {
+                    0 => None,
+                    capture_timestamp => Some(now_us().saturating_sub(capture_timestamp)),
+                };
+            }
+
+            // Refresh the display
+            _ = refresh.tick() => {
+                let new_fps = params.get().fps;
+                if new_fps != fps {
+                    fps = new_fps;
+                    refresh = tokio::time::interval(Duration::from_secs_f64(1.0 / fps));
                 }
-                other_meta => {
-                    tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                if streams.is_empty() {
+                    continue;
+                }
+                let result = compose(&streams, tile)
+                    .map_err(zenoh::Error::from)
+                    .and_then(|frame| output.write(&frame));
+                match result {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => {
+                        tracing::error!("Failed to output frame: {e}");
+                        return;
+                    }
                 }
             }
-        })
-        .await;
+        );
+    }
 }
@@ -12,36 +12,80 @@
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
 use clap::Parser;
+use opencv::{imgproc, prelude::*};
+use openh264::{
+    encoder::{BitRate, Encoder, EncoderConfig, FrameType},
+    formats::{RgbSliceU8, YUVBuffer},
+    OpenH264API,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
 
 use tokio::select;
-use zcam::{config_update_loop, FrameMeta};
+use zcam::{
+    keyframe_request_keyexpr,
+    params::{params_loop, Params, StageParams},
+    rate_control::RateControl,
+    rendition::Rendition,
+    BadFrames, FrameMeta, PixelFormat, RawFrameMeta, VideoFrameMeta,
+};
 use zenoh::{
     config::Config,
+    pubsub::Publisher,
     qos::{CongestionControl, Reliability},
     Session, Wait,
 };
 
+const STAGE: &str = "zencode";
+
 #[tokio::main]
 async fn main() {
     // Initiate logging
     zenoh::init_log_from_env_or("error");
 
     // Parse command line arguments
-    let (config, key_sub, key_pub, reliability, congestion_ctrl) = parse_args();
+    let (config, key_sub, key_pub, reliability, congestion_ctrl, codec_options, renditions) =
+        parse_args();
+    let params = Params::new(EncodeParams {
+        jpeg_quality: codec_options.jpeg_quality,
+        keyframe_interval: codec_options.keyframe_interval,
+    })
+    .expect("Invalid parameters");
 
     println!("Opening session...");
     let z = zenoh::open(config).wait().unwrap();
 
     select!(
         // Processing loop
-        _ = process_loop(&z,key_sub.clone(), key_pub, reliability, congestion_ctrl) => {}
+        _ = process_loop(&z,key_sub.clone(), key_pub, reliability, congestion_ctrl, codec_options, renditions, params.clone()) => {}
         // Config update loop
-        _ = config_update_loop(&z, format!("{}/zencode/conf/**", key_sub)) => {},
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
     );
 }
 
-#[derive(clap::Parser, Clone, PartialEq, Eq, Hash)]
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Codec {
+    /// Every frame is compressed independently as a Jpeg image
+    Jpeg,
+    /// Frames are compressed as an H.264 stream with the OpenH264 software encoder
+    H264,
+}
+
+/// Encoder settings taken from the command line.
+#[derive(Clone, Copy, Debug)]
+struct CodecOptions {
+    codec: Codec,
+    jpeg_quality: i32,
+    keyframe_interval: u64,
+    bitrate: u32,
+    target_bitrate: Option<u64>,
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
 struct Args {
     #[arg(short, long)]
     mode: Option<String>,
@@ -63,6 +107,51 @@ struct Args {
 
     #[arg(long, default_value = "false")]
     block_on_congestion: bool,
+
+    #[arg(long, value_enum, default_value = "jpeg")]
+    codec: Codec,
+
+    #[arg(long, default_value = "90")]
+    jpeg_quality: i32,
+
+    /// Number of frames between two H.264 keyframes
+    #[arg(long, default_value = "50")]
+    keyframe_interval: u64,
+
+    /// Target H.264 bitrate in bits per second
+    #[arg(long, default_value = "4000000")]
+    bitrate: u32,
+
+    /// Enable rate control with the given target output bitrate in bits per second.
+    /// Jpeg quality, resolution and frame rate are adjusted to match it.
+    #[arg(long)]
+    target_bitrate: Option<u64>,
+
+    /// Publish a rendition of the frames on `<key_pub>/<name>`, given as `<name>:<scale>:<jpeg_quality>`
+    /// (e.g. `--rendition high:1.0:90 --rendition low:0.25:50`). May be repeated.
+    #[arg(long, conflicts_with = "target_bitrate")]
+    rendition: Vec<Rendition>,
+}
+
+/// Parameters of `zencode` that can be changed while it runs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct EncodeParams {
+    /// Jpeg quality, the upper bound of the rate control if enabled (renditions have their own)
+    jpeg_quality: i32,
+    /// Number of frames between two H.264 keyframes
+    keyframe_interval: u64,
+}
+
+impl StageParams for EncodeParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(0..=100).contains(&self.jpeg_quality) {
+            return Err("jpeg_quality must be between 0 and 100".to_string());
+        }
+        if self.keyframe_interval == 0 {
+            return Err("keyframe_interval must be at least 1".to_string());
+        }
+        Ok(())
+    }
 }
 
 fn parse_args() -> (
@@ -71,6 +160,8 @@ fn parse_args() -> (
     String,
     zenoh::qos::Reliability,
     zenoh::qos::CongestionControl,
+    CodecOptions,
+    Vec<Rendition>,
 ) {
     let args = Args::parse();
     let mut c = if let Some(f) = args.config {
@@ -97,79 +188,246 @@ fn parse_args() -> (
         zenoh::qos::Reliability::Reliable
     };
 
+    let codec_options = CodecOptions {
+        codec: args.codec,
+        jpeg_quality: args.jpeg_quality,
+        keyframe_interval: args.keyframe_interval.max(1),
+        bitrate: args.bitrate,
+        target_bitrate: args.target_bitrate,
+    };
+
     (
         c,
         args.key_sub,
         args.key_pub,
         reliability,
         congestion_control,
+        codec_options,
+        args.rendition,
     )
 }
 
 /// Processing loop that subscribes to frames, encodes them and republishes encoded frames.
+/// With renditions, each frame is encoded once per rendition and published on `<key_pub>/<name>`.
+#[allow(clippy::too_many_arguments)]
 async fn process_loop(
     session: &Session,
     key_sub: String,
     key_pub: String,
     reliability: Reliability,
     congestion_ctrl: CongestionControl,
+    codec_options: CodecOptions,
+    renditions: Vec<Rendition>,
+    params: Params<EncodeParams>,
 ) {
     // Declare subscriber for frames
     let sub = session.declare_subscriber(&key_sub).await.unwrap();
 
-    // Declare publisher for encoded frames
-    let publ = session
-        .declare_publisher(&key_pub)
-        .reliability(reliability)
-        .congestion_control(congestion_ctrl)
-        .await
-        .unwrap();
+    // Declare publishers for encoded frames, one per rendition
+    let mut outputs = Vec::new();
+    if renditions.is_empty() {
+        outputs.push(Output {
+            rendition: None,
+            publisher: session
+                .declare_publisher(key_pub.clone())
+                .reliability(reliability)
+                .congestion_control(congestion_ctrl)
+                .await
+                .unwrap(),
+            encoder: FrameEncoder::new(codec_options),
+        });
+    }
+    for rendition in renditions {
+        let options = CodecOptions {
+            jpeg_quality: rendition.jpeg_quality,
+            bitrate: (codec_options.bitrate as f64 * rendition.scale * rendition.scale) as u32,
+            ..codec_options
+        };
+        outputs.push(Output {
+            publisher: session
+                .declare_publisher(format!("{key_pub}/{}", rendition.name))
+                .reliability(reliability)
+                .congestion_control(congestion_ctrl)
+                .await
+                .unwrap(),
+            encoder: FrameEncoder::new(options),
+            rendition: Some(rendition),
+        });
+    }
+
+    // Declare queryable listing the available renditions and their parameters
+    let _renditions_queryable = if outputs.iter().any(|output| output.rendition.is_some()) {
+        let renditions = json!(outputs
+            .iter()
+            .filter_map(|output| output.rendition.as_ref().map(|rendition| json!({
+                "name": rendition.name,
+                "key": output.publisher.key_expr().as_str(),
+                "scale": rendition.scale,
+                "jpeg_quality": rendition.jpeg_quality,
+                "codec": format!("{:?}", codec_options.codec).to_lowercase(),
+            })))
+            .collect::<Vec<_>>())
+        .to_string();
+        Some(
+            session
+                .declare_queryable(format!("{key_pub}/renditions"))
+                .callback(move |query| {
+                    let _ = query
+                        .reply(query.key_expr().clone(), renditions.clone())
+                        .wait();
+                })
+                .await
+                .unwrap(),
+        )
+    } else {
+        None
+    };
 
-    // Prepare jpg encoder options
-    let mut encode_options = opencv::core::Vector::<i32>::new();
-    encode_options.push(opencv::imgcodecs::IMWRITE_JPEG_QUALITY);
-    encode_options.push(90);
+    // Declare queryable allowing late subscribers to request a keyframe (on any rendition)
+    let force_keyframe = Arc::new(AtomicBool::new(false));
+    let _keyframe_queryable = {
+        let force_keyframe = force_keyframe.clone();
+        session
+            .declare_queryable(keyframe_request_keyexpr(&format!("{key_pub}/**")))
+            .callback(move |query| {
+                force_keyframe.store(true, Ordering::Relaxed);
+                let _ = query.reply(query.key_expr().clone(), "ok").wait();
+            })
+            .await
+            .unwrap()
+    };
+
+    // Rate control state, updated from the published bandwidth and from subscribers feedback
+    let rate_control = codec_options.target_bitrate.map(|target| {
+        Arc::new(Mutex::new(RateControl::new(
+            target,
+            codec_options.jpeg_quality,
+        )))
+    });
+
+    // Declare subscriber for feedback published by the consumers of the encoded frames, and
+    // queryable exposing the current rate control decisions
+    let (_feedback_sub, _rate_control_queryable) = match &rate_control {
+        Some(rate_control) => {
+            let feedback_rate_control = rate_control.clone();
+            let feedback_sub = session
+                .declare_subscriber(format!("{key_pub}/feedback"))
+                .callback(move |sample| {
+                    match serde_json::from_slice::<serde_json::Value>(&sample.payload().to_bytes())
+                    {
+                        Ok(feedback) => {
+                            feedback_rate_control.lock().unwrap().on_feedback(&feedback)
+                        }
+                        Err(e) => {
+                            tracing::warn!("Invalid feedback from {}: {e}", sample.key_expr())
+                        }
+                    }
+                })
+                .await
+                .unwrap();
+
+            let queryable_rate_control = rate_control.clone();
+            let queryable = session
+                .declare_queryable(format!("{key_sub}/{STAGE}/conf/rate_control"))
+                .callback(move |query| {
+                    let decisions = queryable_rate_control.lock().unwrap().to_json();
+                    let _ = query
+                        .reply(query.key_expr().clone(), decisions.to_string())
+                        .wait();
+                })
+                .await
+                .unwrap();
+
+            (Some(feedback_sub), Some(queryable))
+        }
+        None => (None, None),
+    };
+
+    let mut bad_frames = BadFrames::default();
 
     loop {
         // Receive sample with frame
         let sample = sub.recv_async().await.unwrap();
 
         // Decode frame metadata
-        let meta = FrameMeta::decode(&sample)
-            .expect("Unable to decode frame metadata: probably wrong data format");
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
 
         match meta {
             FrameMeta::Raw(raw_meta) => {
-                let jpeg_buf = {
-                    // This Cow accessor provides immutable access to contained data.
-                    // Access will be zero-copy if data is contiguous (including SHM case).
-                    let contiguous_bytes = sample.payload().to_bytes();
+                let params = params.get();
+
+                // Apply rate control decisions
+                let (rate_scale, rate_quality) = match &rate_control {
+                    Some(rate_control) => {
+                        let mut rate_control = rate_control.lock().unwrap();
+                        rate_control.set_max_quality(params.jpeg_quality);
+                        if rate_control.skip_frame() {
+                            continue;
+                        }
+                        (rate_control.scale(), Some(rate_control.quality()))
+                    }
+                    None => (1.0, None),
+                };
 
-                    // Map opencv Mat into contiguous payload bytes
-                    let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+                // This Cow accessor provides immutable access to contained data.
+                // Access will be zero-copy if data is contiguous (including SHM case).
+                let contiguous_bytes = sample.payload().to_bytes();
 
-                    // Encode as jpeg
-                    let mut buf = opencv::core::Vector::<u8>::new();
-                    opencv::imgcodecs::imencode(".jpeg", &frame, &mut buf, &encode_options)
-                        .expect("Failed to encode frame to Jpeg");
+                // Map opencv Mat into contiguous payload bytes
+                let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
 
-                    buf
-                };
+                let force_keyframe = force_keyframe.swap(false, Ordering::Relaxed);
+                for output in outputs.iter_mut() {
+                    if let Some(quality) = rate_quality {
+                        output.encoder.set_jpeg_quality(quality);
+                    } else if output.rendition.is_none() {
+                        output.encoder.set_jpeg_quality(params.jpeg_quality);
+                    }
+                    output
+                        .encoder
+                        .set_keyframe_interval(params.keyframe_interval);
+                    let scale = rate_scale * output.rendition.as_ref().map_or(1.0, |r| r.scale);
 
-                // Encode frame metadata
-                let attachment = FrameMeta::Jpeg(raw_meta).encode().unwrap();
+                    let (encoded_buf, mut encoded_meta) =
+                        match output
+                            .encoder
+                            .encode(&frame, &raw_meta, scale, force_keyframe)
+                        {
+                            Ok(encoded) => encoded,
+                            Err(e) => {
+                                tracing::error!("Failed to encode frame: {e}");
+                                continue;
+                            }
+                        };
 
-                // Publish encoded frame
-                // NOTE:
-                //      - may leverage Zenoh's implicit SHM optimization and be published as SHM payload
-                //        for SHM-compatible subscribers
-                //      - will be published as Raw payload in other cases
-                publ.put(jpeg_buf.as_slice())
-                    .attachment(attachment)
-                    .await
-                    .unwrap();
+                    if let Some(rate_control) = &rate_control {
+                        rate_control.lock().unwrap().on_published(encoded_buf.len());
+                    }
+
+                    // Encode frame metadata
+                    encoded_meta.raw_mut().add_hop(STAGE);
+                    let attachment = encoded_meta.encode().unwrap();
+
+                    // Publish encoded frame
+                    // NOTE:
+                    //      - may leverage Zenoh's implicit SHM optimization and be published as SHM payload
+                    //        for SHM-compatible subscribers
+                    //      - will be published as Raw payload in other cases
+                    output
+                        .publisher
+                        .put(encoded_buf)
+                        .attachment(attachment)
+                        .await
+                        .unwrap();
+                }
             }
-            FrameMeta::Jpeg(_) => {
+            FrameMeta::Jpeg(_) | FrameMeta::H264(_) => {
                 // Already encoded - republish sample as it is
                 // NOTE: depending on initial sample's payload, the following options available:
                 // 1, SHM payload:
@@ -179,11 +437,160 @@ async fn process_loop(
                 //      - may leverage Zenoh's implicit SHM optimization and be published as SHM payload
                 //        for SHM-compatible subscribers
                 //      - will be published as Raw payload in other cases
-                publ.put(sample.payload().to_owned())
-                    .attachment(sample.attachment().cloned())
-                    .await
-                    .unwrap();
+                for output in &outputs {
+                    output
+                        .publisher
+                        .put(sample.payload().to_owned())
+                        .attachment(sample.attachment().cloned())
+                        .await
+                        .unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// Publisher and encoder of a rendition.
+struct Output<'a> {
+    rendition: Option<Rendition>,
+    publisher: Publisher<'a>,
+    encoder: FrameEncoder,
+}
+
+/// Encoder for the configured codec.
+/// The H.264 encoder is stateful and is created with the first frame to encode.
+struct FrameEncoder {
+    options: CodecOptions,
+    jpeg_options: opencv::core::Vector<i32>,
+    h264: Option<Encoder>,
+    frame_count: u64,
+}
+
+impl FrameEncoder {
+    fn new(options: CodecOptions) -> Self {
+        // Prepare jpg encoder options
+        let mut jpeg_options = opencv::core::Vector::<i32>::new();
+        jpeg_options.push(opencv::imgcodecs::IMWRITE_JPEG_QUALITY);
+        jpeg_options.push(options.jpeg_quality);
+
+        Self {
+            options,
+            jpeg_options,
+            h264: None,
+            frame_count: 0,
+        }
+    }
+
+    fn set_jpeg_quality(&mut self, quality: i32) {
+        if self.options.jpeg_quality != quality {
+            self.options.jpeg_quality = quality;
+            self.jpeg_options = opencv::core::Vector::from_slice(&[
+                opencv::imgcodecs::IMWRITE_JPEG_QUALITY,
+                quality,
+            ]);
+        }
+    }
+
+    fn set_keyframe_interval(&mut self, keyframe_interval: u64) {
+        self.options.keyframe_interval = keyframe_interval.max(1);
+    }
+
+    /// Encodes `frame`, downscaled by `scale` if lower than 1.
+    fn encode(
+        &mut self,
+        frame: &(impl MatTraitConst + opencv::core::ToInputArray),
+        raw_meta: &RawFrameMeta,
+        scale: f64,
+        force_keyframe: bool,
+    ) -> zenoh::Result<(Vec<u8>, FrameMeta)> {
+        if raw_meta.format() != PixelFormat::Bgr || !raw_meta.is_packed() {
+            // The encoders take BGR frames with packed rows: the metadata must describe the new layout
+            let bgr = raw_meta.to_bgr(frame)?;
+            let bgr_meta = raw_meta.to_bgr_meta(&bgr)?;
+            return self.encode(&bgr, &bgr_meta, scale, force_keyframe);
+        }
+        let (cols, rows) =
+            encoded_size(self.options.codec, raw_meta.cols(), raw_meta.rows(), scale);
+        if (cols, rows) != (raw_meta.cols(), raw_meta.rows()) {
+            // Downscale the frame: the metadata must describe the new layout
+            let mut scaled = Mat::default();
+            imgproc::resize(
+                frame,
+                &mut scaled,
+                opencv::core::Size::new(cols, rows),
+                0.0,
+                0.0,
+                imgproc::INTER_AREA,
+            )?;
+            let scaled_meta = raw_meta.with_layout_of(&scaled)?;
+            self.encode_frame(&scaled, scaled_meta, force_keyframe)
+        } else {
+            self.encode_frame(frame, raw_meta.clone(), force_keyframe)
+        }
+    }
+
+    fn encode_frame(
+        &mut self,
+        frame: &(impl MatTraitConst + opencv::core::ToInputArray),
+        raw_meta: RawFrameMeta,
+        force_keyframe: bool,
+    ) -> zenoh::Result<(Vec<u8>, FrameMeta)> {
+        match self.options.codec {
+            Codec::Jpeg => {
+                // Encode as jpeg
+                let mut buf = opencv::core::Vector::<u8>::new();
+                opencv::imgcodecs::imencode(".jpeg", frame, &mut buf, &self.jpeg_options)?;
+                Ok((buf.to_vec(), FrameMeta::Jpeg(raw_meta)))
+            }
+            Codec::H264 => {
+                if self.h264.is_none() {
+                    let config =
+                        EncoderConfig::new().bitrate(BitRate::from_bps(self.options.bitrate));
+                    self.h264 = Some(Encoder::with_api_config(
+                        OpenH264API::from_source(),
+                        config,
+                    )?);
+                }
+                let encoder = self.h264.as_mut().unwrap();
+
+                // Emit a keyframe periodically, or when requested by a late subscriber
+                if force_keyframe || self.frame_count % self.options.keyframe_interval == 0 {
+                    encoder.force_intra_frame();
+                }
+                self.frame_count += 1;
+
+                // OpenH264 takes RGB input while OpenCV frames are BGR
+                let mut rgb = Mat::default();
+                imgproc::cvt_color_def(frame, &mut rgb, imgproc::COLOR_BGR2RGB)?;
+                let yuv = YUVBuffer::from_rgb_source(RgbSliceU8::new(
+                    rgb.data_bytes()?,
+                    (raw_meta.cols() as usize, raw_meta.rows() as usize),
+                ));
+
+                let bitstream = encoder.encode(&yuv)?;
+                let keyframe = matches!(bitstream.frame_type(), FrameType::IDR | FrameType::I);
+                Ok((
+                    bitstream.to_vec(),
+                    FrameMeta::H264(VideoFrameMeta {
+                        raw: raw_meta,
+                        keyframe,
+                    }),
+                ))
             }
         }
     }
 }
+
+/// The size to encode a frame of `cols` x `rows` at, downscaled by `scale` if lower than 1.
+/// H.264 frames are converted to YUV 4:2:0, which takes even dimensions: they are rounded down.
+fn encoded_size(codec: Codec, cols: i32, rows: i32, scale: f64) -> (i32, i32) {
+    let (cols, rows) = if scale < 1.0 {
+        ((cols as f64 * scale) as i32, (rows as f64 * scale) as i32)
+    } else {
+        (cols, rows)
+    };
+    match codec {
+        Codec::Jpeg => (cols.max(1), rows.max(1)),
+        Codec::H264 => ((cols & !1).max(2), (rows & !1).max(2)),
+    }
+}
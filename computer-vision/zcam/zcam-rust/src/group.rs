@@ -0,0 +1,211 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Synchronization of the frames of several cameras: grouping of the frames captured at the same time.
+
+use std::collections::VecDeque;
+
+use rkyv::{Archive, Deserialize, Serialize};
+use zenoh::bytes::ZBytes;
+
+use crate::{params::StageParams, FrameMeta};
+
+/// A frame of a [`FrameGroup`].
+#[derive(Archive, Deserialize, Serialize, Debug)]
+pub struct GroupedFrame {
+    /// Key the frame was published on
+    pub key: String,
+    pub meta: FrameMeta,
+    pub payload: Vec<u8>,
+}
+
+/// Frames of several streams captured at the same time, in the order of the streams.
+#[derive(Archive, Deserialize, Serialize, Debug)]
+pub struct FrameGroup {
+    /// Capture time of the earliest frame, in microseconds since the UNIX epoch
+    pub timestamp: u64,
+    pub frames: Vec<GroupedFrame>,
+}
+
+impl FrameGroup {
+    pub fn decode(payload: &ZBytes) -> zenoh::Result<Self> {
+        let bytes = payload.to_bytes();
+        let group = rkyv::access::<ArchivedFrameGroup, rkyv::rancor::Error>(&bytes)?;
+        Ok(rkyv::deserialize::<FrameGroup, rkyv::rancor::Error>(group)?)
+    }
+
+    pub fn encode(&self) -> zenoh::Result<ZBytes> {
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self)?;
+        Ok(bytes.as_slice().into())
+    }
+}
+
+/// Runtime parameters of `zsync`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct SyncParams {
+    /// Maximum difference between the capture timestamps of the frames of a group, in milliseconds
+    pub tolerance: u64,
+}
+
+impl StageParams for SyncParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.tolerance > 60_000 {
+            return Err("tolerance must be at most 60000 ms".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Groups the frames of several streams whose timestamps are within a tolerance of each other.
+///
+/// Frames are matched oldest first: as long as the oldest pending frames of the streams are not within
+/// the tolerance, the oldest of all is dropped, as no frame to come can match it anymore.
+#[derive(Debug)]
+pub struct FrameSynchronizer<T> {
+    tolerance: u64,
+    max_pending: usize,
+    // Pending frames of each stream, with their timestamp, oldest first
+    pending: Vec<VecDeque<(u64, T)>>,
+    dropped: u64,
+}
+
+impl<T> FrameSynchronizer<T> {
+    /// Synchronizes `streams` streams with a `tolerance` in microseconds, keeping at most `max_pending` frames per stream.
+    pub fn new(streams: usize, tolerance: u64, max_pending: usize) -> Self {
+        Self {
+            tolerance,
+            max_pending,
+            pending: (0..streams).map(|_| VecDeque::new()).collect(),
+            dropped: 0,
+        }
+    }
+
+    /// Changes the tolerance, in microseconds, for the frames pushed from now on.
+    pub fn set_tolerance(&mut self, tolerance: u64) {
+        self.tolerance = tolerance;
+    }
+
+    /// Number of frames dropped so far, for lack of matching frames in the other streams.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Adds a frame of stream `stream`, and returns the frames of all the streams, in order, if it completes a group.
+    pub fn push(&mut self, stream: usize, timestamp: u64, frame: T) -> Option<(u64, Vec<T>)> {
+        let queue = &mut self.pending[stream];
+        if queue.back().is_some_and(|(last, _)| timestamp < *last) {
+            // Out of order frame, the stream restarted: start over
+            self.dropped += queue.len() as u64;
+            queue.clear();
+        }
+        queue.push_back((timestamp, frame));
+        if queue.len() > self.max_pending {
+            queue.pop_front();
+            self.dropped += 1;
+        }
+
+        loop {
+            let heads = self
+                .pending
+                .iter()
+                .map(|queue| queue.front().map(|(timestamp, _)| *timestamp))
+                .collect::<Option<Vec<u64>>>()?;
+            let (oldest, newest) = (*heads.iter().min().unwrap(), *heads.iter().max().unwrap());
+            if newest - oldest <= self.tolerance {
+                let frames = self
+                    .pending
+                    .iter_mut()
+                    .map(|queue| queue.pop_front().unwrap().1)
+                    .collect();
+                return Some((oldest, frames));
+            }
+            // The oldest frame cannot be matched anymore
+            let stream = heads.iter().position(|t| *t == oldest).unwrap();
+            self.pending[stream].pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_within_the_tolerance_are_grouped() {
+        let mut sync = FrameSynchronizer::new(3, 10, 8);
+        assert_eq!(sync.push(0, 1000, "a0"), None);
+        assert_eq!(sync.push(2, 1010, "c0"), None);
+        assert_eq!(
+            sync.push(1, 1005, "b0"),
+            Some((1000, vec!["a0", "b0", "c0"]))
+        );
+        assert_eq!(sync.dropped(), 0);
+
+        // Groups are only made of the frames pending since
+        assert_eq!(sync.push(0, 2000, "a1"), None);
+        assert_eq!(sync.push(1, 2000, "b1"), None);
+        assert_eq!(
+            sync.push(2, 1995, "c1"),
+            Some((1995, vec!["a1", "b1", "c1"]))
+        );
+    }
+
+    #[test]
+    fn frames_that_cannot_match_are_dropped() {
+        let mut sync = FrameSynchronizer::new(2, 10, 8);
+        assert_eq!(sync.push(0, 1000, "a0"), None);
+        assert_eq!(sync.push(0, 1033, "a1"), None);
+        // a0 is too old for b0, and no later frame of the second stream can match it
+        assert_eq!(sync.push(1, 1030, "b0"), Some((1030, vec!["a1", "b0"])));
+        assert_eq!(sync.dropped(), 1);
+
+        // Stale frames are dropped until the heads match
+        assert_eq!(sync.push(1, 1060, "b1"), None);
+        assert_eq!(sync.push(1, 1090, "b2"), None);
+        assert_eq!(sync.push(0, 1095, "a2"), Some((1090, vec!["a2", "b2"])));
+        assert_eq!(sync.dropped(), 2);
+    }
+
+    #[test]
+    fn pending_frames_are_bounded() {
+        let mut sync = FrameSynchronizer::new(2, 10, 2);
+        for (i, timestamp) in [1000, 1033, 1066].iter().enumerate() {
+            assert_eq!(sync.push(0, *timestamp, i), None);
+        }
+        assert_eq!(sync.dropped(), 1);
+        assert_eq!(sync.push(1, 1040, 10), Some((1033, vec![1, 10])));
+    }
+
+    #[test]
+    fn restarted_streams_start_over() {
+        let mut sync = FrameSynchronizer::new(2, 10, 8);
+        assert_eq!(sync.push(0, 5000, "a0"), None);
+        assert_eq!(sync.push(0, 5033, "a1"), None);
+        // The capture restarted with earlier timestamps
+        assert_eq!(sync.push(0, 100, "a2"), None);
+        assert_eq!(sync.dropped(), 2);
+        assert_eq!(sync.push(1, 105, "b0"), Some((100, vec!["a2", "b0"])));
+    }
+
+    #[test]
+    fn tolerance_can_be_changed() {
+        let mut sync = FrameSynchronizer::new(2, 0, 8);
+        assert_eq!(sync.push(0, 1000, "a0"), None);
+        sync.set_tolerance(50);
+        assert_eq!(sync.push(1, 1040, "b0"), Some((1000, vec!["a0", "b0"])));
+        assert!(SyncParams { tolerance: 60_000 }.validate().is_ok());
+        assert!(SyncParams { tolerance: 60_001 }.validate().is_err());
+    }
+}
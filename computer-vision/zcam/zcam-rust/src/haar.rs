@@ -12,48 +12,70 @@
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
 use clap::Parser;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use opencv::{
-    core::{Scalar, ToInputArray, ToInputOutputArray},
+    core::{Scalar, ToInputOutputArray},
     imgproc,
-    objdetect::{self, CascadeClassifier},
-    prelude::*,
+    objdetect::CascadeClassifier,
 };
-use std::{convert::TryInto, io::Read, sync::Arc};
+use std::time::Duration;
 use tokio::select;
-use zcam::{config_update_loop, FrameMeta};
+use zcam::{
+    detection::{detect_cascade, load_cascade, Detection, Detections},
+    params::{params_loop, Params, StageParams},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta,
+};
 use zenoh::{
+    bytes::ZBytes,
     config::Config,
     qos::{CongestionControl, Reliability},
     sample::Sample,
-    shm::{
-        zshm, zshmmut, BlockOn, Defragment, GarbageCollect, PosixShmProviderBackend, ShmProvider,
-        ZShm,
-    },
     Session,
 };
 
+const STAGE: &str = "zhaar";
+
 #[tokio::main]
 async fn main() {
     // Initiate logging
     zenoh::init_log_from_env_or("error");
 
     // Parse command line arguments
-    let (haarcascade_file, config, key_sub, key_pub, reliability, congestion_ctrl, min_weight) =
-        parse_args();
-
-    // Load cascade
-    let cascade = objdetect::CascadeClassifier::new(&haarcascade_file).unwrap();
+    let (
+        haarcascade_file,
+        config,
+        key_sub,
+        key_pub,
+        reliability,
+        congestion_ctrl,
+        min_weight,
+        draw,
+        shm_args,
+    ) = parse_args();
+
+    // Check parameters, including that the cascade loads
+    let params = Params::new(HaarParams {
+        haarcascade_file,
+        min_weight,
+        draw,
+    })
+    .expect("Invalid parameters");
 
     println!("Opening session...");
     let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &shm_args).await;
 
     select!(
         // Processing loop
-        _ = process_loop(&z,key_sub.clone(), key_pub, reliability, congestion_ctrl, cascade, min_weight) => {}
+        _ = process_loop(&z,key_sub.clone(), key_pub, reliability, congestion_ctrl, params.clone(), shm_pool.clone()) => {}
         // Config update loop
-        _ = config_update_loop(&z, format!("{}/zhaar/conf/**", key_sub)) => {},
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", key_sub), shm_pool, Duration::from_secs(1)) => {},
     );
 }
 
@@ -65,6 +87,10 @@ struct Args {
     #[arg(long, default_value = "3.5")]
     min_weight: f64,
 
+    /// Do not draw the detections on the frames, only publish them on `<key_pub>/detections`
+    #[arg(long, default_value = "false")]
+    no_draw: bool,
+
     #[arg(short, long)]
     mode: Option<String>,
 
@@ -80,6 +106,9 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    #[command(flatten)]
+    shm: ShmArgs,
+
     #[arg(long, default_value = "false")]
     best_effort: bool,
 
@@ -95,6 +124,8 @@ fn parse_args() -> (
     zenoh::qos::Reliability,
     zenoh::qos::CongestionControl,
     f64,
+    bool,
+    ShmArgs,
 ) {
     let args = Args::parse();
     let mut c = if let Some(f) = args.config {
@@ -109,6 +140,7 @@ fn parse_args() -> (
     if let Some(m) = args.mode {
         let _ = c.insert_json5("mode", &json!(m).to_string());
     }
+    args.shm.apply(&mut c);
 
     let congestion_control = if args.block_on_congestion {
         zenoh::qos::CongestionControl::Block
@@ -129,9 +161,31 @@ fn parse_args() -> (
         reliability,
         congestion_control,
         args.min_weight,
+        !args.no_draw,
+        args.shm,
     )
 }
 
+/// Parameters of `zhaar` that can be changed while it runs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct HaarParams {
+    /// Cascade file, reloaded when changed
+    haarcascade_file: String,
+    /// Minimum weight of the detections to keep
+    min_weight: f64,
+    /// Draw the detections on the frames
+    draw: bool,
+}
+
+impl StageParams for HaarParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.min_weight < 0.0 {
+            return Err("min_weight must be positive".to_string());
+        }
+        load_cascade(&self.haarcascade_file).map(|_| ())
+    }
+}
+
 /// Processing loop that subscribes to frames, detects objects using Haar cascades and republishes the processed frames.
 /// Frames are processed in-place directly in SHM without copying whenever possible, leveraging Zenoh's zero-copy accessors
 /// and metadata information to map OpenCV Mats into SHM buffers. This makes the processing efficient even for large frames,
@@ -143,12 +197,20 @@ async fn process_loop(
     key_pub: String,
     reliability: Reliability,
     congestion_ctrl: CongestionControl,
-    mut cascade: CascadeClassifier,
-    min_weight: f64,
+    params: Params<HaarParams>,
+    shm_pool: ShmPool,
 ) {
     // Declare subscriber for frames
     let sub = session.declare_subscriber(&key_sub).await.unwrap();
 
+    // Declare publisher for detections
+    let detections_publ = session
+        .declare_publisher(format!("{key_pub}/detections"))
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
     // Declare publisher for processed frames
     let publ = session
         .declare_publisher(&key_pub)
@@ -157,97 +219,105 @@ async fn process_loop(
         .await
         .unwrap();
 
-    // Obtain SHM provider from the session to allocate SHM buffers for frames
-    let shm_provider = session
-        .get_shm_provider()
-        .await
-        .expect("Failed to get transport SHM provider");
+    // Load cascade
+    let mut haarcascade_file = params.get().haarcascade_file;
+    let mut cascade = load_cascade(&haarcascade_file).unwrap();
+
+    let mut bad_frames = BadFrames::default();
 
     loop {
         // Receive sample with frame
         let mut sample = sub.recv_async().await.unwrap();
 
+        // Decode frame metadata
+        let mut meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        // Reload the cascade if it was changed (it was checked when set)
+        let HaarParams {
+            haarcascade_file: file,
+            min_weight,
+            draw,
+        } = params.get();
+        if file != haarcascade_file {
+            match load_cascade(&file) {
+                Ok(loaded) => cascade = loaded,
+                Err(e) => tracing::error!("{e}"),
+            }
+            haarcascade_file = file;
+        }
+
         // Prcess the recieved frame and obtain the processed frame in SHM
-        if let Ok(processed_frame_in_shm) =
-            process_frame(&mut sample, &shm_provider, &mut cascade, min_weight).await
+        if let Ok((processed_frame, detections)) = process_frame(
+            &mut sample,
+            &meta,
+            &shm_pool,
+            &mut cascade,
+            min_weight,
+            draw,
+        )
+        .await
         {
+            // Encode frame metadata
+            meta.raw_mut().add_hop(STAGE);
+            let attachment = meta.encode().unwrap();
+
             // Publish SHM frame
             // NOTE:
             //      - Will be published as SHM payload for SHM-compatible subscribers
             //      - will be published as Raw payload in other cases
-            publ.put(processed_frame_in_shm)
-                .attachment(sample.attachment().cloned())
+            publ.put(processed_frame)
+                .attachment(attachment)
+                .await
+                .unwrap();
+
+            // Publish detections
+            let detections = Detections::new(STAGE, meta.raw(), detections);
+            detections_publ
+                .put(detections.encode().unwrap())
                 .await
                 .unwrap();
         }
     }
 }
 
+/// Detects objects in the frame and, if `draw` is set, draws them in the frame.
+/// Returns the frame to republish along with the detections.
 async fn process_frame(
     sample: &mut Sample,
-    shm_provider: &Arc<ShmProvider<PosixShmProviderBackend>>,
-    cascade: &mut objdetect::CascadeClassifier,
+    meta: &FrameMeta,
+    shm_pool: &ShmPool,
+    cascade: &mut CascadeClassifier,
     min_weight: f64,
-) -> zenoh::Result<ZShm> {
-    // Decode frame metadata
-    let meta = FrameMeta::decode(sample)
-        .expect("Unable to decode frame metadata: probably wrong data format");
-
+    draw: bool,
+) -> zenoh::Result<(ZBytes, Vec<Detection>)> {
     match meta {
+        FrameMeta::Raw(raw_meta) if draw => {
+            // Detect objects and draw rectangles directly on the SHM buffer
+            let (processed_frame, detections) =
+                process_in_shm(sample, raw_meta, shm_pool, |frame| {
+                    let detections = detect_cascade(&*frame, cascade, min_weight)?;
+                    draw_objects(frame, &detections)?;
+                    Ok(detections)
+                })
+                .await?;
+            Ok((processed_frame.into(), detections))
+        }
         FrameMeta::Raw(raw_meta) => {
-            fn try_mutate_shm_inplace(sample: &mut Sample) -> Option<&mut zshmmut> {
-                // Try to interpret the payload as SHM buffer
-                let shm_buf = sample.payload_mut().as_shm_mut()?;
-
-                // Try to get mutable access to the SHM buffer
-                shm_buf.try_into().ok()
-            }
-
-            // First, try to process the frame in-place without copying if the payload is already an SHM buffer
-            // and if we can get mutable access to it. This is the most efficient path as it avoids any copying.
-            match try_mutate_shm_inplace(sample) {
-                Some(shm_mut_inplace) => {
-                    // Map opencv Mat into shared memory
-                    let mut frame = unsafe { raw_meta.mat_mut(shm_mut_inplace.as_mut_ptr()) };
-
-                    // Detect objects and draw rectangles in-place directly on the SHM buffer
-                    detect_objects(&mut frame, cascade, min_weight)?;
-
-                    let shm_immut: &mut zshm = shm_mut_inplace.into();
-
-                    // Return the processed frame as SHM without copying
-                    Ok(shm_immut.to_owned())
-                }
-                None => {
-                    // If any of the in-place SHM mutation steps fail, fall back to copying the data into a new SHM buffer
-                    tracing::debug!("SHM inplace failed, falling back to copy...");
-
-                    let payload = sample.payload();
-
-                    // Allocate SHM buffer for contiguous payload bytes
-                    let mut shmbuf: zenoh::shm::ZShmMut = unsafe {
-                        shm_provider
-                            .alloc(payload.len())
-                            .with_unsafe_policy::<BlockOn<Defragment<GarbageCollect>>>()
-                            .await
-                            .expect("Failed to allocate SHM buffer")
-                    };
-
-                    // Read bytes directly into SHM buffer
-                    payload.reader().read_exact(&mut shmbuf)?;
-
-                    // Map opencv Mat into allocated shared memory
-                    let mut frame = unsafe { raw_meta.mat_mut(shmbuf.as_mut_ptr()) };
-
-                    // Detect objects and draw rectangles directly on the SHM buffer
-                    detect_objects(&mut frame, cascade, min_weight)?;
-
-                    // Return the processed frame as SHM
-                    Ok(shmbuf.into())
-                }
-            }
+            // The frame is left untouched: detect on the payload and republish it as it is
+            let detections = {
+                let contiguous_bytes = sample.payload().to_bytes();
+                let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+                detect_cascade(&frame, cascade, min_weight)?
+            };
+            Ok((sample.payload().clone(), detections))
         }
-        FrameMeta::Jpeg(_) => {
+        FrameMeta::Jpeg(_) | FrameMeta::H264(_) => {
             let err = format!("Unsupported frame meta: {:?}", meta);
             tracing::error!("{err}");
             Err(err.into())
@@ -255,43 +325,20 @@ async fn process_frame(
     }
 }
 
-fn detect_objects<F: MatTraitConst + ToInputArray + ToInputOutputArray>(
-    frame: &mut F,
-    cascade: &mut objdetect::CascadeClassifier,
-    min_weight: f64,
+fn draw_objects(
+    frame: &mut impl ToInputOutputArray,
+    detections: &[Detection],
 ) -> opencv::Result<()> {
-    // Detect directly on the color Mat – no cvt_color needed
-    let mut objects = opencv::core::Vector::new();
-    let mut reject_levels = opencv::core::Vector::new();
-    let mut level_weights = opencv::core::Vector::new();
-    cascade.detect_multi_scale3(
-        frame, // color image here
-        &mut objects,
-        &mut reject_levels,
-        &mut level_weights,
-        1.1,
-        3,
-        objdetect::CASCADE_SCALE_IMAGE,
-        opencv::core::Size::new(30, 30),
-        opencv::core::Size::new(0, 0),
-        true,
-    )?;
-
     // Draw green rectangles around found objects
-    for i in 0..objects.len() {
-        if level_weights.get(i).is_ok_and(|i| i >= min_weight) {
-            if let Ok(object) = objects.get(i) {
-                imgproc::rectangle(
-                    frame,
-                    object,
-                    Scalar::new(0.0, 255.0, 0.0, 0.0), // BGR green
-                    2,
-                    imgproc::LINE_8,
-                    0,
-                )
-                .unwrap();
-            }
-        }
+    for detection in detections {
+        imgproc::rectangle(
+            frame,
+            detection.rect(),
+            Scalar::new(0.0, 255.0, 0.0, 0.0), // BGR green
+            2,
+            imgproc::LINE_8,
+            0,
+        )?;
     }
     Ok(())
 }
@@ -0,0 +1,164 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use tiny_http::Server;
+use tokio::select;
+use zcam::{
+    mjpeg::{Gateway, LatestFrame, RateLimiter},
+    params::{params_loop, Params, StageParams},
+    BadFrames, FrameMeta,
+};
+use zenoh::{config::Config, Session};
+
+const STAGE: &str = "zhttp";
+
+/// Delay after which a stream sends its last frame again, to notice the clients that went away.
+const KEEPALIVE: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let (config, key_sub, listen, max_fps, max_streams) = parse_args();
+
+    let params = Params::new(HttpParams { max_fps }).expect("Invalid parameters");
+
+    // Start the HTTP server
+    let gateway = Arc::new(Gateway::new(max_streams, KEEPALIVE));
+    let server = Server::http(&listen).expect("Unable to start the HTTP server");
+    println!("Serving {key_sub} on http://{listen}/");
+    let server_gateway = gateway.clone();
+    std::thread::spawn(move || server_gateway.serve(server));
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, key_sub.clone(), gateway.latest(), params.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the Jpeg frames to serve (e.g. the output of `zencode`)
+    #[arg(short, long, default_value = "demo/zcam/encoded")]
+    key: String,
+
+    /// Address the HTTP server listens on
+    #[arg(short, long, default_value = "0.0.0.0:8080")]
+    listen: String,
+
+    /// Maximum rate of the frames served, to save the bandwidth of the clients (0 for no limit)
+    #[arg(long, default_value = "0")]
+    max_fps: f64,
+
+    /// Maximum number of streams served at once, further clients being answered with 503
+    #[arg(long, default_value = "16")]
+    max_streams: usize,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+}
+
+fn parse_args() -> (Config, String, String, f64, usize) {
+    let args = Args::parse();
+    let mut c = if let Some(f) = args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    (c, args.key, args.listen, args.max_fps, args.max_streams)
+}
+
+/// Runtime parameters of `zhttp`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct HttpParams {
+    /// Maximum rate of the frames served (0 for no limit)
+    max_fps: f64,
+}
+
+impl StageParams for HttpParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1000.0).contains(&self.max_fps) {
+            return Err("max_fps must be between 0 and 1000".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Processing loop that subscribes to Jpeg frames and makes the latest one available to the HTTP clients.
+/// Jpeg payloads are served as they are, without being decoded.
+/// Frames received less than `1 / max_fps` after the last one served are skipped.
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    latest: &LatestFrame,
+    params: Params<HttpParams>,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    let mut bad_frames = BadFrames::default();
+    let mut rate_limiter = RateLimiter::default();
+
+    loop {
+        // Receive sample with frame
+        let sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        match meta {
+            FrameMeta::Jpeg(_) => {
+                if rate_limiter.allow(params.get().max_fps, Instant::now()) {
+                    latest.set(sample.payload().to_bytes().to_vec())
+                }
+            }
+            other_meta => tracing::error!("Unsupported frame meta: {:?}", other_meta),
+        }
+    }
+}
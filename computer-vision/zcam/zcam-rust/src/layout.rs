@@ -0,0 +1,175 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Layout and statistics of the streams shown by `zdisplay`.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use opencv::core::{Rect, Size};
+
+use crate::params::StageParams;
+
+/// Runtime parameters of `zdisplay`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct DisplayParams {
+    /// Rate at which the display is refreshed, or frames are written.
+    /// A video being written keeps the rate it was opened with.
+    pub fps: f64,
+}
+
+impl StageParams for DisplayParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.1..=1000.0).contains(&self.fps) {
+            return Err("fps must be between 0.1 and 1000".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Parses a size given as `<width>x<height>`.
+pub fn parse_size(s: &str) -> Option<Size> {
+    let (width, height) = s.split_once('x')?;
+    let size = Size::new(width.parse().ok()?, height.parse().ok()?);
+    (size.width > 0 && size.height > 0).then_some(size)
+}
+
+/// Rate of the frames received over the last second.
+#[derive(Debug, Default)]
+pub struct FrameRate {
+    // Reception time of the frames of the last second
+    received: VecDeque<Instant>,
+}
+
+impl FrameRate {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    /// Records a frame received at `now`.
+    pub fn record(&mut self, now: Instant) {
+        self.received.push_back(now);
+        while self
+            .received
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) > Self::WINDOW)
+        {
+            self.received.pop_front();
+        }
+    }
+
+    /// Number of frames received during the second before `now`.
+    pub fn fps(&self, now: Instant) -> usize {
+        self.received
+            .iter()
+            .filter(|t| now.saturating_duration_since(**t) <= Self::WINDOW)
+            .count()
+    }
+}
+
+/// Grid of tiles, as square as possible, filled row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    pub rows: i32,
+    pub columns: i32,
+    pub tile: Size,
+}
+
+impl Grid {
+    /// Returns the smallest grid of `tile` sized cells holding `count` of them.
+    pub fn new(count: usize, tile: Size) -> Self {
+        let columns = ((count as f64).sqrt().ceil() as i32).max(1);
+        let rows = (count as i32 + columns - 1) / columns;
+        Self {
+            rows,
+            columns,
+            tile,
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.columns * self.tile.width, self.rows * self.tile.height)
+    }
+
+    /// Region of the `i`th cell.
+    pub fn cell(&self, i: usize) -> Rect {
+        let i = i as i32;
+        Rect::new(
+            (i % self.columns) * self.tile.width,
+            (i / self.columns) * self.tile.height,
+            self.tile.width,
+            self.tile.height,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_positive() {
+        assert_eq!(parse_size("320x240"), Some(Size::new(320, 240)));
+        for size in ["320", "320x", "x240", "0x240", "320x-1", "axb"] {
+            assert_eq!(parse_size(size), None, "{} was accepted", size);
+        }
+    }
+
+    #[test]
+    fn frame_rate_counts_the_last_second() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut rate = FrameRate::default();
+        assert_eq!(rate.fps(start), 0);
+        for ms in [0, 400, 800, 1200] {
+            rate.record(at(ms));
+        }
+        assert_eq!(rate.fps(at(1200)), 3);
+        assert_eq!(rate.fps(at(2100)), 1);
+        assert_eq!(rate.fps(at(3000)), 0);
+    }
+
+    #[test]
+    fn grid_is_as_square_as_possible() {
+        let tile = Size::new(320, 240);
+        let shape = |count| {
+            let grid = Grid::new(count, tile);
+            (grid.rows, grid.columns)
+        };
+        assert_eq!(shape(0), (0, 1));
+        assert_eq!(shape(1), (1, 1));
+        assert_eq!(shape(2), (1, 2));
+        assert_eq!(shape(3), (2, 2));
+        assert_eq!(shape(5), (2, 3));
+        assert_eq!(shape(9), (3, 3));
+        assert_eq!(shape(10), (3, 4));
+    }
+
+    #[test]
+    fn grid_is_filled_row_by_row() {
+        let grid = Grid::new(5, Size::new(320, 240));
+        assert_eq!(grid.size(), Size::new(960, 480));
+        assert_eq!(grid.cell(0), Rect::new(0, 0, 320, 240));
+        assert_eq!(grid.cell(2), Rect::new(640, 0, 320, 240));
+        assert_eq!(grid.cell(4), Rect::new(320, 240, 320, 240));
+    }
+
+    #[test]
+    fn display_fps_is_bounded() {
+        assert!(DisplayParams { fps: 0.1 }.validate().is_ok());
+        assert!(DisplayParams { fps: 1000.0 }.validate().is_ok());
+        assert!(DisplayParams { fps: 0.0 }.validate().is_err());
+        assert!(DisplayParams { fps: f64::NAN }.validate().is_err());
+    }
+}
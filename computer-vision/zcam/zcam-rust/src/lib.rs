@@ -12,37 +12,409 @@
 //   The Zenoh Team, <zenoh@zettascale.tech>
 //
 
-use std::{convert::TryInto, fmt::Display};
+use std::{
+    convert::TryInto,
+    fmt::Display,
+    io::Read,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use futures::StreamExt;
-use opencv::core::{Mat, MatTraitConst, ToInputArray};
+use opencv::{
+    core::{Mat, MatTraitConst, ToInputArray, CV_8UC1, CV_8UC2, CV_8UC3},
+    imgproc,
+};
 use rkyv::{Archive, Deserialize, Serialize};
-use zenoh::{bytes::ZBytes, key_expr::KeyExpr, sample::Sample, Session};
+use zenoh::{
+    bytes::ZBytes,
+    key_expr::KeyExpr,
+    sample::Sample,
+    shm::{zshm, zshmmut, ZShm},
+    Session,
+};
 
-#[derive(Archive, Deserialize, Serialize, Debug, Clone)]
+pub mod detection;
+pub mod group;
+pub mod layout;
+pub mod masking;
+pub mod mjpeg;
+pub mod params;
+pub mod processor;
+pub mod rate_control;
+pub mod recording;
+pub mod rendition;
+pub mod segment;
+pub mod shm;
+pub mod source;
+pub mod trace;
+pub mod tracking;
+pub mod transform;
+pub mod view;
+
+/// Returns the current wall-clock time in microseconds since the UNIX epoch.
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Record of a frame going through a processing stage.
+#[derive(Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Hop {
+    /// Name of the stage (e.g. "zencode")
+    pub stage: String,
+    /// Time at which the stage published the frame, in microseconds since the UNIX epoch
+    pub timestamp: u64,
+}
+
+/// Capture information of a frame and the list of stages it went through since.
+#[derive(
+    Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone, Default,
+)]
+pub struct FrameTrace {
+    /// Sequence number of the frame, incremented by one for each captured frame
+    pub seq: u64,
+    /// Time at which the frame was captured, in microseconds since the UNIX epoch
+    pub capture_timestamp: u64,
+    pub hops: Vec<Hop>,
+}
+
+/// Layout of the pixels of a raw frame.
+#[derive(
+    Archive,
+    Deserialize,
+    Serialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PixelFormat {
+    /// 3 bytes per pixel, blue first (OpenCV's default)
+    Bgr,
+    /// 3 bytes per pixel, red first
+    Rgb,
+    /// 1 byte per pixel
+    Gray,
+    /// Packed YUV 4:2:2, 2 bytes per pixel (Y0 U Y1 V)
+    Yuyv,
+    /// Planar YUV 4:2:0: a plane of Y bytes followed by a plane of interleaved U and V bytes at half resolution
+    Nv12,
+}
+
+impl PixelFormat {
+    /// Format of the OpenCV frames of type `typ`, if supported.
+    pub fn from_typ(typ: i32) -> Option<Self> {
+        match typ {
+            CV_8UC3 => Some(PixelFormat::Bgr),
+            CV_8UC1 => Some(PixelFormat::Gray),
+            _ => None,
+        }
+    }
+
+    /// OpenCV type of the Mat mapping a frame of this format.
+    pub fn typ(self) -> i32 {
+        match self {
+            PixelFormat::Bgr | PixelFormat::Rgb => CV_8UC3,
+            PixelFormat::Gray | PixelFormat::Nv12 => CV_8UC1,
+            PixelFormat::Yuyv => CV_8UC2,
+        }
+    }
+
+    /// Size of a pixel (of the Y plane for NV12), in bytes.
+    pub fn pixel_size(self) -> usize {
+        match self {
+            PixelFormat::Bgr | PixelFormat::Rgb => 3,
+            PixelFormat::Gray | PixelFormat::Nv12 => 1,
+            PixelFormat::Yuyv => 2,
+        }
+    }
+
+    /// Number of rows of the Mat mapping a frame of `rows` rows, the UV plane of NV12 frames being below the Y plane.
+    pub fn mat_rows(self, rows: i32) -> i32 {
+        match self {
+            PixelFormat::Nv12 => rows * 3 / 2,
+            _ => rows,
+        }
+    }
+
+    pub fn is_yuv(self) -> bool {
+        matches!(self, PixelFormat::Yuyv | PixelFormat::Nv12)
+    }
+
+    /// OpenCV color conversion code from this format to BGR, `None` for BGR.
+    pub fn bgr_conversion(self) -> Option<i32> {
+        match self {
+            PixelFormat::Bgr => None,
+            PixelFormat::Rgb => Some(imgproc::COLOR_RGB2BGR),
+            PixelFormat::Gray => Some(imgproc::COLOR_GRAY2BGR),
+            PixelFormat::Yuyv => Some(imgproc::COLOR_YUV2BGR_YUYV),
+            PixelFormat::Nv12 => Some(imgproc::COLOR_YUV2BGR_NV12),
+        }
+    }
+
+    /// Color space of the frames of this format, unless told otherwise.
+    pub fn default_color_space(self) -> ColorSpace {
+        if self.is_yuv() {
+            ColorSpace::Bt601
+        } else {
+            ColorSpace::Srgb
+        }
+    }
+}
+
+impl Display for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PixelFormat::Bgr => "BGR",
+            PixelFormat::Rgb => "RGB",
+            PixelFormat::Gray => "GRAY",
+            PixelFormat::Yuyv => "YUYV",
+            PixelFormat::Nv12 => "NV12",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Color space of the pixels of a raw frame. OpenCV conversions from YUV assume BT.601.
+#[derive(
+    Archive,
+    Deserialize,
+    Serialize,
+    serde::Serialize,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Bt601,
+    Bt709,
+}
+
+/// Maximum width and height of the frames.
+const MAX_DIMENSION: i32 = 16384;
+
+/// Count of the frames a stage skipped because of malformed metadata or payload.
+#[derive(Debug, Default)]
+pub struct BadFrames {
+    count: u64,
+}
+
+impl BadFrames {
+    /// Records that a frame is skipped because of `error`.
+    pub fn skip(&mut self, error: impl Display) {
+        self.count += 1;
+        tracing::warn!("Skipping bad frame ({} so far): {error}", self.count);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+/// Layout of a raw frame: size, pixel format and row stride, along with its trace.
+///
+/// Producers that do not use rkyv (e.g. `zcam-python`) can give it as a JSON attachment,
+/// see [`FrameMeta::decode`].
+#[derive(Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct RawFrameMeta {
     rows: i32,
     cols: i32,
-    typ: i32,
+    format: PixelFormat,
+    #[serde(default)]
+    color_space: ColorSpace,
+    /// Number of bytes between the start of two rows, possibly larger than a row of pixels
+    stride: usize,
     size: usize,
+    #[serde(default)]
+    trace: FrameTrace,
+    /// CRC32 of the payload, checked by [`FrameMeta::decode`] when given
+    #[serde(default)]
+    checksum: Option<u32>,
 }
 
 impl RawFrameMeta {
+    /// Returns the metadata of frames with the size and type of `frame` (BGR or GRAY), with packed rows.
     pub fn new(frame: &Mat) -> zenoh::Result<Self> {
-        Ok(Self {
-            rows: frame.rows(),
-            cols: frame.cols(),
-            typ: frame.typ(),
-            size: frame.size()?.area() as usize * frame.elem_size()?,
-        })
+        let format = PixelFormat::from_typ(frame.typ())
+            .ok_or_else(|| format!("Unsupported frame type {}", frame.typ()))?;
+        Ok(Self::with_format(frame.rows(), frame.cols(), format))
+    }
+
+    /// Returns the metadata of `rows`x`cols` frames of `format`, with packed rows.
+    pub fn with_format(rows: i32, cols: i32, format: PixelFormat) -> Self {
+        let stride = cols.max(0) as usize * format.pixel_size();
+        Self {
+            rows,
+            cols,
+            format,
+            color_space: format.default_color_space(),
+            stride,
+            size: stride * format.mat_rows(rows).max(0) as usize,
+            trace: FrameTrace::default(),
+            checksum: None,
+        }
+    }
+
+    /// Sets the number of bytes between the start of two rows, for producers padding their rows.
+    pub fn with_stride(mut self, stride: usize) -> zenoh::Result<Self> {
+        if stride < self.cols.max(0) as usize * self.format.pixel_size() {
+            return Err(format!("Stride {stride} is shorter than a row of {self}").into());
+        }
+        self.stride = stride;
+        self.size = stride * self.format.mat_rows(self.rows).max(0) as usize;
+        Ok(self)
+    }
+
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Returns the metadata of this frame resized to `rows`x`cols`, with packed rows, keeping its trace.
+    /// Only meaningful for formats that are not YUV.
+    pub fn with_size(&self, rows: i32, cols: i32) -> Self {
+        let mut meta =
+            Self::with_format(rows, cols, self.format).with_color_space(self.color_space);
+        meta.trace = self.trace.clone();
+        meta
+    }
+
+    /// Returns the metadata of `frame`, keeping the trace of this frame.
+    /// Used by stages that change the size or type of the frames they process.
+    pub fn with_layout_of(&self, frame: &Mat) -> zenoh::Result<Self> {
+        let mut meta = Self::new(frame)?;
+        // Keep the channel order and color space of the frames (e.g. RGB) if their type did not change
+        if !self.format.is_yuv() && self.format.typ() == frame.typ() {
+            meta.format = self.format;
+            meta.color_space = self.color_space;
+        }
+        meta.trace = self.trace.clone();
+        Ok(meta)
+    }
+
+    pub fn trace(&self) -> &FrameTrace {
+        &self.trace
+    }
+
+    /// Stamps a freshly captured frame, resetting its hops.
+    pub fn set_capture(&mut self, seq: u64, capture_timestamp: u64) {
+        self.trace = FrameTrace {
+            seq,
+            capture_timestamp,
+            hops: Vec::new(),
+        };
+    }
+
+    /// Records that the frame is being published by `stage`, dropping the checksum of the payload
+    /// that the stage may have changed.
+    pub fn add_hop(&mut self, stage: &str) {
+        self.checksum = None;
+        self.trace.hops.push(Hop {
+            stage: stage.to_string(),
+            timestamp: now_us(),
+        });
+    }
+
+    pub fn rows(&self) -> i32 {
+        self.rows
+    }
+
+    pub fn cols(&self) -> i32 {
+        self.cols
     }
 
     pub fn size(&self) -> usize {
         self.size
     }
 
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn checksum(&self) -> Option<u32> {
+        self.checksum
+    }
+
+    /// Sets the checksum of the frame to the one of `payload`, or removes it.
+    pub fn set_checksum(&mut self, payload: Option<&[u8]>) {
+        self.checksum = payload.map(crc32fast::hash);
+    }
+
+    /// Checks that the layout is consistent, so that frames can be mapped safely from payloads of `size` bytes.
+    pub fn validate(&self) -> zenoh::Result<()> {
+        if !(1..=MAX_DIMENSION).contains(&self.rows) || !(1..=MAX_DIMENSION).contains(&self.cols) {
+            return Err(format!("Invalid frame size {}x{}", self.cols, self.rows).into());
+        }
+        let odd_rows = self.format == PixelFormat::Nv12 && self.rows % 2 != 0;
+        let odd_cols = self.format.is_yuv() && self.cols % 2 != 0;
+        if odd_rows || odd_cols {
+            return Err(format!(
+                "Odd frame size {}x{} for {}",
+                self.cols, self.rows, self.format
+            )
+            .into());
+        }
+        if self.stride < self.cols as usize * self.format.pixel_size() {
+            return Err(format!("Stride {} is shorter than a row of {self}", self.stride).into());
+        }
+        let size = self
+            .stride
+            .checked_mul(self.format.mat_rows(self.rows) as usize);
+        if size != Some(self.size) {
+            return Err(format!("Size {} does not match the layout of {self}", self.size).into());
+        }
+        Ok(())
+    }
+
+    /// Whether the rows are packed, without padding between them.
+    pub fn is_packed(&self) -> bool {
+        self.stride == self.cols.max(0) as usize * self.format.pixel_size()
+    }
+
+    /// Converts `frame`, mapped with this metadata, into a new BGR frame with packed rows.
+    pub fn to_bgr(&self, frame: &(impl MatTraitConst + ToInputArray)) -> opencv::Result<Mat> {
+        match self.format.bgr_conversion() {
+            None => frame.try_clone(),
+            Some(code) => {
+                let mut bgr = Mat::default();
+                imgproc::cvt_color_def(frame, &mut bgr, code)?;
+                Ok(bgr)
+            }
+        }
+    }
+
+    /// Returns the metadata of `bgr`, the conversion of this frame by [`to_bgr`](Self::to_bgr), keeping the
+    /// trace of this frame. Unlike [`with_layout_of`](Self::with_layout_of), the channel order of this frame
+    /// is not kept, as `bgr` is always BGR.
+    pub fn to_bgr_meta(&self, bgr: &Mat) -> zenoh::Result<Self> {
+        let mut meta = Self::new(bgr)?;
+        meta.trace = self.trace.clone();
+        Ok(meta)
+    }
+
     /// # Safety
-    /// 
+    ///
     /// The caller must ensure that:
     /// - the data pointer is valid and points to a buffer of the correct size
     /// - the data buffer is not modified while the Mat is in use
@@ -52,7 +424,7 @@ impl RawFrameMeta {
     }
 
     /// # Safety
-    /// 
+    ///
     /// The caller must ensure that:
     /// - the data pointer is valid and points to a buffer of the correct size
     /// - the data buffer is not modified while the Mat is in use
@@ -63,55 +435,215 @@ impl RawFrameMeta {
 
     unsafe fn _mat(&self, data: *mut u8) -> Mat {
         unsafe {
-            Mat::new_rows_cols_with_data_unsafe_def(
-                self.rows,
+            Mat::new_rows_cols_with_data_unsafe(
+                self.format.mat_rows(self.rows),
                 self.cols,
-                self.typ,
+                self.format.typ(),
                 data as *mut std::ffi::c_void,
+                self.stride,
             )
             .unwrap()
         }
     }
 }
 
-#[derive(Archive, Deserialize, Serialize, Debug)]
+impl Display for RawFrameMeta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "seq: {}, rows: {}, cols: {}, format: {}, stride: {}",
+            self.trace.seq, self.rows, self.cols, self.format, self.stride
+        )
+    }
+}
+
+/// Metadata of a frame encoded with an inter-frame codec.
+#[derive(Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct VideoFrameMeta {
+    /// Layout of the decoded frame
+    pub raw: RawFrameMeta,
+    /// Whether the frame can be decoded without any previous frame
+    pub keyframe: bool,
+}
+
+#[derive(Archive, Deserialize, Serialize, serde::Serialize, serde::Deserialize, Debug)]
 pub enum FrameMeta {
     Raw(RawFrameMeta),
     Jpeg(RawFrameMeta),
+    H264(VideoFrameMeta),
 }
 
 impl FrameMeta {
+    /// Decodes the metadata attached to `sample` with [`FrameMeta::from_attachment`], and checks it against
+    /// the payload of the sample, so that frames can be mapped safely from it.
     pub fn decode(sample: &Sample) -> zenoh::Result<Self> {
         let attachment = sample.attachment().ok_or("Missing attachment")?;
-        let attachment_bytes = attachment.to_bytes();
-        let meta = rkyv::access::<ArchivedFrameMeta, rkyv::rancor::Error>(&attachment_bytes)?;
-        let meta = rkyv::deserialize::<FrameMeta, rkyv::rancor::Error>(meta)?;
+        let meta = Self::from_attachment(&attachment.to_bytes())?;
+        meta.check_payload(&sample.payload().to_bytes())?;
         Ok(meta)
     }
 
+    /// Decodes and validates metadata encoded with rkyv by [`FrameMeta::encode`], or as JSON
+    /// (e.g. `{"Raw": {"rows": 480, "cols": 640, "format": "BGR", "stride": 1920, "size": 921600}}`)
+    /// by producers that do not use rkyv.
+    pub fn from_attachment(attachment: &[u8]) -> zenoh::Result<Self> {
+        let json = match attachment.first() {
+            Some(b'{') => serde_json::from_slice::<FrameMeta>(attachment).ok(),
+            _ => None,
+        };
+        let meta = match json {
+            Some(meta) => meta,
+            None => {
+                let meta = rkyv::access::<ArchivedFrameMeta, rkyv::rancor::Error>(attachment)?;
+                rkyv::deserialize::<FrameMeta, rkyv::rancor::Error>(meta)?
+            }
+        };
+        meta.validate()?;
+        Ok(meta)
+    }
+
+    /// Checks that the layout is consistent. Encoded frames are decoded as BGR frames with packed rows.
+    pub fn validate(&self) -> zenoh::Result<()> {
+        let raw = self.raw();
+        raw.validate()?;
+        if !matches!(self, FrameMeta::Raw(_))
+            && (raw.format != PixelFormat::Bgr || !raw.is_packed())
+        {
+            return Err(
+                format!("Encoded frames must decode to packed BGR frames, not {raw}").into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that `payload` holds the frame: the whole frame for raw frames, and matches the checksum if any.
+    pub fn check_payload(&self, payload: &[u8]) -> zenoh::Result<()> {
+        let raw = self.raw();
+        match self {
+            FrameMeta::Raw(_) if payload.len() != raw.size => {
+                return Err(format!(
+                    "Payload of {} bytes does not match the {} bytes of {raw}",
+                    payload.len(),
+                    raw.size
+                )
+                .into());
+            }
+            _ if payload.is_empty() => return Err("Empty payload".into()),
+            _ => {}
+        }
+        match raw.checksum {
+            Some(checksum) if checksum != crc32fast::hash(payload) => {
+                Err(format!("Checksum mismatch for {raw}").into())
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub fn encode(&self) -> zenoh::Result<ZBytes> {
         let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self)?;
         Ok(bytes.as_slice().into())
     }
+
+    /// Layout and trace of the frame, whatever its encoding.
+    pub fn raw(&self) -> &RawFrameMeta {
+        match self {
+            FrameMeta::Raw(meta) | FrameMeta::Jpeg(meta) => meta,
+            FrameMeta::H264(meta) => &meta.raw,
+        }
+    }
+
+    pub fn raw_mut(&mut self) -> &mut RawFrameMeta {
+        match self {
+            FrameMeta::Raw(meta) | FrameMeta::Jpeg(meta) => meta,
+            FrameMeta::H264(meta) => &mut meta.raw,
+        }
+    }
 }
 
 impl Display for FrameMeta {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            FrameMeta::Raw(meta) => write!(
-                f,
-                "FrameMeta::Raw {{ rows: {}, cols: {}, typ: {} }}",
-                meta.rows, meta.cols, meta.typ
-            ),
-            FrameMeta::Jpeg(meta) => write!(
+            FrameMeta::Raw(meta) => write!(f, "FrameMeta::Raw {{ {meta} }}"),
+            FrameMeta::Jpeg(meta) => write!(f, "FrameMeta::Jpeg {{ {meta} }}"),
+            FrameMeta::H264(meta) => write!(
                 f,
-                "FrameMeta::Jpeg {{ rows: {}, cols: {}, typ: {} }}",
-                meta.rows, meta.cols, meta.typ
+                "FrameMeta::H264 {{ {}, keyframe: {} }}",
+                meta.raw, meta.keyframe
             ),
         }
     }
 }
 
+/// Processes a raw frame in SHM, and returns the processed frame as SHM along with the result of `process`.
+///
+/// Frames are processed in-place directly in SHM without copying whenever possible, leveraging Zenoh's zero-copy accessors
+/// and metadata information to map OpenCV Mats into SHM buffers. If in-place processing is not possible for any reason
+/// (e.g. the payload is not SHM, or is shared with other subscribers), the frame is copied into a new SHM buffer of `shm_pool`
+/// for processing. When that copy is dropped by the [`ShmPolicy::DropNewest`](shm::ShmPolicy::DropNewest) policy, an error is returned.
+pub async fn process_in_shm<R>(
+    sample: &mut Sample,
+    raw_meta: &RawFrameMeta,
+    shm_pool: &shm::ShmPool,
+    process: impl FnOnce(&mut Mat) -> zenoh::Result<R>,
+) -> zenoh::Result<(ZShm, R)> {
+    fn try_mutate_shm_inplace(sample: &mut Sample) -> Option<&mut zshmmut> {
+        // Try to interpret the payload as SHM buffer
+        let shm_buf = sample.payload_mut().as_shm_mut()?;
+
+        // Try to get mutable access to the SHM buffer
+        shm_buf.try_into().ok()
+    }
+
+    // First, try to process the frame in-place without copying if the payload is already an SHM buffer
+    // and if we can get mutable access to it. This is the most efficient path as it avoids any copying.
+    match try_mutate_shm_inplace(sample) {
+        Some(shm_mut_inplace) => {
+            // Map opencv Mat into shared memory
+            let mut frame = unsafe { raw_meta.mat_mut(shm_mut_inplace.as_mut_ptr()) };
+
+            // Process the frame in-place directly on the SHM buffer
+            let result = process(&mut frame)?;
+
+            let shm_immut: &mut zshm = shm_mut_inplace.into();
+            shm_pool.record_processing(true);
+
+            // Return the processed frame as SHM without copying
+            Ok((shm_immut.to_owned(), result))
+        }
+        None => {
+            // If any of the in-place SHM mutation steps fail, fall back to copying the data into a new SHM buffer
+            tracing::debug!("SHM inplace failed, falling back to copy...");
+
+            let payload = sample.payload();
+
+            // Allocate SHM buffer for contiguous payload bytes
+            let mut shmbuf = shm_pool
+                .alloc(payload.len())
+                .await?
+                .ok_or("SHM pool is full, frame dropped")?;
+            shm_pool.record_processing(false);
+
+            // Read bytes directly into SHM buffer
+            payload.reader().read_exact(&mut shmbuf)?;
+
+            // Map opencv Mat into allocated shared memory
+            let mut frame = unsafe { raw_meta.mat_mut(shmbuf.as_mut_ptr()) };
+
+            // Process the frame directly on the SHM buffer
+            let result = process(&mut frame)?;
+
+            // Return the processed frame as SHM
+            Ok((shmbuf.into(), result))
+        }
+    }
+}
+
+/// Key expression of the queryable that forces the encoder publishing on `key_pub` to emit a keyframe.
+/// Decoders joining an inter-frame encoded stream query it instead of waiting for the next periodic keyframe.
+pub fn keyframe_request_keyexpr(key_pub: &str) -> String {
+    format!("{key_pub}/keyframe")
+}
+
 pub async fn config_update_loop<'a, TryIntoKeyExpr>(
     session: &Session,
     config_keyexpr: TryIntoKeyExpr,
@@ -133,3 +665,162 @@ pub async fn config_update_loop<'a, TryIntoKeyExpr>(
         })
         .await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_consistent_layouts() {
+        let bgr = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        assert!(bgr.validate().is_ok());
+        let padded = bgr.clone().with_stride(2048).unwrap();
+        assert!(padded.validate().is_ok());
+        assert_eq!(padded.size(), 2048 * 480);
+        let nv12 = RawFrameMeta::with_format(480, 640, PixelFormat::Nv12);
+        assert!(nv12.validate().is_ok());
+        assert_eq!(nv12.size(), 640 * 720);
+        let yuyv = RawFrameMeta::with_format(480, 640, PixelFormat::Yuyv);
+        assert!(yuyv.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_layouts() {
+        for (rows, cols) in [(0, 640), (480, 0), (-1, 640), (MAX_DIMENSION + 1, 640)] {
+            let meta = RawFrameMeta::with_format(rows, cols, PixelFormat::Bgr);
+            assert!(meta.validate().is_err(), "{}x{} was accepted", cols, rows);
+        }
+        assert!(RawFrameMeta::with_format(479, 640, PixelFormat::Nv12)
+            .validate()
+            .is_err());
+        assert!(RawFrameMeta::with_format(480, 639, PixelFormat::Yuyv)
+            .validate()
+            .is_err());
+
+        let mut meta = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        meta.stride = 640;
+        assert!(meta.validate().is_err(), "short stride was accepted");
+
+        let mut meta = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        meta.size -= 1;
+        assert!(meta.validate().is_err(), "short size was accepted");
+
+        // The size of the layout would overflow
+        let mut meta = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        meta.stride = usize::MAX;
+        meta.size = 0;
+        assert!(meta.validate().is_err(), "overflowing stride was accepted");
+    }
+
+    #[test]
+    fn validate_requires_packed_bgr_for_encoded_frames() {
+        let bgr = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        let rgb = RawFrameMeta::with_format(480, 640, PixelFormat::Rgb);
+        let padded = bgr.clone().with_stride(2048).unwrap();
+        assert!(FrameMeta::Raw(rgb.clone()).validate().is_ok());
+        assert!(FrameMeta::Raw(padded.clone()).validate().is_ok());
+        assert!(FrameMeta::Jpeg(bgr.clone()).validate().is_ok());
+        assert!(FrameMeta::Jpeg(rgb).validate().is_err());
+        assert!(FrameMeta::Jpeg(padded.clone()).validate().is_err());
+        let h264 = |raw| {
+            FrameMeta::H264(VideoFrameMeta {
+                raw,
+                keyframe: true,
+            })
+        };
+        assert!(h264(bgr).validate().is_ok());
+        assert!(h264(padded).validate().is_err());
+    }
+
+    #[test]
+    fn check_payload_matches_size_and_checksum() {
+        let mut raw = RawFrameMeta::with_format(2, 2, PixelFormat::Gray);
+        let payload = [1u8, 2, 3, 4];
+        assert!(FrameMeta::Raw(raw.clone()).check_payload(&payload).is_ok());
+        assert!(FrameMeta::Raw(raw.clone())
+            .check_payload(&payload[..3])
+            .is_err());
+        assert!(FrameMeta::Raw(raw.clone())
+            .check_payload(&[0u8; 5])
+            .is_err());
+
+        raw.set_checksum(Some(&payload));
+        assert!(FrameMeta::Raw(raw.clone()).check_payload(&payload).is_ok());
+        assert!(FrameMeta::Raw(raw.clone())
+            .check_payload(&[1u8, 2, 3, 5])
+            .is_err());
+        raw.set_checksum(None);
+        assert!(FrameMeta::Raw(raw).check_payload(&[1u8, 2, 3, 5]).is_ok());
+    }
+
+    #[test]
+    fn check_payload_accepts_any_encoded_size() {
+        let mut raw = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        let jpeg = [0xffu8, 0xd8, 0xff];
+        assert!(FrameMeta::Jpeg(raw.clone()).check_payload(&jpeg).is_ok());
+        assert!(FrameMeta::Jpeg(raw.clone()).check_payload(&[]).is_err());
+
+        raw.set_checksum(Some(&jpeg));
+        assert!(FrameMeta::Jpeg(raw.clone()).check_payload(&jpeg).is_ok());
+        assert!(FrameMeta::Jpeg(raw).check_payload(&jpeg[..2]).is_err());
+    }
+
+    #[test]
+    fn hops_are_recorded_after_the_capture() {
+        let payload = [1u8, 2, 3, 4];
+        let mut raw = RawFrameMeta::with_format(2, 2, PixelFormat::Gray);
+        raw.add_hop("zstale");
+        raw.set_capture(42, 1_000);
+        assert_eq!(raw.trace().seq, 42);
+        assert_eq!(raw.trace().capture_timestamp, 1_000);
+        assert!(raw.trace().hops.is_empty());
+
+        raw.set_checksum(Some(&payload));
+        let before = now_us();
+        raw.add_hop("zencode");
+        raw.add_hop("zdecode");
+        let stages = raw
+            .trace()
+            .hops
+            .iter()
+            .map(|hop| hop.stage.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(stages, ["zencode", "zdecode"]);
+        assert!(raw.trace().hops[0].timestamp >= before);
+        // The stage may have changed the payload
+        assert_eq!(raw.checksum(), None);
+    }
+
+    #[test]
+    fn trace_survives_encoding_and_defaults_in_json() {
+        let mut raw = RawFrameMeta::with_format(480, 640, PixelFormat::Bgr);
+        raw.set_capture(7, 1_000);
+        raw.add_hop("zencode");
+        let encoded = FrameMeta::Jpeg(raw).encode().unwrap().to_bytes().to_vec();
+        let decoded = FrameMeta::from_attachment(&encoded).unwrap();
+        let trace = decoded.raw().trace();
+        assert_eq!((trace.seq, trace.capture_timestamp), (7, 1_000));
+        assert_eq!(trace.hops.len(), 1);
+        assert_eq!(trace.hops[0].stage, "zencode");
+
+        let json = br#"{"Raw": {"rows": 480, "cols": 640, "format": "BGR", "stride": 1920, "size": 921600}}"#;
+        let meta = FrameMeta::from_attachment(json).unwrap();
+        assert_eq!(meta.raw().trace().seq, 0);
+        assert!(meta.raw().trace().hops.is_empty());
+    }
+
+    #[test]
+    fn from_attachment_validates_json_and_rkyv_metadata() {
+        let json = br#"{"Raw": {"rows": 480, "cols": 640, "format": "BGR", "stride": 1920, "size": 921600}}"#;
+        let meta = FrameMeta::from_attachment(json).unwrap();
+        assert_eq!(meta.raw().size(), 921600);
+        let invalid =
+            br#"{"Raw": {"rows": 480, "cols": 640, "format": "BGR", "stride": 1920, "size": 1}}"#;
+        assert!(FrameMeta::from_attachment(invalid).is_err());
+
+        let encoded = meta.encode().unwrap().to_bytes().to_vec();
+        let decoded = FrameMeta::from_attachment(&encoded).unwrap();
+        assert_eq!(decoded.raw().size(), 921600);
+        assert!(FrameMeta::from_attachment(&[]).is_err());
+    }
+}
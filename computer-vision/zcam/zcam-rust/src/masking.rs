@@ -0,0 +1,187 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Regions masked by `zprivacy`, and how they are obscured.
+
+use opencv::core::{Rect, Size};
+
+use crate::{detection::load_cascade, params::StageParams};
+
+/// How the masked regions are obscured.
+#[derive(
+    clap::ValueEnum, serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskMethod {
+    Blur,
+    Pixelate,
+}
+
+/// Parameters of `zprivacy` that can be changed while it runs.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PrivacyParams {
+    /// Cascade file of the regions to mask, reloaded when changed, empty to only mask the static masks
+    pub haarcascade_file: String,
+    /// Minimum weight of the detections to mask
+    pub min_weight: f64,
+    /// Margin added around the detected regions, as a fraction of their size
+    pub margin: f64,
+    pub method: MaskMethod,
+    /// Size of the blur kernel, or of the pixelation blocks, in pixels
+    pub strength: i32,
+    /// Static polygons to mask, each a list of `[x, y]` points
+    pub masks: Vec<Vec<[i32; 2]>>,
+}
+
+impl StageParams for PrivacyParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.min_weight < 0.0 || self.margin < 0.0 {
+            return Err("min_weight and margin must be positive".to_string());
+        }
+        if self.strength < 1 {
+            return Err("strength must be at least 1".to_string());
+        }
+        if self.masks.iter().any(|mask| mask.len() < 3) {
+            return Err("masks must have at least 3 points".to_string());
+        }
+        if self.haarcascade_file.is_empty() {
+            return Ok(());
+        }
+        load_cascade(&self.haarcascade_file).map(|_| ())
+    }
+}
+
+impl PrivacyParams {
+    /// Size of the blur kernel, odd as Gaussian kernels must be.
+    pub fn blur_kernel(&self) -> Size {
+        let kernel = self.strength | 1;
+        Size::new(kernel, kernel)
+    }
+
+    /// Size a region of `size` is scaled down to, so that each pixel becomes a block when scaled back up.
+    pub fn pixelated_size(&self, size: Size) -> Size {
+        Size::new(
+            (size.width / self.strength).max(1),
+            (size.height / self.strength).max(1),
+        )
+    }
+}
+
+/// Returns `rect` grown by `margin` of its size on each side.
+pub fn with_margin(rect: Rect, margin: f64) -> Rect {
+    let (dx, dy) = (
+        (rect.width as f64 * margin) as i32,
+        (rect.height as f64 * margin) as i32,
+    );
+    Rect::new(
+        rect.x - dx,
+        rect.y - dy,
+        rect.width + 2 * dx,
+        rect.height + 2 * dy,
+    )
+}
+
+/// Returns the part of `rect` inside a frame of `rows` x `cols`, if any.
+pub fn clip(rect: Rect, rows: i32, cols: i32) -> Option<Rect> {
+    let (x, y) = (rect.x.max(0), rect.y.max(0));
+    let width = (rect.x + rect.width).min(cols) - x;
+    let height = (rect.y + rect.height).min(rows) - y;
+    (width > 0 && height > 0).then(|| Rect::new(x, y, width, height))
+}
+
+/// Bounding box of the pixels of `polygon`.
+pub fn bounding_box(polygon: &[[i32; 2]]) -> Rect {
+    let xs = polygon.iter().map(|[x, _]| *x);
+    let ys = polygon.iter().map(|[_, y]| *y);
+    let (x, y) = (xs.clone().min().unwrap_or(0), ys.clone().min().unwrap_or(0));
+    let (right, bottom) = (xs.max().unwrap_or(-1), ys.max().unwrap_or(-1));
+    Rect::new(x, y, right - x + 1, bottom - y + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(strength: i32, masks: Vec<Vec<[i32; 2]>>) -> PrivacyParams {
+        PrivacyParams {
+            haarcascade_file: String::new(),
+            min_weight: 0.0,
+            margin: 0.2,
+            method: MaskMethod::Pixelate,
+            strength,
+            masks,
+        }
+    }
+
+    #[test]
+    fn detected_regions_are_grown_by_the_margin() {
+        assert_eq!(
+            with_margin(Rect::new(100, 50, 40, 60), 0.25),
+            Rect::new(90, 35, 60, 90)
+        );
+        assert_eq!(
+            with_margin(Rect::new(100, 50, 40, 60), 0.0),
+            Rect::new(100, 50, 40, 60)
+        );
+    }
+
+    #[test]
+    fn regions_are_clipped_to_the_frame() {
+        assert_eq!(
+            clip(Rect::new(-10, -5, 40, 20), 480, 640),
+            Some(Rect::new(0, 0, 30, 15))
+        );
+        assert_eq!(
+            clip(Rect::new(620, 470, 40, 20), 480, 640),
+            Some(Rect::new(620, 470, 20, 10))
+        );
+        assert_eq!(
+            clip(Rect::new(10, 10, 20, 20), 480, 640),
+            Some(Rect::new(10, 10, 20, 20))
+        );
+        assert_eq!(clip(Rect::new(640, 10, 20, 20), 480, 640), None);
+        assert_eq!(clip(Rect::new(-30, 10, 20, 20), 480, 640), None);
+    }
+
+    #[test]
+    fn polygons_are_bounded_by_their_pixels() {
+        assert_eq!(
+            bounding_box(&[[10, 20], [50, 25], [30, 60]]),
+            Rect::new(10, 20, 41, 41)
+        );
+        assert_eq!(bounding_box(&[[5, 5]]), Rect::new(5, 5, 1, 1));
+    }
+
+    #[test]
+    fn obscuring_follows_the_strength() {
+        assert_eq!(params(8, Vec::new()).blur_kernel(), Size::new(9, 9));
+        assert_eq!(params(9, Vec::new()).blur_kernel(), Size::new(9, 9));
+        let pixelate = params(16, Vec::new());
+        assert_eq!(pixelate.pixelated_size(Size::new(100, 40)), Size::new(6, 2));
+        assert_eq!(pixelate.pixelated_size(Size::new(10, 10)), Size::new(1, 1));
+    }
+
+    #[test]
+    fn privacy_params_are_checked() {
+        let triangle = vec![[0, 0], [10, 0], [0, 10]];
+        assert!(params(1, vec![triangle.clone()]).validate().is_ok());
+        assert!(params(0, Vec::new()).validate().is_err());
+        assert!(params(8, vec![triangle[..2].to_vec()]).validate().is_err());
+        let negative_margin = PrivacyParams {
+            margin: -0.1,
+            ..params(8, Vec::new())
+        };
+        assert!(negative_margin.validate().is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! MJPEG/HTTP gateway of `zhttp`, serving the latest Jpeg frame of a stream to browsers.
+
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tiny_http::{Header, Request, Response, Server};
+
+/// Boundary between the parts of the MJPEG stream.
+pub const BOUNDARY: &str = "zcamframe";
+
+/// Jpeg frame along with the number of frames received until it.
+type NumberedFrame = (u64, Arc<Vec<u8>>);
+
+/// Latest Jpeg frame received, shared between the subscriber and the HTTP clients.
+#[derive(Default)]
+pub struct LatestFrame {
+    // Number of frames received so far and last one
+    frame: Mutex<(u64, Option<Arc<Vec<u8>>>)>,
+    updated: Condvar,
+}
+
+impl LatestFrame {
+    pub fn set(&self, jpeg: Vec<u8>) {
+        let mut frame = self.frame.lock().unwrap();
+        frame.0 += 1;
+        frame.1 = Some(Arc::new(jpeg));
+        self.updated.notify_all();
+    }
+
+    pub fn get(&self) -> Option<Arc<Vec<u8>>> {
+        self.frame.lock().unwrap().1.clone()
+    }
+
+    /// Waits up to `timeout` for a frame newer than the `count`th one, and returns it along with the number of
+    /// frames received. On timeout, returns the latest frame again, if any.
+    fn wait_newer(&self, count: u64, timeout: Duration) -> Option<NumberedFrame> {
+        let (frame, _) = self
+            .updated
+            .wait_timeout_while(self.frame.lock().unwrap(), timeout, |frame| {
+                frame.0 <= count || frame.1.is_none()
+            })
+            .unwrap();
+        frame.1.clone().map(|jpeg| (frame.0, jpeg))
+    }
+}
+
+/// Limits the rate of the frames served.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    last_served: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Returns whether a frame received at `now` can be served at `max_fps` (0 for no limit), i.e. whether
+    /// it comes at least `1 / max_fps` after the last one served, and if so records it as served.
+    pub fn allow(&mut self, max_fps: f64, now: Instant) -> bool {
+        if max_fps > 0.0
+            && self.last_served.is_some_and(|last| {
+                now.saturating_duration_since(last).as_secs_f64() * max_fps < 1.0
+            })
+        {
+            return false;
+        }
+        self.last_served = Some(now);
+        true
+    }
+}
+
+/// Slot of a stream in the gateway, released on drop.
+struct StreamSlot(Arc<AtomicUsize>);
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Writes `jpeg` as a part of the MJPEG stream.
+fn write_part(writer: &mut impl Write, jpeg: &[u8]) -> std::io::Result<()> {
+    write!(
+        writer,
+        "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        jpeg.len()
+    )?;
+    writer.write_all(jpeg)?;
+    writer.write_all(b"\r\n")?;
+    // Each frame is sent right away
+    writer.flush()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// HTTP server of the latest frame, as a snapshot or as an MJPEG stream.
+pub struct Gateway {
+    latest: Arc<LatestFrame>,
+    max_streams: usize,
+    keepalive: Duration,
+    streams: Arc<AtomicUsize>,
+}
+
+impl Gateway {
+    /// Returns a gateway serving at most `max_streams` streams at once. Streams send the last frame again when
+    /// no new one was received for `keepalive`, which ends the streams of the clients that went away.
+    pub fn new(max_streams: usize, keepalive: Duration) -> Self {
+        Self {
+            latest: Arc::default(),
+            max_streams,
+            keepalive,
+            streams: Arc::default(),
+        }
+    }
+
+    /// The frame served, to be updated with the frames received.
+    pub fn latest(&self) -> &LatestFrame {
+        &self.latest
+    }
+
+    /// Number of streams being served.
+    pub fn streams(&self) -> usize {
+        self.streams.load(Ordering::SeqCst)
+    }
+
+    /// Serves the HTTP requests, each one in its own thread as streams never end:
+    /// - `/` is a page showing the stream
+    /// - `/stream` is the MJPEG stream
+    /// - `/snapshot` is the latest frame
+    pub fn serve(self: Arc<Self>, server: Server) {
+        for request in server.incoming_requests() {
+            let gateway = self.clone();
+            std::thread::spawn(move || gateway.respond(request));
+        }
+    }
+
+    fn respond(&self, request: Request) {
+        let path = request
+            .url()
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let result = match path.as_str() {
+            "/" => request.respond(
+                Response::from_string(
+                    "<html><body style=\"margin:0;background:black\">\
+                     <img src=\"/stream\" style=\"width:100%\"></body></html>",
+                )
+                .with_header(header("Content-Type", "text/html")),
+            ),
+            "/stream" => match self.open_stream() {
+                // The stream is released once its response ends
+                Ok((_slot, first)) => {
+                    let version = request.http_version().to_string();
+                    self.write_stream(request.into_writer(), &version, first)
+                }
+                Err(reason) => request.respond(Response::from_string(reason).with_status_code(503)),
+            },
+            "/snapshot" => match self.latest.get() {
+                Some(jpeg) => request.respond(
+                    Response::from_data(jpeg.as_slice())
+                        .with_header(header("Content-Type", "image/jpeg"))
+                        .with_header(header("Cache-Control", "no-cache")),
+                ),
+                None => request
+                    .respond(Response::from_string("No frame received yet").with_status_code(503)),
+            },
+            _ => request.respond(Response::from_string("Not found").with_status_code(404)),
+        };
+        if let Err(e) = result {
+            // Usually a client closing the stream
+            tracing::debug!("HTTP response to {path} ended: {e}");
+        }
+    }
+
+    /// Reserves a stream, and waits up to `keepalive` for its first frame.
+    fn open_stream(&self) -> Result<(StreamSlot, NumberedFrame), &'static str> {
+        self.streams
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |streams| {
+                (streams < self.max_streams).then(|| streams + 1)
+            })
+            .map_err(|_| "Too many streams")?;
+        let slot = StreamSlot(self.streams.clone());
+        let first = self
+            .latest
+            .wait_newer(0, self.keepalive)
+            .ok_or("No frame received yet")?;
+        Ok((slot, first))
+    }
+
+    /// Writes the `multipart/x-mixed-replace` response made of `first` and of each new frame, until the client
+    /// goes away. The last frame is sent again when no new one was received for `keepalive`, as writing is the
+    /// only way to notice that the client went away.
+    /// The response is written directly rather than with a [`Response`], that would buffer its endless body.
+    fn write_stream(
+        &self,
+        mut writer: Box<dyn Write + Send>,
+        http_version: &str,
+        first: NumberedFrame,
+    ) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/{http_version} 200 OK\r\n\
+             Content-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\n\
+             Cache-Control: no-cache\r\n\
+             Connection: close\r\n\r\n"
+        )?;
+        let (mut count, mut jpeg) = first;
+        loop {
+            write_part(&mut writer, &jpeg)?;
+            // There is always a frame once the first one was received
+            if let Some((newer_count, newer_jpeg)) = self.latest.wait_newer(count, self.keepalive) {
+                count = newer_count;
+                jpeg = newer_jpeg;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Read,
+        net::{SocketAddr, TcpStream},
+    };
+
+    /// Starts `gateway` on a local port, and returns its address.
+    fn start(gateway: &Arc<Gateway>) -> SocketAddr {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let gateway = gateway.clone();
+        std::thread::spawn(move || gateway.serve(server));
+        addr
+    }
+
+    /// Sends a HTTP/1.0 GET request, so that the response body is not chunked.
+    fn get(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(client, "GET {path} HTTP/1.0\r\n\r\n").unwrap();
+        client
+    }
+
+    /// Reads the whole response of a request.
+    fn read_response(mut client: TcpStream) -> (String, Vec<u8>) {
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        split_head(&response)
+    }
+
+    /// Splits a response in its status line and headers, and its body.
+    fn split_head(response: &[u8]) -> (String, Vec<u8>) {
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("Incomplete response head");
+        (
+            String::from_utf8_lossy(&response[..end]).to_string(),
+            response[end + 4..].to_vec(),
+        )
+    }
+
+    fn part(jpeg: &[u8]) -> Vec<u8> {
+        let mut part = format!(
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )
+        .into_bytes();
+        part.extend_from_slice(jpeg);
+        part.extend_from_slice(b"\r\n");
+        part
+    }
+
+    /// Reads from `client` until `len` bytes were received after the response head.
+    fn read_stream(
+        client: &mut TcpStream,
+        received: &mut Vec<u8>,
+        len: usize,
+    ) -> (String, Vec<u8>) {
+        let mut buf = [0u8; 1024];
+        loop {
+            if let Some(end) = received.windows(4).position(|w| w == b"\r\n\r\n") {
+                if received.len() >= end + 4 + len {
+                    return split_head(&received[..end + 4 + len]);
+                }
+            }
+            let n = client.read(&mut buf).unwrap();
+            assert!(n > 0, "stream ended");
+            received.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn wait_for_streams(gateway: &Gateway, streams: usize) {
+        let start = Instant::now();
+        while gateway.streams() != streams {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "{} streams instead of {}",
+                gateway.streams(),
+                streams
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn snapshot_serves_latest_frame() {
+        let gateway = Arc::new(Gateway::new(4, Duration::from_secs(1)));
+        let addr = start(&gateway);
+
+        let (head, _) = read_response(get(addr, "/snapshot"));
+        assert!(head.starts_with("HTTP/1.0 503"), "{}", head);
+
+        gateway.latest().set(b"first".to_vec());
+        gateway.latest().set(b"second".to_vec());
+        let (head, body) = read_response(get(addr, "/snapshot?t=1"));
+        assert!(head.starts_with("HTTP/1.0 200"), "{}", head);
+        assert!(head.contains("Content-Type: image/jpeg"), "{}", head);
+        assert_eq!(body, b"second");
+
+        let (head, _) = read_response(get(addr, "/unknown"));
+        assert!(head.starts_with("HTTP/1.0 404"), "{}", head);
+    }
+
+    #[test]
+    fn stream_frames_each_jpeg_between_boundaries() {
+        let gateway = Arc::new(Gateway::new(4, Duration::from_secs(5)));
+        let addr = start(&gateway);
+        gateway.latest().set(b"first".to_vec());
+
+        let mut client = get(addr, "/stream");
+        let mut received = Vec::new();
+        let (head, body) = read_stream(&mut client, &mut received, part(b"first").len());
+        assert!(head.starts_with("HTTP/1.0 200"), "{}", head);
+        assert!(
+            head.contains(&format!(
+                "Content-Type: multipart/x-mixed-replace; boundary={BOUNDARY}"
+            )),
+            "{}",
+            head
+        );
+        assert_eq!(body, part(b"first"));
+
+        gateway.latest().set(b"second frame".to_vec());
+        let len = part(b"first").len() + part(b"second frame").len();
+        let (_, body) = read_stream(&mut client, &mut received, len);
+        assert_eq!(body[part(b"first").len()..], part(b"second frame")[..]);
+        assert_eq!(gateway.streams(), 1);
+    }
+
+    #[test]
+    fn streams_are_capped_and_released() {
+        let gateway = Arc::new(Gateway::new(1, Duration::from_millis(50)));
+        let addr = start(&gateway);
+
+        // Without frame, the stream is refused once the keepalive elapsed
+        let (head, _) = read_response(get(addr, "/stream"));
+        assert!(head.starts_with("HTTP/1.0 503"), "{}", head);
+        wait_for_streams(&gateway, 0);
+
+        // A large frame, so that writing to a closed connection fails quickly
+        gateway.latest().set(vec![0xff; 256 * 1024]);
+        let mut client = get(addr, "/stream");
+        let mut buf = [0u8; 1024];
+        assert!(client.read(&mut buf).unwrap() > 0);
+        wait_for_streams(&gateway, 1);
+        let (head, _) = read_response(get(addr, "/stream"));
+        assert!(head.starts_with("HTTP/1.0 503"), "{}", head);
+
+        // No new frame is received, but the stream of the closed client ends with the keepalive
+        drop(client);
+        wait_for_streams(&gateway, 0);
+        let mut client = get(addr, "/stream");
+        assert!(client.read(&mut buf).unwrap() > 0);
+    }
+
+    #[test]
+    fn rate_limiter_serves_at_most_max_fps() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut limiter = RateLimiter::default();
+        let served = [0, 50, 99, 100, 150, 250]
+            .iter()
+            .map(|ms| limiter.allow(10.0, at(*ms)))
+            .collect::<Vec<_>>();
+        assert_eq!(served, [true, false, false, true, false, true]);
+
+        // Without limit, every frame is served
+        let mut limiter = RateLimiter::default();
+        assert!([0, 0, 1].iter().all(|ms| limiter.allow(0.0, at(*ms))));
+    }
+}
@@ -0,0 +1,277 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use clap::Parser;
+use serde_json::json;
+
+use opencv::{
+    core::{Mat, Point, Ptr, Size, ToInputArray, Vector},
+    imgproc,
+    prelude::*,
+    video::{self, BackgroundSubtractorMOG2},
+};
+use tokio::select;
+use zcam::{
+    detection::{MotionEvent, MotionParams},
+    params::{params_loop, Params},
+    BadFrames, FrameMeta, RawFrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    Session,
+};
+
+const STAGE: &str = "zmotion";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+
+    let detector = MotionDetector::new(&args).unwrap();
+    let params = detector.params.clone();
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_pub.clone(), reliability, congestion_ctrl, detector) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    /// Number of frames the background model is built on
+    #[arg(long, default_value = "500")]
+    history: i32,
+
+    /// Threshold on the squared distance between a pixel and the background model to consider it moving
+    #[arg(long, default_value = "16")]
+    var_threshold: f64,
+
+    /// Rate at which the background model adapts, between 0 and 1 (negative for automatic)
+    #[arg(long, default_value = "-1", allow_negative_numbers = true)]
+    learning_rate: f64,
+
+    /// Minimum area of a moving region, in pixels
+    #[arg(long, default_value = "500")]
+    min_area: f64,
+
+    /// Minimum fraction of the frame in motion to emit an event
+    #[arg(long, default_value = "0.01")]
+    min_magnitude: f32,
+
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    /// Key to publish the motion events on
+    #[arg(long, default_value = "demo/zcam/motion")]
+    key_pub: String,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Motion detector based on background subtraction (MOG2).
+struct MotionDetector {
+    subtractor: Ptr<BackgroundSubtractorMOG2>,
+    kernel: Mat,
+    params: Params<MotionParams>,
+    mask: Mat,
+    binary: Mat,
+}
+
+impl MotionDetector {
+    fn new(args: &Args) -> zenoh::Result<Self> {
+        Ok(Self {
+            subtractor: video::create_background_subtractor_mog2(
+                args.history,
+                args.var_threshold,
+                true,
+            )?,
+            kernel: imgproc::get_structuring_element_def(imgproc::MORPH_RECT, Size::new(3, 3))?,
+            params: Params::new(MotionParams {
+                learning_rate: args.learning_rate,
+                min_area: args.min_area,
+                min_magnitude: args.min_magnitude,
+            })?,
+            mask: Mat::default(),
+            binary: Mat::default(),
+        })
+    }
+
+    /// Updates the background model with `frame` and returns the motion in it, if any.
+    fn detect(
+        &mut self,
+        frame: &impl ToInputArray,
+        raw_meta: &RawFrameMeta,
+    ) -> opencv::Result<Option<MotionEvent>> {
+        let params = self.params.get();
+        self.subtractor
+            .apply(frame, &mut self.mask, params.learning_rate)?;
+
+        // Drop shadows (marked 127 in the mask) and noise
+        imgproc::threshold(
+            &self.mask,
+            &mut self.binary,
+            200.0,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        imgproc::morphology_ex_def(
+            &self.binary,
+            &mut self.mask,
+            imgproc::MORPH_OPEN,
+            &self.kernel,
+        )?;
+
+        // Moving regions, along with their area
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours_def(
+            &self.mask,
+            &mut contours,
+            imgproc::RETR_EXTERNAL,
+            imgproc::CHAIN_APPROX_SIMPLE,
+        )?;
+        let regions = contours
+            .iter()
+            .map(|contour| {
+                Ok((
+                    imgproc::bounding_rect(&contour)?,
+                    imgproc::contour_area_def(&contour)?,
+                ))
+            })
+            .collect::<opencv::Result<Vec<_>>>()?;
+        let moving_pixels = opencv::core::count_non_zero(&self.mask)?;
+
+        Ok(MotionEvent::from_regions(
+            raw_meta,
+            regions,
+            moving_pixels,
+            &params,
+        ))
+    }
+}
+
+/// Processing loop that subscribes to raw frames, runs background subtraction on them directly in SHM
+/// and publishes a motion event for each frame with enough motion.
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_pub: String,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    mut detector: MotionDetector,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    // Declare publisher for motion events
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        let raw_meta = match &meta {
+            FrameMeta::Raw(raw_meta) => raw_meta,
+            other_meta => {
+                tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                continue;
+            }
+        };
+
+        // Map the frame without copying and detect motion
+        let motion = {
+            let contiguous_bytes = sample.payload().to_bytes();
+            let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+            detector.detect(&frame, raw_meta)
+        };
+
+        match motion {
+            Ok(Some(event)) => publ.put(event.encode().unwrap()).await.unwrap(),
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to process frame: {e}"),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{collections::HashMap, time::Duration};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use opencv::core::Scalar;
+use tokio::select;
+use zcam::{
+    detection::{draw_detections, Detections, PendingDetections},
+    params::{params_loop, Params, StageParams},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    Session,
+};
+
+const STAGE: &str = "zoverlay";
+
+/// Maximum number of frames for which detections are kept, per detector.
+const MAX_PENDING: usize = 64;
+
+/// Colors given to the detectors, in order of appearance (BGR).
+const COLORS: [(f64, f64, f64); 6] = [
+    (0.0, 255.0, 0.0),
+    (0.0, 255.0, 255.0),
+    (255.0, 0.0, 255.0),
+    (255.0, 255.0, 0.0),
+    (0.0, 128.0, 255.0),
+    (255.0, 128.0, 0.0),
+];
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+
+    let params = Params::new(OverlayParams {
+        max_lag: args.max_lag,
+    })
+    .expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_detections.clone(), args.key_pub.clone(), reliability, congestion_ctrl, params.clone(), shm_pool.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key_sub), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the frames to draw the detections on
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    /// Key (or key expression, to compose several detectors) of the detections
+    #[arg(long, default_value = "demo/zcam/*/detections")]
+    key_detections: String,
+
+    #[arg(long, default_value = "demo/zcam/overlay")]
+    key_pub: String,
+
+    /// Maximum difference of sequence number between a frame and the detections drawn on it
+    #[arg(long, default_value = "15")]
+    max_lag: u64,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Runtime parameters of `zoverlay`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct OverlayParams {
+    /// Maximum difference of sequence number between a frame and the detections drawn on it
+    max_lag: u64,
+}
+
+impl StageParams for OverlayParams {}
+
+/// Detections received from one detector, and the color they are drawn with.
+struct DetectorState {
+    color: Scalar,
+    pending: PendingDetections,
+}
+
+/// Processing loop that subscribes to frames and to the detections of any number of detectors,
+/// and republishes the frames with the matching detections drawn in-place in SHM.
+/// Detections are matched to frames by sequence number.
+#[allow(clippy::too_many_arguments)]
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_detections: String,
+    key_pub: String,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    params: Params<OverlayParams>,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    // Declare subscriber for detections
+    let detections_sub = session.declare_subscriber(&key_detections).await.unwrap();
+
+    // Declare publisher for processed frames
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    // Detections received so far, per key
+    let mut detectors: HashMap<String, DetectorState> = HashMap::new();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        select!(
+            // Receive detections
+            sample = detections_sub.recv_async() => {
+                let sample = sample.unwrap();
+                let detections = match Detections::decode(sample.payload()) {
+                    Ok(detections) => detections,
+                    Err(e) => {
+                        tracing::error!("Unable to decode detections on {}: {e}", sample.key_expr());
+                        continue;
+                    }
+                };
+                let count = detectors.len();
+                detectors
+                    .entry(sample.key_expr().to_string())
+                    .or_insert_with(|| {
+                        let (b, g, r) = COLORS[count % COLORS.len()];
+                        DetectorState {
+                            color: Scalar::new(b, g, r, 0.0),
+                            pending: PendingDetections::new(MAX_PENDING),
+                        }
+                    })
+                    .pending
+                    .insert(detections);
+            }
+
+            // Receive sample with frame
+            sample = sub.recv_async() => {
+                let mut sample = sample.unwrap();
+
+                // Decode frame metadata
+                let mut meta = match FrameMeta::decode(&sample) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        bad_frames.skip(e);
+                        continue;
+                    }
+                };
+
+                let raw_meta = match &meta {
+                    FrameMeta::Raw(raw_meta) => raw_meta.clone(),
+                    other_meta => {
+                        tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                        continue;
+                    }
+                };
+
+                // Gather the detections of each detector for this frame
+                let max_lag = params.get().max_lag;
+                let overlays = detectors
+                    .values()
+                    .filter_map(|detector| {
+                        detector
+                            .pending
+                            .lookup(raw_meta.trace().seq, max_lag, raw_meta.rows(), raw_meta.cols())
+                            .map(|detections| (detector.color, detections))
+                    })
+                    .collect::<Vec<_>>();
+
+                // Draw the detections directly on the SHM buffer
+                match process_in_shm(&mut sample, &raw_meta, &shm_pool, |frame| {
+                    for (color, detections) in &overlays {
+                        draw_detections(frame, detections, *color)?;
+                    }
+                    Ok(())
+                })
+                .await
+                {
+                    Ok((processed_frame_in_shm, ())) => {
+                        // Encode frame metadata
+                        meta.raw_mut().add_hop(STAGE);
+                        let attachment = meta.encode().unwrap();
+
+                        // Publish SHM frame
+                        publ.put(processed_frame_in_shm)
+                            .attachment(attachment)
+                            .await
+                            .unwrap();
+                    }
+                    Err(e) => tracing::error!("Failed to process frame: {e}"),
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Typed runtime parameters of the stages, updated live and read back over Zenoh.
+
+use std::sync::{Arc, RwLock};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use zenoh::{query::Query, Session};
+
+/// The parameters of a stage that can be changed while it runs.
+///
+/// Parameters are (de)serialized as a JSON object, each field being a parameter.
+pub trait StageParams: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    /// Checks the parameters, returning the reason of their rejection if they are invalid.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Error returned when setting a parameter.
+#[derive(Debug)]
+pub enum ParamError {
+    /// The stage has no such parameter
+    Unknown(String),
+    /// The value was rejected
+    Invalid(String),
+}
+
+impl std::fmt::Display for ParamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamError::Unknown(name) => write!(f, "Unknown parameter '{name}'"),
+            ParamError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Shared handle on the current parameters of a stage.
+#[derive(Clone)]
+pub struct Params<T> {
+    inner: Arc<RwLock<T>>,
+}
+
+impl<T: StageParams> Params<T> {
+    /// Wraps the initial parameters (usually taken from the command line), after validating them.
+    pub fn new(params: T) -> Result<Self, String> {
+        params.validate()?;
+        Ok(Self {
+            inner: Arc::new(RwLock::new(params)),
+        })
+    }
+
+    /// Returns a copy of the current parameters.
+    pub fn get(&self) -> T {
+        self.inner.read().unwrap().clone()
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&*self.inner.read().unwrap()).unwrap_or_default()
+    }
+
+    /// Sets parameter `name` to `value`, given in JSON (a value that is not valid JSON is taken as a string).
+    /// The parameters are left unchanged if the value cannot be deserialized or fails validation.
+    pub fn set(&self, name: &str, value: &str) -> Result<T, ParamError> {
+        let mut params = self.inner.write().unwrap();
+        let mut json = serde_json::to_value(&*params)
+            .map_err(|e| ParamError::Invalid(format!("Unable to serialize parameters: {e}")))?;
+        let fields = json
+            .as_object_mut()
+            .ok_or_else(|| ParamError::Invalid("Parameters are not a JSON object".to_string()))?;
+        if !fields.contains_key(name) {
+            return Err(ParamError::Unknown(name.to_string()));
+        }
+
+        let value =
+            serde_json::from_str(value.trim()).unwrap_or_else(|_| Value::String(value.to_string()));
+        fields.insert(name.to_string(), value);
+        let updated: T = serde_json::from_value(json)
+            .map_err(|e| ParamError::Invalid(format!("Invalid value for '{name}': {e}")))?;
+        updated
+            .validate()
+            .map_err(|e| ParamError::Invalid(format!("Invalid value for '{name}': {e}")))?;
+
+        *params = updated.clone();
+        Ok(updated)
+    }
+}
+
+/// Serves the runtime configuration of a stage under `conf_prefix` (i.e. `<key>/<stage>/conf`):
+/// - a put on `<conf_prefix>/<name>` sets parameter `name` of the stage, or the Zenoh configuration
+///   `name` if the stage has no such parameter, as [`config_update_loop`](crate::config_update_loop) does.
+///   Rejected values are logged.
+/// - a query on `<conf_prefix>` replies with all the parameters of the stage, as a JSON object.
+/// - a query on `<conf_prefix>/<name>` with a payload, or a `value` selector parameter, sets the
+///   parameter and replies with all the parameters, or with an error if the value is rejected.
+///   Without value, it replies with the value of the parameter.
+pub async fn params_loop<T: StageParams>(
+    session: &Session,
+    conf_prefix: String,
+    params: Params<T>,
+) {
+    // Declare subscriber for configuration updates
+    let conf_sub = session
+        .declare_subscriber(format!("{conf_prefix}/**"))
+        .await
+        .unwrap();
+
+    // Declare queryable for configuration read-back and checked updates
+    let conf_queryable = session
+        .declare_queryable(format!("{conf_prefix}/**"))
+        .await
+        .unwrap();
+
+    let param_name = |key: &str| {
+        key.strip_prefix(conf_prefix.as_str())
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_default()
+    };
+
+    loop {
+        tokio::select!(
+            sample = conf_sub.recv_async() => {
+                let sample = sample.unwrap();
+                let name = param_name(sample.key_expr().as_str());
+                let value = String::from_utf8_lossy(&sample.payload().to_bytes()).to_string();
+                match set(session, &params, &name, &value) {
+                    Ok(()) => tracing::info!("Set {name} to {value}"),
+                    Err(e) => tracing::error!("Rejected {name}={value}: {e}"),
+                }
+            }
+
+            query = conf_queryable.recv_async() => {
+                let query = query.unwrap();
+                let name = param_name(query.key_expr().as_str());
+                reply(session, &params, &name, &query).await;
+            }
+        );
+    }
+}
+
+/// Sets parameter `name` of the stage, falling back to the Zenoh configuration.
+fn set<T: StageParams>(
+    session: &Session,
+    params: &Params<T>,
+    name: &str,
+    value: &str,
+) -> zenoh::Result<()> {
+    match params.set(name, value) {
+        Ok(_) => Ok(()),
+        Err(ParamError::Unknown(_)) => session.config().insert_json5(name, value),
+        Err(e) => Err(e.to_string().into()),
+    }
+}
+
+async fn reply<T: StageParams>(session: &Session, params: &Params<T>, name: &str, query: &Query) {
+    let value = match query.payload() {
+        Some(payload) => Some(String::from_utf8_lossy(&payload.to_bytes()).to_string()),
+        None => query.parameters().get("value").map(str::to_string),
+    };
+
+    let result = match (name, value) {
+        ("", _) => Ok(params.to_json()),
+        (name, None) => match params.to_json().get(name) {
+            Some(value) => Ok(value.clone()),
+            // Not a parameter of the stage, leave it to other queryables (e.g. stage state)
+            None => return,
+        },
+        (name, Some(value)) => set(session, params, name, &value).map(|()| params.to_json()),
+    };
+
+    let _ = match result {
+        Ok(value) => {
+            query
+                .reply(query.key_expr().clone(), value.to_string())
+                .await
+        }
+        Err(e) => query.reply_err(e.to_string()).await,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+    struct TestParams {
+        rate: f64,
+        name: String,
+        enabled: bool,
+    }
+
+    impl StageParams for TestParams {
+        fn validate(&self) -> Result<(), String> {
+            if self.rate <= 0.0 {
+                return Err("rate must be positive".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    fn params() -> Params<TestParams> {
+        Params::new(TestParams {
+            rate: 1.0,
+            name: "zcam".to_string(),
+            enabled: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn new_validates_the_initial_parameters() {
+        let invalid = TestParams {
+            rate: 0.0,
+            name: String::new(),
+            enabled: false,
+        };
+        assert!(Params::new(invalid).is_err());
+        assert_eq!(
+            params().to_json(),
+            serde_json::json!({"rate": 1.0, "name": "zcam", "enabled": false})
+        );
+    }
+
+    #[test]
+    fn set_takes_json_values_or_strings() {
+        let params = params();
+        assert_eq!(params.set("rate", " 2.5\n").unwrap().rate, 2.5);
+        assert!(params.set("enabled", "true").unwrap().enabled);
+        // A value that is not valid JSON is taken as a string
+        assert_eq!(params.set("name", "front door").unwrap().name, "front door");
+        assert_eq!(params.set("name", "\"42\"").unwrap().name, "42");
+        assert_eq!(
+            params.get(),
+            TestParams {
+                rate: 2.5,
+                name: "42".to_string(),
+                enabled: true,
+            }
+        );
+    }
+
+    #[test]
+    fn set_leaves_the_parameters_unchanged_on_error() {
+        let params = params();
+        assert!(matches!(
+            params.set("unknown", "1"),
+            Err(ParamError::Unknown(name)) if name == "unknown"
+        ));
+        // Not a number
+        assert!(matches!(
+            params.set("rate", "fast"),
+            Err(ParamError::Invalid(_))
+        ));
+        // Rejected by the validation
+        let error = params.set("rate", "-1").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for 'rate': rate must be positive"
+        );
+        assert_eq!(params.get().rate, 1.0);
+    }
+
+    #[test]
+    fn clones_share_the_parameters() {
+        let params = params();
+        let clone = params.clone();
+        params.set("rate", "3").unwrap();
+        assert_eq!(clone.get().rate, 3.0);
+    }
+}
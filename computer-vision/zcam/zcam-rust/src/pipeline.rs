@@ -0,0 +1,126 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::path::PathBuf;
+
+use clap::Parser;
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::Deserialize;
+use serde_json::Value;
+
+use zcam::{
+    params::Params,
+    processor::{run, FrameProcessor, StageArgs, StageConfig},
+    transform::{Blur, Crop, Grayscale, Resize, Rotate},
+};
+use zenoh::Session;
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let config = args.stage.zenoh_config();
+    let specs: Vec<StageSpec> = serde_json::from_str(
+        &std::fs::read_to_string(&args.pipeline).expect("Unable to read the pipeline file"),
+    )
+    .expect("Invalid pipeline file");
+    if specs.is_empty() {
+        panic!("The pipeline has no stage");
+    }
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    // Chain the stages: each one processes the output of the previous one
+    let mut stages = Vec::new();
+    let mut key_sub = args.stage.key_sub.clone();
+    for (i, spec) in specs.iter().enumerate() {
+        let mut stage_config = StageArgs {
+            key_sub: key_sub.clone(),
+            key_pub: spec.key_pub.clone(),
+            ..args.stage.clone()
+        }
+        .stage_config(&spec.stage);
+        if i == specs.len() - 1 {
+            if let Some(key_pub) = &args.stage.key_pub {
+                stage_config.key_pub = key_pub.clone();
+            }
+        }
+        key_sub = stage_config.key_pub.clone();
+
+        println!("{} -> {} -> {}", stage_config.key_sub, spec.stage, key_sub);
+        match start_stage(&z, stage_config, spec) {
+            Ok(stage) => stages.push(stage),
+            Err(e) => panic!("Invalid stage {}: {e}", spec.stage),
+        }
+    }
+
+    futures::future::join_all(stages).await;
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    /// JSON file listing the stages, e.g. `[{"stage": "resize", "params": {"width": 640}}, {"stage": "grayscale"}]`.
+    /// Available stages: resize, crop, rotate, grayscale, blur
+    #[arg(short, long)]
+    pipeline: PathBuf,
+
+    /// Keys and QoS, `--key-pub` being the key of the output of the last stage
+    #[command(flatten)]
+    stage: StageArgs,
+}
+
+/// A stage of the pipeline file.
+#[derive(Deserialize)]
+struct StageSpec {
+    /// Kind of stage
+    stage: String,
+    /// Key to publish the output of the stage on, `<input key>/<stage>` by default
+    #[serde(default)]
+    key_pub: Option<String>,
+    /// Initial parameters of the stage, the defaults being used for the missing ones
+    #[serde(default)]
+    params: Value,
+}
+
+fn start_stage<'a>(
+    session: &'a Session,
+    config: StageConfig,
+    spec: &StageSpec,
+) -> zenoh::Result<LocalBoxFuture<'a, ()>> {
+    match spec.stage.as_str() {
+        "resize" => start(session, config, Resize, &spec.params),
+        "crop" => start(session, config, Crop, &spec.params),
+        "rotate" => start(session, config, Rotate, &spec.params),
+        "grayscale" => start(session, config, Grayscale, &spec.params),
+        "blur" => start(session, config, Blur, &spec.params),
+        other => Err(format!("Unknown stage '{other}'").into()),
+    }
+}
+
+fn start<P: FrameProcessor>(
+    session: &Session,
+    config: StageConfig,
+    processor: P,
+    params: &Value,
+) -> zenoh::Result<LocalBoxFuture<'_, ()>> {
+    let params = match params {
+        Value::Null => Value::Object(Default::default()),
+        params => params.clone(),
+    };
+    let params = Params::new(serde_json::from_value::<P::Params>(params)?)?;
+    Ok(run(session, config, processor, params).boxed_local())
+}
@@ -0,0 +1,319 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use tokio::select;
+use zcam::{
+    params::{params_loop, Params, StageParams},
+    segment, FrameMeta,
+};
+use zenoh::{
+    bytes::ZBytes,
+    config::Config,
+    key_expr::KeyExpr,
+    query::{ConsolidationMode, Query, ZenohParameters},
+    Session,
+};
+
+const STAGE: &str = "zplay";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let config = parse_args(&args);
+
+    let source = match (&args.input, &args.key_storage) {
+        (Some(dir), None) => PlaySource::Segments(dir.clone()),
+        (None, Some(key)) => PlaySource::Storage(key.clone()),
+        _ => unreachable!("clap ensures exactly one of --input and --key-storage is given"),
+    };
+
+    let params = Params::new(PlayParams { speed: args.speed }).expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_query.clone(), args.key_pub.clone(), source, params.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_query), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+#[command(group(clap::ArgGroup::new("source").required(true).args(["input", "key_storage"])))]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Directory of the segment files written by `zrecord --output`
+    #[arg(long)]
+    input: Option<PathBuf>,
+
+    /// Key prefix of the frames stored by `zrecord --key-storage`
+    #[arg(long)]
+    key_storage: Option<String>,
+
+    /// Key of the queryable receiving the replay requests
+    #[arg(short, long, default_value = "demo/zcam/play")]
+    key_query: String,
+
+    /// Key to republish the frames on, unless the request gives a `key` parameter
+    #[arg(long, default_value = "demo/zcam/replay")]
+    key_pub: String,
+
+    /// Replay speed, unless the request gives a `speed` parameter (e.g. 2.0 replays twice as fast)
+    #[arg(long, default_value = "1.0")]
+    speed: f64,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+}
+
+fn parse_args(args: &Args) -> Config {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    c
+}
+
+/// Bounds of the replay speed.
+const MIN_SPEED: f64 = 1.0 / 64.0;
+const MAX_SPEED: f64 = 64.0;
+
+/// Runtime parameters of `zplay`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PlayParams {
+    /// Replay speed of the requests that do not give a `speed` parameter
+    speed: f64,
+}
+
+impl StageParams for PlayParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(MIN_SPEED..=MAX_SPEED).contains(&self.speed) {
+            return Err(format!("speed must be between {MIN_SPEED} and {MAX_SPEED}"));
+        }
+        Ok(())
+    }
+}
+
+/// Where the recorded frames are read from.
+enum PlaySource {
+    Segments(PathBuf),
+    Storage(String),
+}
+
+/// A recorded frame, with its timestamp in microseconds since the UNIX epoch.
+struct RecordedFrame {
+    timestamp: u64,
+    attachment: ZBytes,
+    payload: ZBytes,
+}
+
+fn to_system_time(timestamp: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(timestamp)
+}
+
+impl PlaySource {
+    /// Returns the recorded frames matching the `_time` parameter of the query, if any, in time order.
+    async fn fetch(&self, session: &Session, query: &Query) -> zenoh::Result<Vec<RecordedFrame>> {
+        let time_range = query.parameters().time_range().transpose()?;
+        let in_range = |timestamp: u64| {
+            time_range
+                .as_ref()
+                .map_or(true, |range| range.contains(to_system_time(timestamp)))
+        };
+
+        let mut frames = match self {
+            PlaySource::Segments(dir) => {
+                let mut frames = Vec::new();
+                for (_, path) in segment::list_segments(dir)? {
+                    for record in segment::read_segment(&path, in_range)? {
+                        frames.push(RecordedFrame {
+                            timestamp: record.timestamp,
+                            attachment: record.attachment.into(),
+                            payload: record.payload.into(),
+                        });
+                    }
+                }
+                frames
+            }
+            PlaySource::Storage(key) => {
+                // Forward the time range to the storage, and filter the replies in case it ignores it
+                let selector = match query.parameters().as_str() {
+                    "" => format!("{key}/**"),
+                    parameters => format!("{key}/**?{parameters}"),
+                };
+                let replies = session
+                    .get(&selector)
+                    .consolidation(ConsolidationMode::None)
+                    .await?;
+                let mut frames = Vec::new();
+                while let Ok(reply) = replies.recv_async().await {
+                    let Ok(sample) = reply.into_result() else {
+                        continue;
+                    };
+                    let Ok(meta) = FrameMeta::decode(&sample) else {
+                        continue;
+                    };
+                    let timestamp = match meta.raw().trace().capture_timestamp {
+                        0 => match sample.timestamp() {
+                            Some(ts) => ts
+                                .get_time()
+                                .to_system_time()
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap_or_default()
+                                .as_micros() as u64,
+                            None => continue,
+                        },
+                        timestamp => timestamp,
+                    };
+                    if in_range(timestamp) {
+                        frames.push(RecordedFrame {
+                            timestamp,
+                            attachment: sample.attachment().cloned().unwrap(),
+                            payload: sample.payload().clone(),
+                        });
+                    }
+                }
+                frames
+            }
+        };
+        frames.sort_by_key(|frame| frame.timestamp);
+        Ok(frames)
+    }
+}
+
+/// Republishes `frames` on `key`, with their original timing divided by `speed`.
+async fn replay(session: Session, key: KeyExpr<'static>, speed: f64, frames: Vec<RecordedFrame>) {
+    let publ = match session.declare_publisher(key.clone()).await {
+        Ok(publ) => publ,
+        Err(e) => {
+            tracing::error!("Failed to declare publisher on {key}: {e}");
+            return;
+        }
+    };
+
+    let Some(first_timestamp) = frames.first().map(|frame| frame.timestamp) else {
+        return;
+    };
+    let start = Instant::now();
+    for frame in frames {
+        let offset = Duration::from_micros(frame.timestamp - first_timestamp).div_f64(speed);
+        let elapsed = start.elapsed();
+        if offset > elapsed {
+            tokio::time::sleep(offset - elapsed).await;
+        }
+        if let Err(e) = publ.put(frame.payload).attachment(frame.attachment).await {
+            tracing::error!("Failed to republish frame on {key}: {e}");
+        }
+    }
+    println!("Replayed on {key}");
+}
+
+/// Processing loop that answers the replay requests: each query on `key_query` selects the frames in
+/// its `_time` range (all of them if none is given), gets a summary of the selection in reply, and
+/// starts republishing them on the key given by its `key` parameter at the speed given by its
+/// `speed` parameter, or the `speed` runtime parameter of the stage.
+async fn process_loop(
+    session: &Session,
+    key_query: String,
+    key_pub: String,
+    source: PlaySource,
+    params: Params<PlayParams>,
+) {
+    let key_pub = KeyExpr::try_from(key_pub).expect("Invalid --key-pub");
+
+    // Declare queryable for replay requests
+    let queryable = session.declare_queryable(&key_query).await.unwrap();
+
+    while let Ok(query) = queryable.recv_async().await {
+        // The key is given by a remote querier: it must be checked before publishing on it
+        let key = match query.parameters().get("key") {
+            None => key_pub.clone(),
+            Some(key) => match KeyExpr::try_from(key.to_string()) {
+                Ok(key) if !key.as_str().contains('*') => key,
+                _ => {
+                    let _ = query
+                        .reply_err(format!(
+                            "Invalid key '{key}', expected a key without wildcards"
+                        ))
+                        .await;
+                    continue;
+                }
+            },
+        };
+        let speed = match query.parameters().get("speed").map(str::parse::<f64>) {
+            None => params.get().speed,
+            Some(Ok(speed)) if (MIN_SPEED..=MAX_SPEED).contains(&speed) => speed,
+            Some(_) => {
+                let _ = query
+                    .reply_err(format!(
+                        "Invalid speed, expected a number between {MIN_SPEED} and {MAX_SPEED}"
+                    ))
+                    .await;
+                continue;
+            }
+        };
+
+        let frames = match source.fetch(session, &query).await {
+            Ok(frames) => frames,
+            Err(e) => {
+                let _ = query
+                    .reply_err(format!("Unable to read the recording: {e}"))
+                    .await;
+                continue;
+            }
+        };
+
+        let summary = json!({
+            "frames": frames.len(),
+            "start": frames.first().map(|frame| frame.timestamp),
+            "end": frames.last().map(|frame| frame.timestamp),
+            "key": key.as_str(),
+            "speed": speed,
+        });
+        println!("Replaying {summary}");
+        let _ = query
+            .reply(query.key_expr().clone(), summary.to_string())
+            .await;
+
+        tokio::spawn(replay(session.clone(), key, speed, frames));
+    }
+}
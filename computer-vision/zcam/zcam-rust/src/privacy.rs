@@ -0,0 +1,398 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use clap::Parser;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vector, CV_8UC1},
+    imgproc,
+    objdetect::CascadeClassifier,
+    prelude::*,
+};
+use tokio::select;
+use zcam::{
+    detection::{detect_cascade, load_cascade},
+    masking::{bounding_box, clip, with_margin, MaskMethod, PrivacyParams},
+    params::{params_loop, Params},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    Session,
+};
+
+const STAGE: &str = "zprivacy";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+
+    // Check parameters, including that the cascade loads
+    let masks = match &args.masks {
+        Some(file) => serde_json::from_str(
+            &std::fs::read_to_string(file).expect("Unable to read the masks file"),
+        )
+        .expect("Invalid masks file"),
+        None => Vec::new(),
+    };
+    let params = Params::new(PrivacyParams {
+        haarcascade_file: match args.no_detect {
+            true => String::new(),
+            false => args.haarcascade_file.clone(),
+        },
+        min_weight: args.min_weight,
+        margin: args.margin,
+        method: args.method,
+        strength: args.strength,
+        masks,
+    })
+    .expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_pub.clone(), reliability, congestion_ctrl, params.clone(), shm_pool.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key_sub), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    #[arg(long, default_value = "haarcascade_frontalface_default.xml")]
+    haarcascade_file: String,
+
+    #[arg(long, default_value = "3.5")]
+    min_weight: f64,
+
+    /// Do not detect regions to mask, only mask the static masks
+    #[arg(long, default_value = "false")]
+    no_detect: bool,
+
+    /// JSON file of static masks: a list of polygons, each a list of `[x, y]` points in pixels of the frame
+    #[arg(long)]
+    masks: Option<String>,
+
+    #[arg(long, value_enum, default_value = "pixelate")]
+    method: MaskMethod,
+
+    /// Size of the blur kernel, or of the pixelation blocks, in pixels
+    #[arg(long, default_value = "16")]
+    strength: i32,
+
+    /// Margin added around the detected regions, as a fraction of their size
+    #[arg(long, default_value = "0.2")]
+    margin: f64,
+
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    #[arg(long, default_value = "demo/zcam/privacy")]
+    key_pub: String,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Numbers of regions masked in a frame, published next to it.
+#[derive(Serialize, Debug)]
+struct MaskAudit {
+    seq: u64,
+    capture_timestamp: u64,
+    /// Regions detected by the cascade
+    detected: usize,
+    /// Static masks
+    fixed: usize,
+    /// All the masked regions
+    masked: usize,
+}
+
+/// Processing loop that subscribes to frames, obscures the detected regions and the static masks in-place in SHM
+/// whenever possible, and republishes the frames along with an audit of the masked regions on `<key_pub>/audit`.
+/// Frames that cannot be masked are dropped rather than published as they are.
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_pub: String,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    params: Params<PrivacyParams>,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    // Declare publisher for masking audits
+    let audit_publ = session
+        .declare_publisher(format!("{key_pub}/audit"))
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    // Declare publisher for masked frames
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    // Load cascade, if any
+    let mut haarcascade_file = params.get().haarcascade_file;
+    let mut cascade = match haarcascade_file.as_str() {
+        "" => None,
+        file => Some(load_cascade(file).unwrap()),
+    };
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let mut sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let mut raw_meta = match FrameMeta::decode(&sample) {
+            Ok(FrameMeta::Raw(raw_meta)) => raw_meta,
+            Ok(other_meta) => {
+                tracing::error!("Unsupported frame meta: {other_meta}");
+                continue;
+            }
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+        if raw_meta.format().is_yuv() {
+            tracing::error!(
+                "Unable to mask {} frames, frame {} dropped",
+                raw_meta.format(),
+                raw_meta.trace().seq
+            );
+            continue;
+        }
+
+        // Reload the cascade if it was changed (it was checked when set)
+        let params = params.get();
+        if params.haarcascade_file != haarcascade_file {
+            haarcascade_file = params.haarcascade_file.clone();
+            cascade = match haarcascade_file.as_str() {
+                "" => None,
+                file => load_cascade(file).map_err(|e| tracing::error!("{e}")).ok(),
+            };
+        }
+        if !haarcascade_file.is_empty() && cascade.is_none() {
+            // The faces would go out unmasked
+            tracing::error!(
+                "No cascade loaded from {haarcascade_file}, frame {} dropped",
+                raw_meta.trace().seq
+            );
+            continue;
+        }
+
+        // Mask the frame in SHM
+        let (masked_frame, (detected, fixed)) =
+            match process_in_shm(&mut sample, &raw_meta, &shm_pool, |frame| {
+                mask_frame(frame, cascade.as_mut(), &params)
+            })
+            .await
+            {
+                Ok(masked) => masked,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to mask frame {}, frame dropped: {e}",
+                        raw_meta.trace().seq
+                    );
+                    continue;
+                }
+            };
+
+        // Encode frame metadata
+        raw_meta.add_hop(STAGE);
+        let audit = MaskAudit {
+            seq: raw_meta.trace().seq,
+            capture_timestamp: raw_meta.trace().capture_timestamp,
+            detected,
+            fixed,
+            masked: detected + fixed,
+        };
+        let attachment = FrameMeta::Raw(raw_meta).encode().unwrap();
+
+        // Publish SHM frame
+        if let Err(e) = publ.put(masked_frame).attachment(attachment).await {
+            tracing::error!("Failed to publish frame: {e}");
+            continue;
+        }
+
+        // Publish masking audit
+        if let Err(e) = audit_publ.put(serde_json::to_string(&audit).unwrap()).await {
+            tracing::error!("Failed to publish masking audit: {e}");
+        }
+    }
+}
+
+/// Obscures the regions detected with `cascade` and the static masks of `params` in `frame`.
+/// Returns the numbers of detected regions and of static masks.
+fn mask_frame(
+    frame: &mut Mat,
+    cascade: Option<&mut CascadeClassifier>,
+    params: &PrivacyParams,
+) -> zenoh::Result<(usize, usize)> {
+    let detections = match cascade {
+        Some(cascade) => detect_cascade(&*frame, cascade, params.min_weight)?,
+        None => Vec::new(),
+    };
+
+    for detection in &detections {
+        mask_region(
+            frame,
+            with_margin(detection.rect(), params.margin),
+            None,
+            params,
+        )?;
+    }
+
+    for mask in &params.masks {
+        let polygon = mask
+            .iter()
+            .map(|[x, y]| Point::new(*x, *y))
+            .collect::<Vector<Point>>();
+        mask_region(frame, bounding_box(mask), Some(&polygon), params)?;
+    }
+
+    Ok((detections.len(), params.masks.len()))
+}
+
+/// Obscures the region `rect` of `frame`, restricted to `polygon` if any.
+fn mask_region(
+    frame: &mut Mat,
+    rect: Rect,
+    polygon: Option<&Vector<Point>>,
+    params: &PrivacyParams,
+) -> opencv::Result<()> {
+    let Some(clipped) = clip(rect, frame.rows(), frame.cols()) else {
+        return Ok(());
+    };
+    let Rect {
+        x,
+        y,
+        width,
+        height,
+    } = clipped;
+
+    let mut region = frame.roi_mut(clipped)?;
+    let source = region.try_clone()?;
+    let mut obscured = Mat::default();
+    match params.method {
+        MaskMethod::Blur => {
+            imgproc::gaussian_blur_def(&source, &mut obscured, params.blur_kernel(), 0.0)?;
+        }
+        MaskMethod::Pixelate => {
+            let mut blocks = Mat::default();
+            let size = params.pixelated_size(clipped.size());
+            imgproc::resize(&source, &mut blocks, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+            imgproc::resize(
+                &blocks,
+                &mut obscured,
+                clipped.size(),
+                0.0,
+                0.0,
+                imgproc::INTER_NEAREST,
+            )?;
+        }
+    }
+
+    match polygon {
+        None => obscured.copy_to(&mut *region),
+        Some(polygon) => {
+            // Only obscure the inside of the polygon
+            let mut mask = Mat::zeros(height, width, CV_8UC1)?.to_mat()?;
+            let polygons = Vector::<Vector<Point>>::from_iter([polygon.clone()]);
+            imgproc::fill_poly(
+                &mut mask,
+                &polygons,
+                Scalar::all(255.0),
+                imgproc::LINE_8,
+                0,
+                Point::new(-x, -y),
+            )?;
+            obscured.copy_to_masked(&mut *region, &mask)
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Generic image processing stage: a [`FrameProcessor`] only transforms frames, and [`run`] does the rest
+//! (subscription, metadata, SHM handling, publication and runtime parameters).
+
+use std::time::Duration;
+
+use opencv::core::{Mat, MatTraitConst};
+use serde_json::json;
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    sample::Sample,
+    shm::ZShm,
+    Session,
+};
+
+use crate::{
+    params::{params_loop, Params, StageParams},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta, PixelFormat, RawFrameMeta,
+};
+
+/// Transformation of raw frames, run as a stage by [`run`].
+pub trait FrameProcessor: Send + 'static {
+    /// Parameters of the transformation that can be changed while it runs
+    type Params: StageParams;
+
+    /// Name of the stage, recorded in the hops of the frames and used in its configuration key.
+    fn name(&self) -> &str;
+
+    /// Whether the processor takes frames of `format`. Frames of other formats are converted to BGR first,
+    /// that all the processors must take.
+    fn accepts(&self, format: PixelFormat) -> bool {
+        format == PixelFormat::Bgr
+    }
+
+    /// Processes `frame`, either in-place, returning `None`, or into a new frame of any size and type, returning it.
+    /// In-place processing happens directly in SHM whenever possible.
+    fn process(&mut self, frame: &mut Mat, params: &Self::Params) -> zenoh::Result<Option<Mat>>;
+}
+
+/// Keys, QoS and SHM pool of a stage.
+#[derive(Debug, Clone)]
+pub struct StageConfig {
+    /// Key of the frames to process
+    pub key_sub: String,
+    /// Key to publish the processed frames on
+    pub key_pub: String,
+    pub reliability: Reliability,
+    pub congestion_control: CongestionControl,
+    pub shm: ShmArgs,
+}
+
+/// Command line arguments common to all the stages, to be flattened in the arguments of a stage.
+#[derive(clap::Args, Debug, Clone, PartialEq)]
+pub struct StageArgs {
+    #[arg(short, long)]
+    pub mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    pub key_sub: String,
+
+    /// Key to publish the processed frames on [default: <key_sub>/<stage>]
+    #[arg(long)]
+    pub key_pub: Option<String>,
+
+    #[arg(short('e'), long)]
+    pub connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    pub config: Option<String>,
+
+    #[arg(long, default_value = "false")]
+    pub best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    pub block_on_congestion: bool,
+
+    #[command(flatten)]
+    pub shm: ShmArgs,
+}
+
+impl StageArgs {
+    pub fn zenoh_config(&self) -> Config {
+        let mut c = if let Some(f) = &self.config {
+            zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+        } else {
+            zenoh::Config::default()
+        };
+
+        if let Some(ls) = &self.connect {
+            let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+        }
+        if let Some(m) = &self.mode {
+            let _ = c.insert_json5("mode", &json!(m).to_string());
+        }
+        self.shm.apply(&mut c);
+        c
+    }
+
+    pub fn stage_config(&self, stage: &str) -> StageConfig {
+        StageConfig {
+            key_sub: self.key_sub.clone(),
+            key_pub: self
+                .key_pub
+                .clone()
+                .unwrap_or_else(|| format!("{}/{stage}", self.key_sub)),
+            reliability: if self.best_effort {
+                Reliability::BestEffort
+            } else {
+                Reliability::Reliable
+            },
+            congestion_control: if self.block_on_congestion {
+                CongestionControl::Block
+            } else {
+                CongestionControl::Drop
+            },
+            shm: self.shm.clone(),
+        }
+    }
+}
+
+/// Runs `processor` as a stage: processes the raw frames received on `config.key_sub` and publishes them
+/// on `config.key_pub`, while serving its parameters on `<key_sub>/<stage>/conf` and publishing the metrics
+/// of its SHM pool on `<key_sub>/<stage>/metrics`.
+/// Frames that cannot be decoded or processed are logged and skipped.
+pub async fn run<P: FrameProcessor>(
+    session: &Session,
+    config: StageConfig,
+    processor: P,
+    params: Params<P::Params>,
+) {
+    let conf_prefix = format!("{}/{}/conf", config.key_sub, processor.name());
+    let key_metrics = format!("{}/{}/metrics", config.key_sub, processor.name());
+    // Obtain SHM pool from the session to allocate SHM buffers for frames
+    let shm_pool = ShmPool::new(session, &config.shm).await;
+    tokio::select!(
+        // Processing loop
+        _ = process_loop(session, config, processor, params.clone(), shm_pool.clone()) => {}
+        // Config update loop
+        _ = params_loop(session, conf_prefix, params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(session, key_metrics, shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+async fn process_loop<P: FrameProcessor>(
+    session: &Session,
+    config: StageConfig,
+    mut processor: P,
+    params: Params<P::Params>,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&config.key_sub).await.unwrap();
+
+    // Declare publisher for processed frames
+    let publ = session
+        .declare_publisher(&config.key_pub)
+        .reliability(config.reliability)
+        .congestion_control(config.congestion_control)
+        .await
+        .unwrap();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let mut sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let raw_meta = match FrameMeta::decode(&sample) {
+            Ok(FrameMeta::Raw(raw_meta)) => raw_meta,
+            Ok(other_meta) => {
+                tracing::error!("Unsupported frame meta: {other_meta}");
+                continue;
+            }
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        let params = params.get();
+        let (processed_frame, mut raw_meta) =
+            match process_frame(&mut sample, &raw_meta, &shm_pool, &mut processor, &params).await {
+                Ok(processed) => processed,
+                Err(e) => {
+                    tracing::error!("Failed to process frame {}: {e}", raw_meta.trace().seq);
+                    continue;
+                }
+            };
+
+        // Encode frame metadata
+        raw_meta.add_hop(processor.name());
+        let attachment = FrameMeta::Raw(raw_meta).encode().unwrap();
+
+        // Publish SHM frame
+        if let Err(e) = publ.put(processed_frame).attachment(attachment).await {
+            tracing::error!("Failed to publish frame: {e}");
+        }
+    }
+}
+
+/// Processes the frame with `processor`, and returns the processed frame in SHM along with its metadata.
+async fn process_frame<P: FrameProcessor>(
+    sample: &mut Sample,
+    raw_meta: &RawFrameMeta,
+    shm_pool: &ShmPool,
+    processor: &mut P,
+    params: &P::Params,
+) -> zenoh::Result<(ZShm, RawFrameMeta)> {
+    if !processor.accepts(raw_meta.format()) {
+        // Convert the frame to BGR and process the copy
+        let mut frame = {
+            let contiguous_bytes = sample.payload().to_bytes();
+            raw_meta.to_bgr(&unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) })?
+        };
+        let bgr_meta = raw_meta.to_bgr_meta(&frame)?;
+        let frame = processor.process(&mut frame, params)?.unwrap_or(frame);
+        return copy_to_shm(&frame, &bgr_meta, shm_pool).await;
+    }
+
+    let (processed_frame, new_frame) = process_in_shm(sample, raw_meta, shm_pool, |frame| {
+        processor.process(frame, params)
+    })
+    .await?;
+
+    match new_frame {
+        None => Ok((processed_frame, raw_meta.clone())),
+        // The frame changed layout
+        Some(frame) => copy_to_shm(&frame, raw_meta, shm_pool).await,
+    }
+}
+
+/// Copies `frame` into a new SHM buffer, and returns it along with its metadata, keeping the trace of `raw_meta`.
+async fn copy_to_shm(
+    frame: &Mat,
+    raw_meta: &RawFrameMeta,
+    shm_pool: &ShmPool,
+) -> zenoh::Result<(ZShm, RawFrameMeta)> {
+    let raw_meta = raw_meta.with_layout_of(frame)?;
+    let mut shmbuf = shm_pool
+        .alloc(raw_meta.size())
+        .await?
+        .ok_or("SHM pool is full, frame dropped")?;
+    let mut shm_frame = unsafe { raw_meta.mat_mut(shmbuf.as_mut_ptr()) };
+    frame.copy_to(&mut shm_frame)?;
+    Ok((shmbuf.into(), raw_meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::shm::ShmPolicy;
+
+    #[derive(clap::Parser)]
+    struct Cli {
+        #[command(flatten)]
+        stage: StageArgs,
+    }
+
+    fn stage_config(args: &[&str]) -> StageConfig {
+        let args = std::iter::once("zstage").chain(args.iter().copied());
+        Cli::try_parse_from(args)
+            .unwrap()
+            .stage
+            .stage_config("zblur")
+    }
+
+    #[test]
+    fn stage_publishes_under_its_input_by_default() {
+        let config = stage_config(&["-k", "demo/zcam/front"]);
+        assert_eq!(config.key_sub, "demo/zcam/front");
+        assert_eq!(config.key_pub, "demo/zcam/front/zblur");
+        assert_eq!(config.reliability, Reliability::Reliable);
+        assert_eq!(config.congestion_control, CongestionControl::Drop);
+        assert_eq!(config.shm, ShmArgs::default());
+    }
+
+    #[test]
+    fn stage_config_follows_the_arguments() {
+        let config = stage_config(&[
+            "--key-pub",
+            "demo/zcam/blurred",
+            "--best-effort",
+            "--block-on-congestion",
+            "--shm-pool-size",
+            "1048576",
+            "--shm-policy",
+            "drop-newest",
+        ]);
+        assert_eq!(config.key_sub, "demo/zcam");
+        assert_eq!(config.key_pub, "demo/zcam/blurred");
+        assert_eq!(config.reliability, Reliability::BestEffort);
+        assert_eq!(config.congestion_control, CongestionControl::Block);
+        assert_eq!(config.shm.shm_pool_size, Some(1 << 20));
+        assert_eq!(config.shm.shm_policy, ShmPolicy::DropNewest);
+    }
+}
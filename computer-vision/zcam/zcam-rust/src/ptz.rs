@@ -0,0 +1,349 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use clap::Parser;
+use serde_json::json;
+
+use opencv::{
+    core::{Mat, Rect, Size},
+    imgproc,
+    prelude::*,
+};
+use tokio::select;
+use zcam::{
+    detection::Detections,
+    params::{params_loop, Params},
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    view::{view_size, PtzParams, Window},
+    BadFrames, FrameMeta, RawFrameMeta,
+};
+use zenoh::{
+    bytes::ZBytes,
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    sample::Sample,
+    Session,
+};
+
+const STAGE: &str = "zptz";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+    let params = Params::new(PtzParams {
+        max_zoom: args.max_zoom,
+        fill: args.fill,
+        smoothing: args.smoothing,
+    })
+    .expect("Invalid parameters");
+    let window = Arc::new(Mutex::new(Window {
+        following: args.follow,
+        ..Default::default()
+    }));
+    let key_ptz = args
+        .key_ptz
+        .clone()
+        .unwrap_or_else(|| format!("{}/ptz", args.key_pub));
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_pub.clone(), (args.width, args.height), window.clone(), reliability, congestion_ctrl, shm_pool.clone()) => {}
+        // PTZ commands loop
+        _ = control_loop(&z, key_ptz, window.clone(), params.clone()) => {},
+        // Detections following loop
+        _ = follow_loop(&z, args.key_detections.clone(), window, params.clone()) => {},
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key_sub), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    /// Width of the published view, 0 to keep the aspect ratio of the frames given `height`
+    #[arg(long, default_value = "640")]
+    width: i32,
+
+    /// Height of the published view, 0 to keep the aspect ratio of the frames given `width`
+    #[arg(long, default_value = "0")]
+    height: i32,
+
+    /// Key of the PTZ commands [default: <key_pub>/ptz]
+    #[arg(long)]
+    key_ptz: Option<String>,
+
+    /// Key of the detections to follow (e.g. `demo/zcam/haarcascade/detections`)
+    #[arg(long)]
+    key_detections: Option<String>,
+
+    /// Follow the largest detection from the start
+    #[arg(long, default_value = "false")]
+    follow: bool,
+
+    #[arg(long, default_value = "8.0")]
+    max_zoom: f64,
+
+    /// Fraction of the view taken by a followed detection
+    #[arg(long, default_value = "0.5")]
+    fill: f64,
+
+    /// Smoothing of the moves following the detections, from 0 (none) to 1 (frozen)
+    #[arg(long, default_value = "0.8")]
+    smoothing: f64,
+
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    #[arg(long, default_value = "demo/zcam/ptz")]
+    key_pub: String,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Processing loop that subscribes to raw frames, and publishes the view of `window` scaled to `size`.
+/// The view is scaled from the frame mapped in the payload directly into a new SHM buffer, and frames
+/// shown whole at their size are republished as they are.
+#[allow(clippy::too_many_arguments)]
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_pub: String,
+    size: (i32, i32),
+    window: Arc<Mutex<Window>>,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    // Declare publisher for views
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let raw_meta = match FrameMeta::decode(&sample) {
+            Ok(FrameMeta::Raw(raw_meta)) => raw_meta,
+            Ok(other_meta) => {
+                tracing::error!("Unsupported frame meta: {other_meta}");
+                continue;
+            }
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        let window = *window.lock().unwrap();
+        let (view, mut view_meta) =
+            match process_frame(&sample, &raw_meta, window, size, &shm_pool).await {
+                Ok(Some(view)) => view,
+                // Dropped as the SHM pool is full
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("Failed to process frame {}: {e}", raw_meta.trace().seq);
+                    continue;
+                }
+            };
+
+        // Encode frame metadata
+        view_meta.add_hop(STAGE);
+        let attachment = FrameMeta::Raw(view_meta).encode().unwrap();
+
+        // Publish SHM frame
+        if let Err(e) = publ.put(view).attachment(attachment).await {
+            tracing::error!("Failed to publish frame: {e}");
+        }
+    }
+}
+
+/// Returns the view of `window` of the frame, scaled to `size`, along with its metadata.
+async fn process_frame(
+    sample: &Sample,
+    raw_meta: &RawFrameMeta,
+    window: Window,
+    size: (i32, i32),
+    shm_pool: &ShmPool,
+) -> zenoh::Result<Option<(ZBytes, RawFrameMeta)>> {
+    let (rows, cols) = (raw_meta.rows(), raw_meta.cols());
+    let size = view_size(size, cols, rows);
+    let region = window.region(cols, rows, size);
+    let yuv = raw_meta.format().is_yuv();
+    if !yuv && region == Rect::new(0, 0, cols, rows) && size == Size::new(cols, rows) {
+        return Ok(Some((sample.payload().clone(), raw_meta.clone())));
+    }
+
+    // YUV frames are converted to BGR first, that can be cropped and scaled
+    let contiguous_bytes = sample.payload().to_bytes();
+    let frame = unsafe { raw_meta.mat(contiguous_bytes.as_ptr()) };
+    let bgr = match yuv {
+        true => Some(raw_meta.to_bgr(&frame)?),
+        false => None,
+    };
+    let view_meta = match &bgr {
+        Some(bgr) => raw_meta.with_layout_of(bgr)?,
+        None => raw_meta.clone(),
+    }
+    .with_size(size.height, size.width);
+
+    // Allocate SHM buffer for the view
+    let Some(mut shmbuf) = shm_pool.alloc(view_meta.size()).await? else {
+        return Ok(None);
+    };
+
+    // Map opencv Mat into allocated shared memory and scale the region of the frame into it
+    let mut view = unsafe { view_meta.mat_mut(shmbuf.as_mut_ptr()) };
+    let region = match &bgr {
+        Some(bgr) => Mat::roi(bgr, region)?,
+        None => Mat::roi(&frame, region)?,
+    };
+    imgproc::resize(&region, &mut view, size, 0.0, 0.0, imgproc::INTER_LINEAR)?;
+
+    Ok(Some((shmbuf.into(), view_meta)))
+}
+
+/// Loop applying the PTZ commands received on `key_ptz` to `window`.
+async fn control_loop(
+    session: &Session,
+    key_ptz: String,
+    window: Arc<Mutex<Window>>,
+    params: Params<PtzParams>,
+) {
+    // Declare subscriber for PTZ commands
+    let sub = session.declare_subscriber(&key_ptz).await.unwrap();
+
+    while let Ok(sample) = sub.recv_async().await {
+        let command = match serde_json::from_slice(&sample.payload().to_bytes()) {
+            Ok(command) => command,
+            Err(e) => {
+                tracing::warn!("Invalid PTZ command: {e}");
+                continue;
+            }
+        };
+        tracing::debug!("PTZ command: {command:?}");
+        window.lock().unwrap().apply(command, params.get().max_zoom);
+    }
+}
+
+/// Loop moving `window` towards the largest detection received on `key_detections` while following.
+async fn follow_loop(
+    session: &Session,
+    key_detections: Option<String>,
+    window: Arc<Mutex<Window>>,
+    params: Params<PtzParams>,
+) {
+    let Some(key_detections) = key_detections else {
+        return futures::future::pending().await;
+    };
+
+    // Declare subscriber for detections
+    let sub = session.declare_subscriber(&key_detections).await.unwrap();
+
+    while let Ok(sample) = sub.recv_async().await {
+        let detections = match Detections::decode(sample.payload()) {
+            Ok(detections) => detections,
+            Err(e) => {
+                tracing::warn!("Unable to decode detections: {e}");
+                continue;
+            }
+        };
+        let Some(largest) = detections
+            .detections
+            .iter()
+            .max_by_key(|detection| detection.width * detection.height)
+        else {
+            continue;
+        };
+
+        let mut window = window.lock().unwrap();
+        if window.following {
+            window.follow(largest, detections.cols, detections.rows, &params.get());
+        }
+    }
+}
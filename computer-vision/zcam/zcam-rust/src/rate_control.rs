@@ -0,0 +1,276 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Rate control of `zencode`, adjusting the encoding to a target bitrate.
+
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+/// Rate controller adjusting Jpeg quality, resolution and frame skipping so that the output
+/// bitrate matches the target bitrate, or the bitrate subscribers report they can take.
+///
+/// When the output is above the target, quality is lowered first, then resolution, then frames
+/// are skipped. When it is well below, the same knobs are restored in reverse order.
+pub struct RateControl {
+    target_bitrate: u64,
+    max_quality: i32,
+    // Current decisions
+    quality: i32,
+    scale: f64,
+    skip: u64,
+    // Measurement over the current window
+    window_start: Instant,
+    window_bytes: usize,
+    measured_bitrate: u64,
+    frame_count: u64,
+    // Latest feedback from subscribers
+    feedback_bitrate: Option<(u64, Instant)>,
+}
+
+impl RateControl {
+    const MIN_QUALITY: i32 = 30;
+    const MIN_SCALE: f64 = 0.25;
+    const MAX_SKIP: u64 = 9;
+    const WINDOW: Duration = Duration::from_secs(1);
+    const FEEDBACK_VALIDITY: Duration = Duration::from_secs(5);
+
+    pub fn new(target_bitrate: u64, max_quality: i32) -> Self {
+        Self {
+            target_bitrate,
+            max_quality,
+            quality: max_quality,
+            scale: 1.0,
+            skip: 0,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            measured_bitrate: 0,
+            frame_count: 0,
+            feedback_bitrate: None,
+        }
+    }
+
+    /// Current Jpeg quality.
+    pub fn quality(&self) -> i32 {
+        self.quality
+    }
+
+    /// Current downscaling factor of the frames, at most 1.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Follows a change of the configured Jpeg quality.
+    pub fn set_max_quality(&mut self, max_quality: i32) {
+        self.max_quality = max_quality;
+        self.quality = self.quality.min(max_quality);
+    }
+
+    /// Effective target: the configured one, lowered by recent subscribers feedback.
+    pub fn effective_target(&self) -> u64 {
+        match self.feedback_bitrate {
+            Some((bitrate, received)) if received.elapsed() < Self::FEEDBACK_VALIDITY => {
+                bitrate.min(self.target_bitrate)
+            }
+            _ => self.target_bitrate,
+        }
+    }
+
+    /// Returns whether the next frame must be skipped to reduce the frame rate.
+    pub fn skip_frame(&mut self) -> bool {
+        let skip = !self.frame_count.is_multiple_of(self.skip + 1);
+        self.frame_count += 1;
+        skip
+    }
+
+    /// Feedback is a JSON object such as `{"bitrate": 1000000}` giving the bitrate (in bits per
+    /// second) a subscriber is able to receive, and/or `{"loss": 0.2}` giving the fraction of frames
+    /// it lost.
+    pub fn on_feedback(&mut self, feedback: &serde_json::Value) {
+        let reference = self.measured_bitrate.max(1);
+        let bitrate = match (feedback["bitrate"].as_u64(), feedback["loss"].as_f64()) {
+            (Some(bitrate), _) => bitrate,
+            (None, Some(loss)) if loss > 0.05 => (reference as f64 * (1.0 - loss)) as u64,
+            (None, Some(_)) => return,
+            (None, None) => {
+                tracing::warn!("Ignoring feedback without 'bitrate' or 'loss': {feedback}");
+                return;
+            }
+        };
+        self.feedback_bitrate = Some((bitrate, Instant::now()));
+    }
+
+    pub fn on_published(&mut self, bytes: usize) {
+        self.window_bytes += bytes;
+        let elapsed = self.window_start.elapsed();
+        if elapsed < Self::WINDOW {
+            return;
+        }
+        self.measured_bitrate = (self.window_bytes as f64 * 8.0 / elapsed.as_secs_f64()) as u64;
+        self.window_start = Instant::now();
+        self.window_bytes = 0;
+        self.adjust();
+    }
+
+    fn adjust(&mut self) {
+        let target = self.effective_target();
+        if self.measured_bitrate > target + target / 10 {
+            // Too much: degrade quality, then resolution, then frame rate
+            if self.quality > Self::MIN_QUALITY {
+                self.quality = (self.quality - 10).max(Self::MIN_QUALITY);
+            } else if self.scale > Self::MIN_SCALE {
+                self.scale = (self.scale * 0.75).max(Self::MIN_SCALE);
+            } else if self.skip < Self::MAX_SKIP {
+                self.skip += 1;
+            }
+        } else if self.measured_bitrate < target * 7 / 10 {
+            // Room left: restore frame rate, then resolution, then quality
+            if self.skip > 0 {
+                self.skip -= 1;
+            } else if self.scale < 1.0 {
+                self.scale = (self.scale / 0.75).min(1.0);
+            } else if self.quality < self.max_quality {
+                self.quality = (self.quality + 5).min(self.max_quality);
+            }
+        } else {
+            return;
+        }
+        tracing::debug!("Rate control: {}", self.to_json());
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "target_bitrate": self.target_bitrate,
+            "effective_target_bitrate": self.effective_target(),
+            "measured_bitrate": self.measured_bitrate,
+            "jpeg_quality": self.quality,
+            "scale": self.scale,
+            "skip": self.skip,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds a window of `bitrate` to the rate control.
+    fn measure(rate_control: &mut RateControl, bitrate: u64) {
+        rate_control.measured_bitrate = bitrate;
+        rate_control.adjust();
+    }
+
+    #[test]
+    fn rate_control_degrades_quality_then_scale_then_frame_rate() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        for quality in [70, 60, 50, 40, 30] {
+            measure(&mut rate_control, 2_000_000);
+            assert_eq!(rate_control.quality, quality);
+            assert_eq!(rate_control.scale, 1.0);
+        }
+        for scale in [0.75, 0.5625, 0.421875, 0.31640625, RateControl::MIN_SCALE] {
+            measure(&mut rate_control, 2_000_000);
+            assert_eq!(rate_control.quality, RateControl::MIN_QUALITY);
+            assert_eq!(rate_control.scale, scale);
+            assert_eq!(rate_control.skip, 0);
+        }
+        for skip in 1..=RateControl::MAX_SKIP {
+            measure(&mut rate_control, 2_000_000);
+            assert_eq!(rate_control.skip, skip);
+        }
+        // Nothing left to degrade
+        measure(&mut rate_control, 2_000_000);
+        assert_eq!(rate_control.skip, RateControl::MAX_SKIP);
+    }
+
+    #[test]
+    fn rate_control_restores_frame_rate_then_scale_then_quality() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        rate_control.quality = 50;
+        rate_control.scale = 0.5625;
+        rate_control.skip = 2;
+
+        measure(&mut rate_control, 100_000);
+        measure(&mut rate_control, 100_000);
+        assert_eq!(rate_control.skip, 0);
+        assert_eq!(rate_control.scale, 0.5625);
+        measure(&mut rate_control, 100_000);
+        measure(&mut rate_control, 100_000);
+        assert_eq!(rate_control.scale, 1.0);
+        assert_eq!(rate_control.quality, 50);
+        for _ in 0..10 {
+            measure(&mut rate_control, 100_000);
+        }
+        assert_eq!(rate_control.quality, 80);
+    }
+
+    #[test]
+    fn rate_control_keeps_decisions_near_target() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        rate_control.quality = 50;
+        for bitrate in [700_000, 1_000_000, 1_100_000] {
+            measure(&mut rate_control, bitrate);
+            assert_eq!(rate_control.quality, 50);
+        }
+    }
+
+    #[test]
+    fn rate_control_follows_feedback() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        rate_control.measured_bitrate = 800_000;
+
+        // A higher bitrate than the target does not raise it
+        rate_control.on_feedback(&json!({ "bitrate": 2_000_000 }));
+        assert_eq!(rate_control.effective_target(), 1_000_000);
+        rate_control.on_feedback(&json!({ "bitrate": 500_000 }));
+        assert_eq!(rate_control.effective_target(), 500_000);
+
+        // Losses lower the target from the measured bitrate, small ones are ignored
+        rate_control.on_feedback(&json!({ "loss": 0.5 }));
+        assert_eq!(rate_control.effective_target(), 400_000);
+        rate_control.on_feedback(&json!({ "loss": 0.01 }));
+        assert_eq!(rate_control.effective_target(), 400_000);
+        rate_control.on_feedback(&json!({ "foo": 1 }));
+        assert_eq!(rate_control.effective_target(), 400_000);
+
+        // Feedback expires
+        rate_control.feedback_bitrate = Some((
+            100_000,
+            Instant::now() - RateControl::FEEDBACK_VALIDITY - Duration::from_secs(1),
+        ));
+        assert_eq!(rate_control.effective_target(), 1_000_000);
+    }
+
+    #[test]
+    fn rate_control_skips_frames() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        assert!((0..5).all(|_| !rate_control.skip_frame()));
+
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        rate_control.skip = 2;
+        let skipped = (0..6)
+            .map(|_| rate_control.skip_frame())
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, [false, true, true, false, true, true]);
+    }
+
+    #[test]
+    fn rate_control_caps_quality() {
+        let mut rate_control = RateControl::new(1_000_000, 80);
+        rate_control.set_max_quality(60);
+        assert_eq!(rate_control.quality, 60);
+        rate_control.set_max_quality(90);
+        assert_eq!(rate_control.quality, 60);
+    }
+}
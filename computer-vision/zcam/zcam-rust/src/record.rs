@@ -0,0 +1,201 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::path::PathBuf;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use tokio::select;
+use zcam::{
+    now_us,
+    params::{params_loop, Params, StageParams},
+    segment::{Record, SegmentWriter},
+    BadFrames, FrameMeta,
+};
+use zenoh::{config::Config, qos::CongestionControl, Session};
+
+const STAGE: &str = "zrecord";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, congestion_ctrl) = parse_args(&args);
+
+    let params = Params::new(RecordParams {
+        segment_duration: args.segment_duration,
+    })
+    .expect("Invalid parameters");
+
+    let sink = match (&args.output, &args.key_storage) {
+        (Some(dir), None) => RecordSink::Segments(
+            SegmentWriter::new(dir, args.segment_duration * 1_000_000)
+                .expect("Unable to create the recording directory"),
+        ),
+        (None, Some(key)) => RecordSink::Storage(key.clone()),
+        _ => unreachable!("clap ensures exactly one of --output and --key-storage is given"),
+    };
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), sink, congestion_ctrl, params.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+#[command(group(clap::ArgGroup::new("sink").required(true).args(["output", "key_storage"])))]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the frames to record (preferably encoded, e.g. the output of `zencode`)
+    #[arg(short, long, default_value = "demo/zcam/encoded")]
+    key_sub: String,
+
+    /// Directory to write the segment files in
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Key prefix to put the frames on, for a Zenoh storage, as `<key_storage>/<seq>` samples
+    #[arg(long)]
+    key_storage: Option<String>,
+
+    /// Duration of a segment file, in seconds
+    #[arg(long, default_value = "60")]
+    segment_duration: u64,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(args: &Args) -> (Config, zenoh::qos::CongestionControl) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+
+    (c, congestion_control)
+}
+
+/// Runtime parameters of `zrecord`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct RecordParams {
+    /// Duration of a segment file, in seconds
+    segment_duration: u64,
+}
+
+impl StageParams for RecordParams {
+    fn validate(&self) -> Result<(), String> {
+        if !(1..=86_400).contains(&self.segment_duration) {
+            return Err("segment_duration must be between 1 and 86400 seconds".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Where the frames are recorded.
+enum RecordSink {
+    Segments(SegmentWriter),
+    Storage(String),
+}
+
+/// Processing loop that subscribes to frames and records them with their capture timestamp.
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    mut sink: RecordSink,
+    congestion_ctrl: CongestionControl,
+    params: Params<RecordParams>,
+) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        // Receive sample with frame
+        let sample = sub.recv_async().await.unwrap();
+
+        // Decode frame metadata
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+
+        let result = match &mut sink {
+            RecordSink::Segments(writer) => {
+                writer.set_segment_duration(params.get().segment_duration * 1_000_000);
+                let timestamp = match meta.raw().trace().capture_timestamp {
+                    0 => now_us(),
+                    timestamp => timestamp,
+                };
+                let record = Record {
+                    timestamp,
+                    attachment: sample.attachment().unwrap().to_bytes().to_vec(),
+                    payload: sample.payload().to_bytes().to_vec(),
+                };
+                // Flush each frame so that zplay can read the frames being recorded
+                writer
+                    .write(&record)
+                    .and_then(|()| writer.flush())
+                    .map_err(zenoh::Error::from)
+            }
+            RecordSink::Storage(key) => {
+                session
+                    .put(
+                        format!("{key}/{:020}", meta.raw().trace().seq),
+                        sample.payload().clone(),
+                    )
+                    .attachment(sample.attachment().cloned())
+                    .congestion_control(congestion_ctrl)
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to record frame: {e}");
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Event-triggered recording of `zclip`: the clips start with a pre-roll of the frames received before
+//! the first event, and last until a post-roll after the last one.
+
+use std::collections::VecDeque;
+
+use crate::params::StageParams;
+
+/// Maximum pre-roll and post-roll, in milliseconds.
+pub const MAX_ROLL: u64 = 3_600_000;
+
+/// Runtime parameters of `zclip`. Roll durations apply from the next motion event.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct ClipParams {
+    /// Duration recorded before the first motion event, in milliseconds
+    pub pre_roll: u64,
+    /// Duration recorded after the last motion event, in milliseconds
+    pub post_roll: u64,
+    /// Quality of the Jpeg encoding of raw frames
+    pub jpeg_quality: i32,
+}
+
+impl StageParams for ClipParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.pre_roll > MAX_ROLL || self.post_roll > MAX_ROLL {
+            return Err(format!(
+                "pre_roll and post_roll must be at most {MAX_ROLL} ms"
+            ));
+        }
+        if !(0..=100).contains(&self.jpeg_quality) {
+            return Err("jpeg_quality must be between 0 and 100".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What to do with a frame given to [`ClipRecorder::on_frame`].
+#[derive(Debug, PartialEq)]
+pub enum ClipFrame<T> {
+    /// The frame belongs to the clip being recorded
+    Record { clip: String, frame: T },
+    /// The frame was kept for the pre-roll of the next clip, after the end of the `ended` clip if any
+    Buffered { ended: Option<String> },
+}
+
+/// Decides which frames go to which clip. Timestamps and durations are in microseconds.
+#[derive(Debug)]
+pub struct ClipRecorder<T> {
+    // Frames of the pre-roll and their timestamp, oldest first
+    buffer: VecDeque<(u64, T)>,
+    // Name of the clip being recorded and time until which it is recorded
+    recording: Option<(String, u64)>,
+}
+
+impl<T> Default for ClipRecorder<T> {
+    fn default() -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            recording: None,
+        }
+    }
+}
+
+impl<T> ClipRecorder<T> {
+    /// Accounts for an event at `timestamp`, that extends the clip being recorded to `post_roll` after it.
+    /// Without clip being recorded, starts a new one and returns its name along with its pre-roll.
+    pub fn on_event(&mut self, timestamp: u64, post_roll: u64) -> Option<(String, Vec<T>)> {
+        let until = timestamp.saturating_add(post_roll);
+        match &mut self.recording {
+            Some((_, recorded_until)) => {
+                *recorded_until = (*recorded_until).max(until);
+                None
+            }
+            None => {
+                let clip = format!("clip-{timestamp}");
+                self.recording = Some((clip.clone(), until));
+                Some((
+                    clip,
+                    self.buffer.drain(..).map(|(_, frame)| frame).collect(),
+                ))
+            }
+        }
+    }
+
+    /// Accounts for a frame at `timestamp`: it goes to the clip being recorded if any, or is kept for the
+    /// pre-roll of the next clip along with the frames of the last `pre_roll`.
+    pub fn on_frame(&mut self, timestamp: u64, frame: T, pre_roll: u64) -> ClipFrame<T> {
+        let ended = match self.recording.take() {
+            Some((clip, until)) if timestamp <= until => {
+                self.recording = Some((clip.clone(), until));
+                return ClipFrame::Record { clip, frame };
+            }
+            ended => ended.map(|(clip, _)| clip),
+        };
+
+        self.buffer.push_back((timestamp, frame));
+        while self
+            .buffer
+            .front()
+            .is_some_and(|(oldest, _)| oldest.saturating_add(pre_roll) < timestamp)
+        {
+            self.buffer.pop_front();
+        }
+        ClipFrame::Buffered { ended }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(clip: &str, frame: u64) -> ClipFrame<u64> {
+        ClipFrame::Record {
+            clip: clip.to_string(),
+            frame,
+        }
+    }
+
+    #[test]
+    fn clips_start_with_the_pre_roll() {
+        let mut recorder = ClipRecorder::default();
+        for timestamp in [0, 100, 200, 300] {
+            assert_eq!(
+                recorder.on_frame(timestamp, timestamp, 150),
+                ClipFrame::Buffered { ended: None }
+            );
+        }
+        let (clip, pre_roll) = recorder.on_event(350, 100).unwrap();
+        assert_eq!(clip, "clip-350");
+        assert_eq!(pre_roll, [200, 300]);
+    }
+
+    #[test]
+    fn clips_last_until_the_post_roll_of_the_last_event() {
+        let mut recorder = ClipRecorder::default();
+        assert_eq!(recorder.on_event(1000, 500).unwrap().1, Vec::<u64>::new());
+        assert_eq!(recorder.on_frame(1200, 1200, 0), record("clip-1000", 1200));
+        // Events during the clip extend it, but never shorten it
+        assert_eq!(recorder.on_event(1400, 500), None);
+        assert_eq!(recorder.on_event(1450, 0), None);
+        assert_eq!(recorder.on_frame(1900, 1900, 0), record("clip-1000", 1900));
+        assert_eq!(
+            recorder.on_frame(1901, 1901, 10),
+            ClipFrame::Buffered {
+                ended: Some("clip-1000".to_string())
+            }
+        );
+        assert_eq!(
+            recorder.on_frame(1902, 1902, 10),
+            ClipFrame::Buffered { ended: None }
+        );
+
+        // The next clip starts with the frames buffered since
+        let (clip, pre_roll) = recorder.on_event(1902, 500).unwrap();
+        assert_eq!(clip, "clip-1902");
+        assert_eq!(pre_roll, [1901, 1902]);
+    }
+
+    #[test]
+    fn clip_params_bound_the_rolls_and_quality() {
+        let params = |pre_roll, post_roll, jpeg_quality| ClipParams {
+            pre_roll,
+            post_roll,
+            jpeg_quality,
+        };
+        assert!(params(0, MAX_ROLL, 100).validate().is_ok());
+        assert!(params(MAX_ROLL + 1, 0, 90).validate().is_err());
+        assert!(params(0, MAX_ROLL + 1, 90).validate().is_err());
+        assert!(params(2000, 3000, 101).validate().is_err());
+        assert!(params(2000, 3000, -1).validate().is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Renditions of the frames published by `zencode`, given as `<name>:<scale>:<jpeg_quality>`.
+
+/// A rendition of the encoded frames, published on its own key.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rendition {
+    /// Name of the rendition, the last chunk of its key
+    pub name: String,
+    /// Downscaling factor of the frames, in ]0, 1]
+    pub scale: f64,
+    pub jpeg_quality: i32,
+}
+
+impl std::str::FromStr for Rendition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid rendition '{s}', expected <name>:<scale>:<jpeg_quality>");
+        let mut parts = s.split(':');
+        let (Some(name), Some(scale), Some(jpeg_quality), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(err());
+        };
+        let scale: f64 = scale.parse().map_err(|_| err())?;
+        let jpeg_quality: i32 = jpeg_quality.parse().map_err(|_| err())?;
+        if name.is_empty() || !(scale > 0.0 && scale <= 1.0) || !(0..=100).contains(&jpeg_quality) {
+            return Err(err());
+        }
+        Ok(Rendition {
+            name: name.to_string(),
+            scale,
+            jpeg_quality,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendition_parses_name_scale_and_quality() {
+        assert_eq!(
+            "low:0.5:60".parse(),
+            Ok(Rendition {
+                name: "low".to_string(),
+                scale: 0.5,
+                jpeg_quality: 60,
+            })
+        );
+        assert!("full:1:100".parse::<Rendition>().is_ok());
+    }
+
+    #[test]
+    fn rendition_rejects_invalid_renditions() {
+        for rendition in [
+            "",
+            "low",
+            "low:0.5",
+            "low:0.5:60:1",
+            ":0.5:60",
+            "low:0:60",
+            "low:1.5:60",
+            "low:-0.5:60",
+            "low:NaN:60",
+            "low:0.5:101",
+            "low:0.5:-1",
+            "low:half:60",
+        ] {
+            assert!(
+                rendition.parse::<Rendition>().is_err(),
+                "'{}' was accepted",
+                rendition
+            );
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Segment files storing recorded frames, as written by `zrecord` and read by `zplay`.
+//!
+//! A recording is a directory of segment files named after the timestamp of their first frame
+//! (`<timestamp>.zseg`). Each segment starts with the [`MAGIC`] bytes followed by records made of:
+//! - the timestamp of the frame, in microseconds since the UNIX epoch (u64, little endian)
+//! - the length of the attachment (u32, little endian)
+//! - the length of the payload (u32, little endian)
+//! - the attachment (the encoded [`FrameMeta`](crate::FrameMeta)) and the payload of the frame
+
+use std::{
+    convert::TryInto,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+pub const MAGIC: &[u8; 8] = b"ZCAMSEG1";
+pub const EXTENSION: &str = "zseg";
+/// Maximum length of the attachment of a record, that only holds the metadata of its frame.
+pub const MAX_ATTACHMENT_LEN: usize = 1 << 20;
+
+/// A recorded frame.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub timestamp: u64,
+    pub attachment: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+/// Writes records to a recording directory, starting a new segment every `segment_duration` microseconds.
+pub struct SegmentWriter {
+    dir: PathBuf,
+    segment_duration: u64,
+    current: Option<(u64, BufWriter<File>)>,
+}
+
+impl SegmentWriter {
+    pub fn new(dir: impl Into<PathBuf>, segment_duration: u64) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            segment_duration,
+            current: None,
+        })
+    }
+
+    /// Changes the duration of the segments, in microseconds, the current one included.
+    pub fn set_segment_duration(&mut self, segment_duration: u64) {
+        self.segment_duration = segment_duration;
+    }
+
+    pub fn write(&mut self, record: &Record) -> std::io::Result<()> {
+        let rotate = match &self.current {
+            Some((start, _)) => record.timestamp >= start.saturating_add(self.segment_duration),
+            None => true,
+        };
+        if rotate {
+            self.flush()?;
+            let path = self
+                .dir
+                .join(format!("{:020}.{EXTENSION}", record.timestamp));
+            let mut writer = BufWriter::new(File::create(path)?);
+            writer.write_all(MAGIC)?;
+            self.current = Some((record.timestamp, writer));
+        }
+
+        let (_, writer) = self.current.as_mut().unwrap();
+        writer.write_all(&record.timestamp.to_le_bytes())?;
+        writer.write_all(&(record.attachment.len() as u32).to_le_bytes())?;
+        writer.write_all(&(record.payload.len() as u32).to_le_bytes())?;
+        writer.write_all(&record.attachment)?;
+        writer.write_all(&record.payload)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.current {
+            Some((_, writer)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the segments of a recording directory, with the timestamp of their first frame, in time order.
+pub fn list_segments(dir: &Path) -> std::io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+        .filter_map(|path| {
+            let start = path.file_stem()?.to_str()?.parse().ok()?;
+            Some((start, path))
+        })
+        .collect::<Vec<_>>();
+    segments.sort();
+    Ok(segments)
+}
+
+/// Reads the records of a segment for which `filter` returns true, skipping over the payload of the others.
+/// A truncated last record (e.g. if the recorder was killed) ends the segment, as does a record
+/// longer than the rest of the file.
+pub fn read_segment(
+    path: &Path,
+    mut filter: impl FnMut(u64) -> bool,
+) -> std::io::Result<Vec<Record>> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is not a zcam segment", path.display()),
+        ));
+    }
+
+    let mut records = Vec::new();
+    let mut header = [0u8; 16];
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let timestamp = u64::from_le_bytes(header[0..8].try_into().unwrap());
+        let attachment_len = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let payload_len = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+
+        if attachment_len > MAX_ATTACHMENT_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Record of {} has an attachment of {attachment_len} bytes",
+                    path.display()
+                ),
+            ));
+        }
+        // The lengths are checked against the file before allocating anything
+        let record_len = (attachment_len + payload_len) as u64;
+        if record_len > file_len.saturating_sub(reader.stream_position()?) {
+            break;
+        }
+
+        if !filter(timestamp) {
+            reader.seek(SeekFrom::Current(record_len as i64))?;
+            continue;
+        }
+
+        let mut attachment = vec![0u8; attachment_len];
+        let mut payload = vec![0u8; payload_len];
+        reader.read_exact(&mut attachment)?;
+        reader.read_exact(&mut payload)?;
+        records.push(Record {
+            timestamp,
+            attachment,
+            payload,
+        });
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for the recording of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zcam-segment-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(timestamp: u64) -> Record {
+        Record {
+            timestamp,
+            attachment: vec![1, 2, 3],
+            payload: vec![timestamp as u8; 10],
+        }
+    }
+
+    /// Writes records at `timestamps` in a single segment and returns its path.
+    fn write_segment(dir: &Path, timestamps: &[u64]) -> PathBuf {
+        let mut writer = SegmentWriter::new(dir, u64::MAX).unwrap();
+        for &timestamp in timestamps {
+            writer.write(&record(timestamp)).unwrap();
+        }
+        writer.flush().unwrap();
+        let segments = list_segments(dir).unwrap();
+        assert_eq!(segments.len(), 1);
+        segments[0].1.clone()
+    }
+
+    fn truncate(path: &Path, len: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_len(len)
+            .unwrap();
+    }
+
+    fn timestamps(records: &[Record]) -> Vec<u64> {
+        records.iter().map(|r| r.timestamp).collect()
+    }
+
+    /// Size of a record of `record()` in a segment.
+    const RECORD_LEN: u64 = 16 + 3 + 10;
+
+    #[test]
+    fn segments_rotate_and_read_back() {
+        let dir = test_dir("rotate");
+        let mut writer = SegmentWriter::new(&dir, 10).unwrap();
+        for timestamp in [100, 105, 110, 115, 125] {
+            writer.write(&record(timestamp)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let segments = list_segments(&dir).unwrap();
+        let starts = segments.iter().map(|(start, _)| *start).collect::<Vec<_>>();
+        assert_eq!(starts, [100, 110, 125]);
+        let records = read_segment(&segments[1].1, |_| true).unwrap();
+        assert_eq!(timestamps(&records), [110, 115]);
+        assert_eq!(records[1].attachment, [1, 2, 3]);
+        assert_eq!(records[1].payload, [115; 10]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_segment_skips_filtered_records() {
+        let dir = test_dir("filter");
+        let path = write_segment(&dir, &[1, 2, 3, 4]);
+        let records = read_segment(&path, |timestamp| timestamp % 2 == 0).unwrap();
+        assert_eq!(timestamps(&records), [2, 4]);
+        assert_eq!(records[0].payload, [2; 10]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_segment_stops_at_truncated_record() {
+        let dir = test_dir("truncated");
+        let path = write_segment(&dir, &[1, 2, 3]);
+        let len = MAGIC.len() as u64 + 3 * RECORD_LEN;
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        // In the payload, the attachment and the header of the last record
+        for cut in [1, 10 + 1, 10 + 3 + 1] {
+            truncate(&path, len - cut);
+            let records = read_segment(&path, |_| true).unwrap();
+            assert_eq!(timestamps(&records), [1, 2], "cut of {} bytes", cut);
+            // The skipped truncated record is not returned either
+            let records = read_segment(&path, |timestamp| timestamp != 1).unwrap();
+            assert_eq!(timestamps(&records), [2], "cut of {} bytes", cut);
+        }
+
+        // Only the magic bytes
+        truncate(&path, MAGIC.len() as u64);
+        assert!(read_segment(&path, |_| true).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_segment_checks_lengths_before_allocating() {
+        let dir = test_dir("lengths");
+        let path = write_segment(&dir, &[1, 2]);
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = MAGIC.len() + RECORD_LEN as usize;
+
+        // A corrupt payload length, far beyond the end of the file
+        bytes[last + 12..last + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(timestamps(&read_segment(&path, |_| true).unwrap()), [1]);
+        assert!(read_segment(&path, |_| false).unwrap().is_empty());
+
+        // A corrupt attachment length
+        bytes[last + 8..last + 12].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let err = read_segment(&path, |_| true).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_segment_rejects_other_files() {
+        let dir = test_dir("invalid");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("1.{EXTENSION}"));
+        std::fs::write(&path, b"NOTASEGMENT").unwrap();
+        let err = read_segment(&path, |_| true).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Shorter than the magic bytes
+        std::fs::write(&path, b"ZCAM").unwrap();
+        assert!(read_segment(&path, |_| true).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! SHM pool of a stage: allocation policy when the pool is full, and metrics of its use.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use zenoh::{
+    config::Config,
+    shm::{BlockOn, Defragment, GarbageCollect, PosixShmProviderBackend, ShmProvider, ZShmMut},
+    Session, Wait,
+};
+
+/// Configuration key of the size of the SHM pool of the transport.
+const POOL_SIZE_KEY: &str = "transport/shared_memory/transport_optimization/pool_size";
+
+/// Default size of the SHM pool of the transport, in bytes.
+pub const DEFAULT_POOL_SIZE: usize = 16 * 1024 * 1024;
+
+/// What to do when the SHM pool is full, i.e. when subscribers hold the frames for too long.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ShmPolicy {
+    /// Wait for buffers to be released
+    #[default]
+    Block,
+    /// Drop the frame being processed, the newest one, and go on with the next one.
+    /// The frames already published are left to their subscribers: their buffers are mapped by the
+    /// subscribers, possibly in other processes, and can only be reused once all of them released them,
+    /// so the oldest frames cannot be reclaimed to make room for the newest one.
+    DropNewest,
+}
+
+/// Command line arguments of the SHM pool, to be flattened in the arguments of a stage.
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ShmArgs {
+    /// Size of the SHM pool, in bytes [default: 16 MiB]
+    #[arg(long)]
+    pub shm_pool_size: Option<usize>,
+
+    /// What to do when the SHM pool is full
+    #[arg(long, value_enum, default_value = "block")]
+    pub shm_policy: ShmPolicy,
+}
+
+impl ShmArgs {
+    /// Sets the size of the SHM pool in the Zenoh configuration of the stage.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(size) = self.shm_pool_size {
+            if let Err(e) = config.insert_json5(POOL_SIZE_KEY, &size.to_string()) {
+                tracing::error!("Unable to set the SHM pool size: {e}");
+            }
+        }
+    }
+}
+
+/// Counters of the use of a SHM pool.
+#[derive(Debug, Default)]
+struct ShmMetrics {
+    allocations: AtomicU64,
+    alloc_wait_us: AtomicU64,
+    // Since the last report
+    max_alloc_wait_us: AtomicU64,
+    dropped: AtomicU64,
+    in_place: AtomicU64,
+    copies: AtomicU64,
+}
+
+/// The SHM provider of a stage, allocating according to its [`ShmPolicy`] and keeping metrics.
+#[derive(Clone)]
+pub struct ShmPool {
+    provider: Arc<ShmProvider<PosixShmProviderBackend>>,
+    policy: ShmPolicy,
+    pool_size: usize,
+    metrics: Arc<ShmMetrics>,
+}
+
+impl ShmPool {
+    /// Returns the pool of the transport SHM provider of `session`, configured with `args`
+    /// (which must have been applied to the configuration of the session).
+    pub async fn new(session: &Session, args: &ShmArgs) -> Self {
+        let provider = session
+            .get_shm_provider()
+            .await
+            .expect("Failed to get transport SHM provider");
+        Self {
+            provider,
+            policy: args.shm_policy,
+            pool_size: args.shm_pool_size.unwrap_or(DEFAULT_POOL_SIZE),
+            metrics: Arc::default(),
+        }
+    }
+
+    /// Allocates a SHM buffer of `len` bytes. When the pool is full, waits for buffers to be released with
+    /// [`ShmPolicy::Block`], or returns `None` with [`ShmPolicy::DropNewest`] for the frame to be dropped.
+    pub async fn alloc(&self, len: usize) -> zenoh::Result<Option<ZShmMut>> {
+        let start = Instant::now();
+        let result = match self.policy {
+            ShmPolicy::Block => unsafe {
+                self.provider
+                    .alloc(len)
+                    .with_unsafe_policy::<BlockOn<Defragment<GarbageCollect>>>()
+                    .await
+            },
+            ShmPolicy::DropNewest => unsafe {
+                self.provider
+                    .alloc(len)
+                    .with_unsafe_policy::<Defragment<GarbageCollect>>()
+                    .wait()
+            },
+        };
+        self.record_alloc(start.elapsed());
+
+        match result {
+            Ok(buf) => Ok(Some(buf)),
+            Err(e) if self.policy == ShmPolicy::DropNewest => {
+                tracing::debug!("SHM pool is full ({e:?}), dropping frame");
+                self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(e) => Err(format!("Failed to allocate SHM buffer: {e:?}").into()),
+        }
+    }
+
+    fn record_alloc(&self, wait: Duration) {
+        let wait = wait.as_micros() as u64;
+        self.metrics.allocations.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .alloc_wait_us
+            .fetch_add(wait, Ordering::Relaxed);
+        self.metrics
+            .max_alloc_wait_us
+            .fetch_max(wait, Ordering::Relaxed);
+    }
+
+    /// Records whether a frame was processed in-place in SHM, or had to be copied first.
+    pub(crate) fn record_processing(&self, in_place: bool) {
+        let counter = match in_place {
+            true => &self.metrics.in_place,
+            false => &self.metrics.copies,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Metrics of the pool since the start of the stage, as JSON. The maximum allocation wait time
+    /// is the one since the previous call.
+    pub fn metrics(&self) -> Value {
+        let m = &self.metrics;
+        let available = self.provider.available();
+        let allocations = m.allocations.load(Ordering::Relaxed);
+        json!({
+            "pool_size": self.pool_size,
+            "available": available,
+            "occupancy": 1.0 - available as f64 / self.pool_size.max(1) as f64,
+            "allocations": allocations,
+            "mean_alloc_wait_us": m.alloc_wait_us.load(Ordering::Relaxed) / allocations.max(1),
+            "max_alloc_wait_us": m.max_alloc_wait_us.swap(0, Ordering::Relaxed),
+            "dropped": m.dropped.load(Ordering::Relaxed),
+            "in_place": m.in_place.load(Ordering::Relaxed),
+            "copies": m.copies.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Publishes the metrics of `pool` on `key_metrics`, as JSON, every `period`.
+pub async fn metrics_loop(session: &Session, key_metrics: String, pool: ShmPool, period: Duration) {
+    // Declare publisher for metrics
+    let publ = session.declare_publisher(&key_metrics).await.unwrap();
+
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        if let Err(e) = publ.put(pool.metrics().to_string()).await {
+            tracing::error!("Failed to publish SHM metrics: {e}");
+        }
+    }
+}
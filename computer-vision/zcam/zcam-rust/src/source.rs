@@ -0,0 +1,429 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Frame sources for `zcapture`: camera devices, video files, image folders and synthetic test patterns.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use opencv::{
+    core::{Point, Rect, Scalar, Size, CV_8UC3},
+    imgcodecs, imgproc,
+    prelude::*,
+    videoio,
+};
+
+/// Description of a frame source, as given on the command line.
+///
+/// Accepted forms are:
+/// - `device:<index|path>`, or a bare device index (e.g. `0`) or `/dev/...` path: a camera device
+/// - `video:<path>`, or a bare path to a file: a video file
+/// - `images:<dir>`, or a bare path to a directory: the images of a folder, in name order
+/// - `pattern[:<width>x<height>]`: a synthetic test pattern with an embedded frame counter
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SourceSpec {
+    DeviceIndex(i32),
+    DevicePath(String),
+    Video(String),
+    Images(String),
+    Pattern { width: i32, height: i32 },
+}
+
+impl FromStr for SourceSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("device", device)) => Ok(match device.parse() {
+                Ok(index) => SourceSpec::DeviceIndex(index),
+                Err(_) => SourceSpec::DevicePath(device.to_string()),
+            }),
+            Some(("video", path)) => Ok(SourceSpec::Video(path.to_string())),
+            Some(("images", path)) => Ok(SourceSpec::Images(path.to_string())),
+            Some(("pattern", size)) => {
+                let (width, height) = size
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|(width, height)| *width > 0 && *height > 0)
+                    .ok_or_else(|| {
+                        format!("Invalid pattern size '{size}', expected <width>x<height>")
+                    })?;
+                Ok(SourceSpec::Pattern { width, height })
+            }
+            _ if s == "pattern" => Ok(SourceSpec::Pattern {
+                width: 640,
+                height: 480,
+            }),
+            _ => {
+                if let Ok(index) = s.parse() {
+                    Ok(SourceSpec::DeviceIndex(index))
+                } else if s.starts_with("/dev/") {
+                    Ok(SourceSpec::DevicePath(s.to_string()))
+                } else if Path::new(s).is_dir() {
+                    Ok(SourceSpec::Images(s.to_string()))
+                } else {
+                    Ok(SourceSpec::Video(s.to_string()))
+                }
+            }
+        }
+    }
+}
+
+impl Display for SourceSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceSpec::DeviceIndex(index) => write!(f, "device:{index}"),
+            SourceSpec::DevicePath(path) => write!(f, "device:{path}"),
+            SourceSpec::Video(path) => write!(f, "video:{path}"),
+            SourceSpec::Images(path) => write!(f, "images:{path}"),
+            SourceSpec::Pattern { width, height } => write!(f, "pattern:{width}x{height}"),
+        }
+    }
+}
+
+/// An opened frame source.
+pub enum FrameSource {
+    Capture {
+        cap: videoio::VideoCapture,
+        rewindable: bool,
+        looping: bool,
+    },
+    Images {
+        files: Vec<PathBuf>,
+        next: usize,
+        looping: bool,
+    },
+    Pattern {
+        width: i32,
+        height: i32,
+        count: u64,
+    },
+}
+
+impl FrameSource {
+    /// Opens the source. With `looping`, video files and image folders restart from the beginning
+    /// once exhausted instead of ending.
+    pub fn open(spec: &SourceSpec, looping: bool) -> zenoh::Result<Self> {
+        match spec {
+            SourceSpec::DeviceIndex(index) => Self::open_capture(
+                videoio::VideoCapture::new(*index, videoio::CAP_ANY)?,
+                spec,
+                false,
+                false,
+            ),
+            SourceSpec::DevicePath(path) => Self::open_capture(
+                videoio::VideoCapture::from_file(path, videoio::CAP_ANY)?,
+                spec,
+                false,
+                false,
+            ),
+            SourceSpec::Video(path) => Self::open_capture(
+                videoio::VideoCapture::from_file(path, videoio::CAP_ANY)?,
+                spec,
+                true,
+                looping,
+            ),
+            SourceSpec::Images(dir) => {
+                let mut files = std::fs::read_dir(dir)?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| {
+                        path.extension()
+                            .and_then(|ext| ext.to_str())
+                            .is_some_and(|ext| {
+                                imgcodecs::have_image_reader(&format!("x.{ext}")).unwrap_or(false)
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                if files.is_empty() {
+                    return Err(format!("No image found in {dir}").into());
+                }
+                files.sort();
+                Ok(FrameSource::Images {
+                    files,
+                    next: 0,
+                    looping,
+                })
+            }
+            SourceSpec::Pattern { width, height } => Ok(FrameSource::Pattern {
+                width: *width,
+                height: *height,
+                count: 0,
+            }),
+        }
+    }
+
+    fn open_capture(
+        cap: videoio::VideoCapture,
+        spec: &SourceSpec,
+        rewindable: bool,
+        looping: bool,
+    ) -> zenoh::Result<Self> {
+        if !cap.is_opened()? {
+            return Err(format!("Unable to open {spec}").into());
+        }
+        Ok(FrameSource::Capture {
+            cap,
+            rewindable,
+            looping,
+        })
+    }
+
+    /// Reads the next frame into `frame`.
+    ///
+    /// If `frame` is empty, it is allocated with the layout of the source. Otherwise the frame is written
+    /// in place (resized if needed), so that a `Mat` mapped on a SHM buffer keeps pointing to it.
+    /// Returns `false` once the source is exhausted.
+    pub fn read(&mut self, frame: &mut Mat) -> zenoh::Result<bool> {
+        if !self.grab()? {
+            return Ok(false);
+        }
+        self.retrieve(frame)?;
+        Ok(true)
+    }
+
+    /// Takes the next frame, without decoding it yet, so that frames of several sources can be taken
+    /// at the same time and retrieved afterwards. Returns `false` once the source is exhausted.
+    pub fn grab(&mut self) -> zenoh::Result<bool> {
+        match self {
+            FrameSource::Capture {
+                cap,
+                rewindable,
+                looping,
+            } => {
+                if cap.grab()? {
+                    return Ok(true);
+                }
+                if *rewindable && *looping {
+                    cap.set(videoio::CAP_PROP_POS_FRAMES, 0.0)?;
+                    return Ok(cap.grab()?);
+                }
+                Ok(false)
+            }
+            FrameSource::Images {
+                files,
+                next,
+                looping,
+            } => {
+                if *next >= files.len() {
+                    if !*looping {
+                        return Ok(false);
+                    }
+                    *next = 0;
+                }
+                *next += 1;
+                Ok(true)
+            }
+            FrameSource::Pattern { count, .. } => {
+                *count += 1;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Decodes the frame taken by the last [`grab`](Self::grab) into `frame`, like [`read`](Self::read) does.
+    pub fn retrieve(&mut self, frame: &mut Mat) -> zenoh::Result<()> {
+        match self {
+            FrameSource::Capture { cap, .. } => {
+                cap.retrieve(frame, 0)?;
+                Ok(())
+            }
+            FrameSource::Images { files, next, .. } => {
+                let path = &files[next.saturating_sub(1)];
+                let image = imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_COLOR)?;
+                if image.empty() {
+                    return Err(format!("Unable to read image {}", path.display()).into());
+                }
+                copy_into(&image, frame)?;
+                Ok(())
+            }
+            FrameSource::Pattern {
+                width,
+                height,
+                count,
+            } => {
+                if frame.empty() {
+                    *frame = Mat::new_rows_cols_with_default(
+                        *height,
+                        *width,
+                        CV_8UC3,
+                        Scalar::all(0.0),
+                    )?;
+                }
+                draw_pattern(frame, count.saturating_sub(1))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Copies `image` into `frame`, resizing it if `frame` is already allocated with another size.
+fn copy_into(image: &Mat, frame: &mut Mat) -> opencv::Result<()> {
+    if frame.empty() || frame.size()? == image.size()? {
+        image.copy_to(frame)
+    } else {
+        let size = frame.size()?;
+        imgproc::resize(image, frame, size, 0.0, 0.0, imgproc::INTER_LINEAR)
+    }
+}
+
+/// Draws color bars, a moving square and the frame counter.
+fn draw_pattern(frame: &mut Mat, count: u64) -> opencv::Result<()> {
+    const BARS: [(f64, f64, f64); 7] = [
+        (192.0, 192.0, 192.0),
+        (0.0, 192.0, 192.0),
+        (192.0, 192.0, 0.0),
+        (0.0, 192.0, 0.0),
+        (192.0, 0.0, 192.0),
+        (0.0, 0.0, 192.0),
+        (192.0, 0.0, 0.0),
+    ];
+
+    let Size { width, height } = frame.size()?;
+    let bar_width = width / BARS.len() as i32 + 1;
+    for (i, (b, g, r)) in BARS.iter().enumerate() {
+        imgproc::rectangle(
+            frame,
+            Rect::new(i as i32 * bar_width, 0, bar_width, height),
+            Scalar::new(*b, *g, *r, 0.0),
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )?;
+    }
+
+    // A square moving along the frame makes frozen or repeated frames visible
+    let side = height / 8;
+    let x = (count as i32 * 8) % (width - side).max(1);
+    imgproc::rectangle(
+        frame,
+        Rect::new(x, height / 2 - side / 2, side, side),
+        Scalar::all(255.0),
+        imgproc::FILLED,
+        imgproc::LINE_8,
+        0,
+    )?;
+
+    imgproc::rectangle(
+        frame,
+        Rect::new(0, height - 48, width, 48),
+        Scalar::all(0.0),
+        imgproc::FILLED,
+        imgproc::LINE_8,
+        0,
+    )?;
+    imgproc::put_text(
+        frame,
+        &format!("frame {count}"),
+        Point::new(12, height - 14),
+        imgproc::FONT_HERSHEY_SIMPLEX,
+        1.0,
+        Scalar::all(255.0),
+        2,
+        imgproc::LINE_AA,
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_spec_parses_prefixed_and_bare_forms() {
+        let parse = |s: &str| s.parse::<SourceSpec>().unwrap();
+        assert_eq!(parse("device:2"), SourceSpec::DeviceIndex(2));
+        assert_eq!(
+            parse("device:/dev/video1"),
+            SourceSpec::DevicePath("/dev/video1".to_string())
+        );
+        assert_eq!(parse("0"), SourceSpec::DeviceIndex(0));
+        assert_eq!(
+            parse("/dev/video0"),
+            SourceSpec::DevicePath("/dev/video0".to_string())
+        );
+        assert_eq!(
+            parse("video:clip.mp4"),
+            SourceSpec::Video("clip.mp4".to_string())
+        );
+        assert_eq!(parse("clip.mp4"), SourceSpec::Video("clip.mp4".to_string()));
+        assert_eq!(
+            parse("images:shots"),
+            SourceSpec::Images("shots".to_string())
+        );
+        let dir = std::env::temp_dir().to_string_lossy().to_string();
+        assert_eq!(parse(&dir), SourceSpec::Images(dir.clone()));
+        assert_eq!(
+            parse("pattern"),
+            SourceSpec::Pattern {
+                width: 640,
+                height: 480
+            }
+        );
+        assert_eq!(
+            parse("pattern:320x240"),
+            SourceSpec::Pattern {
+                width: 320,
+                height: 240
+            }
+        );
+    }
+
+    #[test]
+    fn source_spec_rejects_invalid_pattern_sizes() {
+        for spec in [
+            "pattern:",
+            "pattern:640",
+            "pattern:640x",
+            "pattern:0x480",
+            "pattern:-1x480",
+        ] {
+            assert!(spec.parse::<SourceSpec>().is_err(), "{} was accepted", spec);
+        }
+    }
+
+    #[test]
+    fn source_spec_displays_as_it_parses() {
+        for spec in [
+            SourceSpec::DeviceIndex(1),
+            SourceSpec::DevicePath("/dev/video0".to_string()),
+            SourceSpec::Video("clip.mp4".to_string()),
+            SourceSpec::Images("shots".to_string()),
+            SourceSpec::Pattern {
+                width: 320,
+                height: 240,
+            },
+        ] {
+            assert_eq!(spec.to_string().parse::<SourceSpec>().unwrap(), spec);
+        }
+    }
+
+    #[test]
+    fn images_end_or_loop_once_exhausted() {
+        let images = |looping| FrameSource::Images {
+            files: vec![PathBuf::from("a.png"), PathBuf::from("b.png")],
+            next: 0,
+            looping,
+        };
+        let mut source = images(false);
+        let grabbed = (0..3).map(|_| source.grab().unwrap()).collect::<Vec<_>>();
+        assert_eq!(grabbed, [true, true, false]);
+
+        let mut source = images(true);
+        assert!((0..5).all(|_| source.grab().unwrap()));
+        assert!(matches!(source, FrameSource::Images { next: 1, .. }));
+    }
+}
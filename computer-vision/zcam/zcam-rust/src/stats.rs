@@ -0,0 +1,145 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, time::Duration};
+use tokio::select;
+use zcam::{
+    now_us,
+    params::{params_loop, Params, StageParams},
+    trace::StreamStats,
+    FrameMeta,
+};
+use zenoh::{config::Config, Session, Wait};
+
+const STAGE: &str = "zstats";
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let (config, key_sub, period) = parse_args();
+
+    let params = Params::new(StatsParams { period }).expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).wait().unwrap();
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, key_sub.clone(), params.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", key_sub), params) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq, Eq, Hash)]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    #[arg(short, long, default_value = "demo/zcam")]
+    key: String,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Reporting period in milliseconds
+    #[arg(short, long, default_value = "1000")]
+    period: u64,
+}
+
+fn parse_args() -> (Config, String, u64) {
+    let args = Args::parse();
+    let mut c = if let Some(f) = args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+
+    (c, args.key, args.period)
+}
+
+/// Runtime parameters of `zstats`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct StatsParams {
+    /// Reporting period in milliseconds
+    period: u64,
+}
+
+impl StageParams for StatsParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.period == 0 {
+            return Err("period must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Processing loop that subscribes to frames of any stage and periodically reports the FPS,
+/// drop rate and latencies of each matching stream.
+/// Latencies are computed from wall-clock timestamps, so hosts running the stages should have
+/// synchronized clocks.
+/// A change of the reporting period takes effect at the end of the current one.
+async fn process_loop(session: &Session, key_sub: String, params: Params<StatsParams>) {
+    // Declare subscriber for frames
+    let sub = session.declare_subscriber(&key_sub).await.unwrap();
+
+    let mut stats = BTreeMap::<String, StreamStats>::new();
+    let mut period = Duration::from_millis(params.get().period);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    loop {
+        select!(
+            sample = sub.recv_async() => {
+                let sample = sample.unwrap();
+                let received_at = now_us();
+                match FrameMeta::decode(&sample) {
+                    Ok(meta) => stats
+                        .entry(sample.key_expr().to_string())
+                        .or_default()
+                        .add(meta.raw().trace(), received_at),
+                    Err(e) => {
+                        tracing::warn!("Bad frame on {}: {e}", sample.key_expr());
+                        stats.entry(sample.key_expr().to_string()).or_default().bad += 1;
+                    }
+                }
+            },
+            _ = ticker.tick() => {
+                for (key, stream_stats) in stats.iter_mut() {
+                    stream_stats.report(key, period);
+                    stream_stats.reset();
+                }
+                let new_period = Duration::from_millis(params.get().period);
+                if new_period != period {
+                    period = new_period;
+                    ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                }
+            },
+        );
+    }
+}
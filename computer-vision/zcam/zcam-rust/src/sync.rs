@@ -0,0 +1,304 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use clap::Parser;
+use futures::StreamExt;
+use serde_json::json;
+use std::time::Duration;
+
+use opencv::{
+    core::{Rect, Size},
+    imgproc,
+    prelude::*,
+};
+use tokio::select;
+use zcam::{
+    group::{FrameGroup, FrameSynchronizer, GroupedFrame, SyncParams},
+    now_us,
+    params::{params_loop, Params},
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    BadFrames, FrameMeta, PixelFormat, RawFrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    sample::Sample,
+    shm::ZShm,
+    Session,
+};
+
+const STAGE: &str = "zsync";
+
+/// Maximum number of frames of a stream waiting for the frames of the other streams.
+const MAX_PENDING: usize = 8;
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+    if args.key.len() < 2 {
+        panic!("At least two streams are needed, give `-k` once per stream");
+    }
+
+    let params = Params::new(SyncParams {
+        tolerance: args.tolerance,
+    })
+    .expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key.clone(), args.key_pub.clone(), args.output, reliability, congestion_ctrl, params.clone(), shm_pool.clone()) => {}
+        // Config update loop, on the key of the first stream
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key[0]), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key[0]), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+/// How the groups of frames are published.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SyncOutput {
+    /// A raw frame made of the frames of the group side by side, scaled to the height of the first one
+    SideBySide,
+    /// A `FrameGroup` message holding the frames of the group as they are
+    Composite,
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the frames of a stream, given once per stream (e.g. left then right camera)
+    #[arg(short, long, required = true)]
+    key: Vec<String>,
+
+    /// Key to publish the groups of frames on
+    #[arg(long, default_value = "demo/zcam/sync")]
+    key_pub: String,
+
+    /// Maximum difference between the capture timestamps of the frames of a group, in milliseconds
+    #[arg(long, default_value = "15")]
+    tolerance: u64,
+
+    #[arg(long, value_enum, default_value = "side-by-side")]
+    output: SyncOutput,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Composes the raw frames of a group side by side into a new SHM buffer, and returns it along with its metadata.
+async fn side_by_side(
+    frames: &[(FrameMeta, Sample)],
+    timestamp: u64,
+    shm_pool: &ShmPool,
+) -> zenoh::Result<(ZShm, RawFrameMeta)> {
+    // Frames are scaled to the height of the first one
+    let height = frames[0].0.raw().rows();
+    let widths = frames
+        .iter()
+        .map(|(meta, _)| meta.raw().cols() * height / meta.raw().rows().max(1))
+        .collect::<Vec<_>>();
+
+    let mut raw_meta = RawFrameMeta::with_format(height, widths.iter().sum(), PixelFormat::Bgr);
+    raw_meta.set_capture(frames[0].0.raw().trace().seq, timestamp);
+
+    // Allocate SHM buffer for the composed frame
+    let mut shmbuf = shm_pool
+        .alloc(raw_meta.size())
+        .await?
+        .ok_or("SHM pool is full, frame dropped")?;
+
+    // Map opencv Mat into allocated shared memory and copy the frames into it
+    let mut composed = unsafe { raw_meta.mat_mut(shmbuf.as_mut_ptr()) };
+    let mut x = 0;
+    for ((meta, sample), width) in frames.iter().zip(widths) {
+        let FrameMeta::Raw(frame_meta) = meta else {
+            return Err(format!("Unsupported frame meta: {meta}").into());
+        };
+        let contiguous_bytes = sample.payload().to_bytes();
+        let frame = unsafe { frame_meta.mat(contiguous_bytes.as_ptr()) };
+        let mut cell = composed.roi_mut(Rect::new(x, 0, width, height))?;
+        if frame_meta.format() == PixelFormat::Bgr && frame_meta.rows() == height {
+            frame.copy_to(&mut *cell)?;
+        } else {
+            let bgr = frame_meta.to_bgr(&frame)?;
+            imgproc::resize(
+                &bgr,
+                &mut *cell,
+                Size::new(width, height),
+                0.0,
+                0.0,
+                imgproc::INTER_AREA,
+            )?;
+        }
+        x += width;
+    }
+
+    Ok((shmbuf.into(), raw_meta))
+}
+
+/// Processing loop that subscribes to the frames of several streams, groups the frames captured within
+/// the `tolerance` of each other (one per stream) and publishes each group, side by side in a
+/// single raw frame or as a `FrameGroup` message.
+#[allow(clippy::too_many_arguments)]
+async fn process_loop(
+    session: &Session,
+    keys: Vec<String>,
+    key_pub: String,
+    output: SyncOutput,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    params: Params<SyncParams>,
+    shm_pool: ShmPool,
+) {
+    // Declare subscribers for the frames of each stream
+    let mut subs = Vec::new();
+    for key in &keys {
+        subs.push(session.declare_subscriber(key).await.unwrap());
+    }
+    let mut samples = futures::stream::select_all(
+        subs.iter()
+            .enumerate()
+            .map(|(stream, sub)| sub.stream().map(move |sample| (stream, sample)).boxed()),
+    );
+
+    // Declare publisher for the groups of frames
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    let mut synchronizer =
+        FrameSynchronizer::new(keys.len(), params.get().tolerance * 1000, MAX_PENDING);
+    let mut dropped = 0;
+
+    let mut bad_frames = BadFrames::default();
+
+    while let Some((stream, sample)) = samples.next().await {
+        // Decode frame metadata
+        let meta = match FrameMeta::decode(&sample) {
+            Ok(meta) => meta,
+            Err(e) => {
+                bad_frames.skip(e);
+                continue;
+            }
+        };
+        let timestamp = match meta.raw().trace().capture_timestamp {
+            0 => now_us(),
+            timestamp => timestamp,
+        };
+
+        synchronizer.set_tolerance(params.get().tolerance * 1000);
+        let group = synchronizer.push(stream, timestamp, (meta, sample));
+        if synchronizer.dropped() > dropped {
+            dropped = synchronizer.dropped();
+            tracing::debug!("{dropped} frames dropped without matching frames");
+        }
+        let Some((timestamp, frames)) = group else {
+            continue;
+        };
+
+        let result = match output {
+            SyncOutput::SideBySide => match side_by_side(&frames, timestamp, &shm_pool).await {
+                Ok((composed, mut raw_meta)) => {
+                    raw_meta.add_hop(STAGE);
+                    let attachment = FrameMeta::Raw(raw_meta).encode().unwrap();
+                    publ.put(composed).attachment(attachment).await
+                }
+                Err(e) => Err(e),
+            },
+            SyncOutput::Composite => {
+                let group = FrameGroup {
+                    timestamp,
+                    frames: frames
+                        .into_iter()
+                        .map(|(mut meta, sample)| {
+                            meta.raw_mut().add_hop(STAGE);
+                            GroupedFrame {
+                                key: sample.key_expr().to_string(),
+                                meta,
+                                payload: sample.payload().to_bytes().to_vec(),
+                            }
+                        })
+                        .collect(),
+                };
+                match group.encode() {
+                    Ok(payload) => publ.put(payload).await,
+                    Err(e) => Err(e),
+                }
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to publish group of frames: {e}");
+        }
+    }
+}
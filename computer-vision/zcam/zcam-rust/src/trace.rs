@@ -0,0 +1,201 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Statistics of `zstats`, computed from the [`FrameTrace`] of the frames of a stream.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::FrameTrace;
+
+/// Latency accumulator, in microseconds.
+#[derive(Debug, Default)]
+pub struct Latency {
+    sum: u64,
+    max: u64,
+    count: u64,
+}
+
+impl Latency {
+    pub fn add(&mut self, latency: u64) {
+        self.sum += latency;
+        self.max = self.max.max(latency);
+        self.count += 1;
+    }
+
+    pub fn avg_ms(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64 / 1000.0
+        }
+    }
+
+    pub fn max_ms(&self) -> f64 {
+        self.max as f64 / 1000.0
+    }
+}
+
+/// Statistics of a stream over the current reporting period.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    last_seq: Option<u64>,
+    frames: u64,
+    dropped: u64,
+    /// Frames with malformed metadata or payload
+    pub bad: u64,
+    end_to_end: Latency,
+    segments: BTreeMap<String, Latency>,
+}
+
+impl StreamStats {
+    /// Accounts for a frame with `trace` received at `received_at`, in microseconds since the UNIX epoch.
+    pub fn add(&mut self, trace: &FrameTrace, received_at: u64) {
+        // Gaps in sequence numbers are frames dropped somewhere in the pipeline.
+        // A lower sequence number means that the capture was restarted.
+        if let Some(last_seq) = self.last_seq {
+            if trace.seq > last_seq {
+                self.dropped += trace.seq - last_seq - 1;
+            }
+        }
+        self.last_seq = Some(trace.seq);
+        self.frames += 1;
+
+        self.end_to_end
+            .add(received_at.saturating_sub(trace.capture_timestamp));
+
+        let mut previous = ("zcapture", trace.capture_timestamp);
+        for hop in &trace.hops {
+            self.segments
+                .entry(format!("{} -> {}", previous.0, hop.stage))
+                .or_default()
+                .add(hop.timestamp.saturating_sub(previous.1));
+            previous = (hop.stage.as_str(), hop.timestamp);
+        }
+        self.segments
+            .entry(format!("{} -> zstats", previous.0))
+            .or_default()
+            .add(received_at.saturating_sub(previous.1));
+    }
+
+    /// Percentage of the frames of the period that were dropped.
+    pub fn drop_rate(&self) -> f64 {
+        if self.frames + self.dropped == 0 {
+            0.0
+        } else {
+            self.dropped as f64 * 100.0 / (self.frames + self.dropped) as f64
+        }
+    }
+
+    pub fn report(&self, key: &str, period: Duration) {
+        println!(
+            "{key}: {:.1} fps, {} dropped ({:.1}%), {} bad, end-to-end latency avg {:.1} ms / max {:.1} ms",
+            self.frames as f64 / period.as_secs_f64(),
+            self.dropped,
+            self.drop_rate(),
+            self.bad,
+            self.end_to_end.avg_ms(),
+            self.end_to_end.max_ms(),
+        );
+        for (segment, latency) in &self.segments {
+            println!(
+                "    {segment}: avg {:.1} ms / max {:.1} ms",
+                latency.avg_ms(),
+                latency.max_ms()
+            );
+        }
+    }
+
+    /// Resets the counters for the next period, keeping track of the last sequence number.
+    pub fn reset(&mut self) {
+        *self = StreamStats {
+            last_seq: self.last_seq,
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hop;
+
+    fn trace(seq: u64, capture_timestamp: u64, hops: &[(&str, u64)]) -> FrameTrace {
+        FrameTrace {
+            seq,
+            capture_timestamp,
+            hops: hops
+                .iter()
+                .map(|(stage, timestamp)| Hop {
+                    stage: stage.to_string(),
+                    timestamp: *timestamp,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn latency_averages_in_milliseconds() {
+        let mut latency = Latency::default();
+        assert_eq!(latency.avg_ms(), 0.0);
+        latency.add(1000);
+        latency.add(3000);
+        assert_eq!(latency.avg_ms(), 2.0);
+        assert_eq!(latency.max_ms(), 3.0);
+    }
+
+    #[test]
+    fn gaps_in_sequence_numbers_are_dropped_frames() {
+        let mut stats = StreamStats::default();
+        for seq in [1, 2, 5, 6] {
+            stats.add(&trace(seq, 0, &[]), 0);
+        }
+        assert_eq!(stats.frames, 4);
+        assert_eq!(stats.dropped, 2);
+        assert_eq!(stats.drop_rate(), 2.0 * 100.0 / 6.0);
+
+        // A restarted capture is not a drop, and the last sequence number outlives the period
+        stats.reset();
+        stats.add(&trace(7, 0, &[]), 0);
+        stats.add(&trace(0, 0, &[]), 0);
+        assert_eq!((stats.frames, stats.dropped), (2, 0));
+    }
+
+    #[test]
+    fn latencies_are_split_between_the_hops() {
+        let mut stats = StreamStats::default();
+        stats.add(
+            &trace(0, 1_000, &[("zencode", 3_000), ("zdecode", 4_000)]),
+            8_000,
+        );
+        assert_eq!(stats.end_to_end.avg_ms(), 7.0);
+        let segments = stats
+            .segments
+            .iter()
+            .map(|(segment, latency)| (segment.as_str(), latency.avg_ms()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            [
+                ("zcapture -> zencode", 2.0),
+                ("zdecode -> zstats", 4.0),
+                ("zencode -> zdecode", 1.0),
+            ]
+        );
+
+        // Clocks going backwards count as no latency
+        let mut stats = StreamStats::default();
+        stats.add(&trace(0, 5_000, &[]), 4_000);
+        assert_eq!(stats.end_to_end.max_ms(), 0.0);
+    }
+}
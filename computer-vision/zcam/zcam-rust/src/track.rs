@@ -0,0 +1,305 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::{collections::BTreeMap, time::Duration};
+
+use clap::Parser;
+use serde_json::json;
+
+use opencv::core::Scalar;
+use tokio::select;
+use zcam::{
+    detection::{draw_detections, Detection, Detections},
+    params::{params_loop, Params},
+    process_in_shm,
+    shm::{metrics_loop, ShmArgs, ShmPool},
+    tracking::{Tracker, TrackerConfig},
+    BadFrames, FrameMeta,
+};
+use zenoh::{
+    config::Config,
+    qos::{CongestionControl, Reliability},
+    Session,
+};
+
+const STAGE: &str = "ztrack";
+
+/// Maximum number of frames for which the tracks are kept to be drawn.
+const MAX_PENDING: usize = 64;
+
+#[tokio::main]
+async fn main() {
+    // Initiate logging
+    zenoh::init_log_from_env_or("error");
+
+    // Parse command line arguments
+    let args = Args::parse();
+    let (config, reliability, congestion_ctrl) = parse_args(&args);
+
+    let params = Params::new(TrackerConfig {
+        min_iou: args.min_iou,
+        min_hits: args.min_hits,
+        max_misses: args.max_misses,
+    })
+    .expect("Invalid parameters");
+
+    println!("Opening session...");
+    let z = zenoh::open(config).await.unwrap();
+    let shm_pool = ShmPool::new(&z, &args.shm).await;
+
+    select!(
+        // Processing loop
+        _ = process_loop(&z, args.key_sub.clone(), args.key_detections.clone(), args.key_pub.clone(), args.events_only, reliability, congestion_ctrl, params.clone(), shm_pool.clone()) => {}
+        // Config update loop
+        _ = params_loop(&z, format!("{}/{STAGE}/conf", args.key_sub), params) => {},
+        // SHM metrics loop
+        _ = metrics_loop(&z, format!("{}/{STAGE}/metrics", args.key_sub), shm_pool, Duration::from_secs(1)) => {},
+    );
+}
+
+#[derive(clap::Parser, Clone, PartialEq)]
+struct Args {
+    #[arg(short, long)]
+    mode: Option<String>,
+
+    /// Key of the frames to draw the tracks on
+    #[arg(short, long, default_value = "demo/zcam")]
+    key_sub: String,
+
+    /// Key of the detections to track
+    #[arg(long, default_value = "demo/zcam/haarcascade/detections")]
+    key_detections: String,
+
+    #[arg(long, default_value = "demo/zcam/track")]
+    key_pub: String,
+
+    /// Minimum IoU between a track and a detection to associate them
+    #[arg(long, default_value = "0.3")]
+    min_iou: f64,
+
+    /// Number of consecutive detections before a track is confirmed
+    #[arg(long, default_value = "3")]
+    min_hits: u32,
+
+    /// Number of frames without detection before a track is lost
+    #[arg(long, default_value = "10")]
+    max_misses: u32,
+
+    /// Only publish the track events and the tracks, not the annotated frames
+    #[arg(long, default_value = "false")]
+    events_only: bool,
+
+    #[arg(short('e'), long)]
+    connect: Option<Vec<String>>,
+
+    #[arg(short, long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    shm: ShmArgs,
+
+    #[arg(long, default_value = "false")]
+    best_effort: bool,
+
+    #[arg(long, default_value = "false")]
+    block_on_congestion: bool,
+}
+
+fn parse_args(
+    args: &Args,
+) -> (
+    Config,
+    zenoh::qos::Reliability,
+    zenoh::qos::CongestionControl,
+) {
+    let mut c = if let Some(f) = &args.config {
+        zenoh::Config::from_file(f).expect("Invalid Zenoh Configuraiton File")
+    } else {
+        zenoh::Config::default()
+    };
+
+    if let Some(ls) = &args.connect {
+        let _ = c.insert_json5("connect/endpoints", &json!(ls).to_string());
+    }
+    if let Some(m) = &args.mode {
+        let _ = c.insert_json5("mode", &json!(m).to_string());
+    }
+    args.shm.apply(&mut c);
+
+    let congestion_control = if args.block_on_congestion {
+        zenoh::qos::CongestionControl::Block
+    } else {
+        zenoh::qos::CongestionControl::Drop
+    };
+    let reliability = if args.best_effort {
+        zenoh::qos::Reliability::BestEffort
+    } else {
+        zenoh::qos::Reliability::Reliable
+    };
+
+    (c, reliability, congestion_control)
+}
+
+/// Processing loop that feeds the detections to the tracker and publishes:
+/// - the track events on `<key_pub>/events/{enter,update,exit}`
+/// - the confirmed tracks of each frame, as detections labelled with the track ID, on `<key_pub>/detections`
+/// - unless `events_only` is set, the frames with the tracks drawn in-place in SHM, on `<key_pub>`
+#[allow(clippy::too_many_arguments)]
+async fn process_loop(
+    session: &Session,
+    key_sub: String,
+    key_detections: String,
+    key_pub: String,
+    events_only: bool,
+    reliability: Reliability,
+    congestion_ctrl: CongestionControl,
+    params: Params<TrackerConfig>,
+    shm_pool: ShmPool,
+) {
+    // Declare subscriber for detections
+    let detections_sub = session.declare_subscriber(&key_detections).await.unwrap();
+
+    // Declare subscriber for frames, if they are to be annotated
+    let sub = if events_only {
+        None
+    } else {
+        Some(session.declare_subscriber(&key_sub).await.unwrap())
+    };
+
+    // Declare publisher for processed frames
+    let publ = session
+        .declare_publisher(&key_pub)
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    // Declare publisher for tracks
+    let tracks_publ = session
+        .declare_publisher(format!("{key_pub}/detections"))
+        .reliability(reliability)
+        .congestion_control(congestion_ctrl)
+        .await
+        .unwrap();
+
+    let mut tracker = Tracker::new(params.get());
+
+    // Confirmed tracks of the latest frames, indexed by frame sequence number
+    let mut pending: BTreeMap<u64, Detections> = BTreeMap::new();
+
+    let mut bad_frames = BadFrames::default();
+
+    loop {
+        select!(
+            // Receive detections
+            sample = detections_sub.recv_async() => {
+                let sample = sample.unwrap();
+                let detections = match Detections::decode(sample.payload()) {
+                    Ok(detections) => detections,
+                    Err(e) => {
+                        tracing::error!("Unable to decode detections on {}: {e}", sample.key_expr());
+                        continue;
+                    }
+                };
+
+                // Update the tracks and publish the events
+                tracker.set_config(params.get());
+                for event in tracker.update(&detections.trace, detections.detections) {
+                    session
+                        .put(
+                            format!("{key_pub}/events/{}", event.kind.as_str()),
+                            event.encode().unwrap(),
+                        )
+                        .congestion_control(congestion_ctrl)
+                        .await
+                        .unwrap();
+                }
+
+                // Publish the confirmed tracks
+                let tracks = Detections {
+                    detector: STAGE.to_string(),
+                    trace: detections.trace,
+                    rows: detections.rows,
+                    cols: detections.cols,
+                    detections: tracker
+                        .tracks()
+                        .map(|track| {
+                            let estimate = track.estimate();
+                            Detection {
+                                label: format!("#{} {}", track.id, estimate.label).trim_end().to_string(),
+                                ..estimate
+                            }
+                        })
+                        .collect(),
+                };
+                tracks_publ.put(tracks.encode().unwrap()).await.unwrap();
+
+                if !events_only {
+                    pending.insert(tracks.trace.seq, tracks);
+                    while pending.len() > MAX_PENDING {
+                        pending.pop_first();
+                    }
+                }
+            }
+
+            // Receive sample with frame
+            Some(sample) = async { Some(sub.as_ref()?.recv_async().await) } => {
+                let mut sample = sample.unwrap();
+
+                // Decode frame metadata
+                let mut meta = match FrameMeta::decode(&sample) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        bad_frames.skip(e);
+                        continue;
+                    }
+                };
+
+                let raw_meta = match &meta {
+                    FrameMeta::Raw(raw_meta) => raw_meta.clone(),
+                    other_meta => {
+                        tracing::error!("Unsupported frame meta: {:?}", other_meta);
+                        continue;
+                    }
+                };
+
+                // Tracks of the frame, or of the most recent frame before it
+                let tracks = pending
+                    .range(..=raw_meta.trace().seq)
+                    .next_back()
+                    .map(|(_, tracks)| tracks.detections.clone())
+                    .unwrap_or_default();
+
+                // Draw the tracks directly on the SHM buffer
+                match process_in_shm(&mut sample, &raw_meta, &shm_pool, |frame| {
+                    Ok(draw_detections(frame, &tracks, Scalar::new(255.0, 128.0, 0.0, 0.0))?)
+                })
+                .await
+                {
+                    Ok((processed_frame_in_shm, ())) => {
+                        // Encode frame metadata
+                        meta.raw_mut().add_hop(STAGE);
+                        let attachment = meta.encode().unwrap();
+
+                        // Publish SHM frame
+                        publ.put(processed_frame_in_shm)
+                            .attachment(attachment)
+                            .await
+                            .unwrap();
+                    }
+                    Err(e) => tracing::error!("Failed to process frame: {e}"),
+                }
+            }
+        );
+    }
+}
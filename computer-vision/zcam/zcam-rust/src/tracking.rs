@@ -0,0 +1,493 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Multi-object tracking: association of per-frame detections into tracks with persistent IDs.
+
+use rkyv::{Archive, Deserialize, Serialize};
+use zenoh::bytes::ZBytes;
+
+use crate::{detection::Detection, params::StageParams, FrameTrace};
+
+/// Kind of a track event.
+#[derive(Archive, Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEventKind {
+    /// The track has just been confirmed
+    Enter,
+    /// The track has been matched with a detection (or predicted, if the detection was missed)
+    Update,
+    /// The track has been lost
+    Exit,
+}
+
+impl TrackEventKind {
+    /// Name of the kind, as used in the key of the events.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackEventKind::Enter => "enter",
+            TrackEventKind::Update => "update",
+            TrackEventKind::Exit => "exit",
+        }
+    }
+}
+
+/// An event in the life of a track.
+#[derive(Archive, Deserialize, Serialize, Debug, Clone)]
+pub struct TrackEvent {
+    pub kind: TrackEventKind,
+    /// Identifier of the track, unique for the lifetime of the tracker
+    pub track_id: u64,
+    /// Trace of the frame on which the event happened
+    pub trace: FrameTrace,
+    /// Last state of the track; the bounding box is the estimate of the tracker
+    pub detection: Detection,
+}
+
+impl TrackEvent {
+    pub fn decode(payload: &ZBytes) -> zenoh::Result<Self> {
+        let bytes = payload.to_bytes();
+        let event = rkyv::access::<ArchivedTrackEvent, rkyv::rancor::Error>(&bytes)?;
+        Ok(rkyv::deserialize::<TrackEvent, rkyv::rancor::Error>(event)?)
+    }
+
+    pub fn encode(&self) -> zenoh::Result<ZBytes> {
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self)?;
+        Ok(bytes.as_slice().into())
+    }
+}
+
+/// Parameters of a [`Tracker`].
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TrackerConfig {
+    /// Minimum IoU between a track and a detection to associate them
+    pub min_iou: f64,
+    /// Number of consecutive matches before a track is confirmed (and its `Enter` event emitted)
+    pub min_hits: u32,
+    /// Number of consecutive frames without match before a track is dropped
+    pub max_misses: u32,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            min_iou: 0.3,
+            min_hits: 3,
+            max_misses: 10,
+        }
+    }
+}
+
+impl StageParams for TrackerConfig {
+    fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.min_iou) {
+            return Err("min_iou must be between 0 and 1".to_string());
+        }
+        if self.min_hits == 0 {
+            return Err("min_hits must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Constant velocity Kalman filter on one coordinate.
+#[derive(Debug, Clone)]
+struct Kalman1D {
+    position: f64,
+    velocity: f64,
+    // Covariance of (position, velocity)
+    p: [[f64; 2]; 2],
+}
+
+impl Kalman1D {
+    const PROCESS_NOISE: f64 = 1.0;
+    const MEASUREMENT_NOISE: f64 = 4.0;
+
+    fn new(position: f64) -> Self {
+        Self {
+            position,
+            velocity: 0.0,
+            p: [[10.0, 0.0], [0.0, 100.0]],
+        }
+    }
+
+    fn predict(&mut self, dt: f64) {
+        self.position += self.velocity * dt;
+        let [[p00, p01], [p10, p11]] = self.p;
+        let q = Self::PROCESS_NOISE;
+        self.p = [
+            [
+                p00 + dt * (p10 + p01) + dt * dt * p11 + q * dt.powi(4) / 4.0,
+                p01 + dt * p11 + q * dt.powi(3) / 2.0,
+            ],
+            [p10 + dt * p11 + q * dt.powi(3) / 2.0, p11 + q * dt * dt],
+        ];
+    }
+
+    fn correct(&mut self, measurement: f64) {
+        let [[p00, p01], [p10, p11]] = self.p;
+        let s = p00 + Self::MEASUREMENT_NOISE;
+        let (k0, k1) = (p00 / s, p10 / s);
+        let innovation = measurement - self.position;
+        self.position += k0 * innovation;
+        self.velocity += k1 * innovation;
+        self.p = [
+            [(1.0 - k0) * p00, (1.0 - k0) * p01],
+            [p10 - k1 * p00, p11 - k1 * p01],
+        ];
+    }
+}
+
+/// A tracked object.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub id: u64,
+    /// Last detection matched with the track
+    pub detection: Detection,
+    pub hits: u32,
+    pub misses: u32,
+    pub confirmed: bool,
+    // Center and size of the bounding box
+    cx: Kalman1D,
+    cy: Kalman1D,
+    width: Kalman1D,
+    height: Kalman1D,
+}
+
+impl Track {
+    fn new(id: u64, detection: Detection) -> Self {
+        Self {
+            id,
+            cx: Kalman1D::new(detection.x as f64 + detection.width as f64 / 2.0),
+            cy: Kalman1D::new(detection.y as f64 + detection.height as f64 / 2.0),
+            width: Kalman1D::new(detection.width as f64),
+            height: Kalman1D::new(detection.height as f64),
+            detection,
+            hits: 1,
+            misses: 0,
+            confirmed: false,
+        }
+    }
+
+    /// Current estimate of the bounding box, as `(x, y, width, height)`.
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
+        let (w, h) = (self.width.position.max(1.0), self.height.position.max(1.0));
+        (self.cx.position - w / 2.0, self.cy.position - h / 2.0, w, h)
+    }
+
+    /// Last detection of the track, with the bounding box estimated by the tracker.
+    pub fn estimate(&self) -> Detection {
+        let (x, y, width, height) = self.bbox();
+        Detection {
+            x: x.round() as i32,
+            y: y.round() as i32,
+            width: width.round() as i32,
+            height: height.round() as i32,
+            ..self.detection.clone()
+        }
+    }
+
+    fn predict(&mut self, dt: f64) {
+        self.cx.predict(dt);
+        self.cy.predict(dt);
+        self.width.predict(dt);
+        self.height.predict(dt);
+    }
+
+    fn correct(&mut self, detection: Detection) {
+        self.cx
+            .correct(detection.x as f64 + detection.width as f64 / 2.0);
+        self.cy
+            .correct(detection.y as f64 + detection.height as f64 / 2.0);
+        self.width.correct(detection.width as f64);
+        self.height.correct(detection.height as f64);
+        self.detection = detection;
+        self.hits += 1;
+        self.misses = 0;
+    }
+}
+
+/// Intersection over union of two `(x, y, width, height)` boxes.
+pub fn iou(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> f64 {
+    let w = (a.0 + a.2).min(b.0 + b.2) - a.0.max(b.0);
+    let h = (a.1 + a.3).min(b.1 + b.3) - a.1.max(b.1);
+    if w <= 0.0 || h <= 0.0 {
+        return 0.0;
+    }
+    let intersection = w * h;
+    intersection / (a.2 * a.3 + b.2 * b.3 - intersection)
+}
+
+/// Tracker associating the detections of successive frames by IoU with the Kalman prediction of each track.
+///
+/// Only detections of the same class are associated. Tracks are confirmed after `min_hits` consecutive matches,
+/// and dropped after `max_misses` frames without match.
+#[derive(Debug)]
+pub struct Tracker {
+    config: TrackerConfig,
+    tracks: Vec<Track>,
+    next_id: u64,
+    last_seq: Option<u64>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            config,
+            tracks: Vec::new(),
+            next_id: 1,
+            last_seq: None,
+        }
+    }
+
+    /// Changes the configuration, applied from the next update on.
+    pub fn set_config(&mut self, config: TrackerConfig) {
+        self.config = config;
+    }
+
+    /// Confirmed tracks.
+    pub fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter().filter(|track| track.confirmed)
+    }
+
+    /// Updates the tracks with the detections of the frame `trace`, and returns the resulting events.
+    pub fn update(&mut self, trace: &FrameTrace, detections: Vec<Detection>) -> Vec<TrackEvent> {
+        // Predict the tracks up to the frame, counting the frames in between
+        let dt = match self.last_seq {
+            Some(last_seq) if trace.seq > last_seq => (trace.seq - last_seq) as f64,
+            _ => 1.0,
+        };
+        self.last_seq = Some(trace.seq);
+        for track in &mut self.tracks {
+            track.predict(dt);
+        }
+
+        // Greedy association, best IoU first
+        let mut pairs = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            for (d, detection) in detections.iter().enumerate() {
+                if track.detection.class_id != detection.class_id {
+                    continue;
+                }
+                let iou = iou(
+                    track.bbox(),
+                    (
+                        detection.x as f64,
+                        detection.y as f64,
+                        detection.width as f64,
+                        detection.height as f64,
+                    ),
+                );
+                if iou >= self.config.min_iou {
+                    pairs.push((iou, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut matched_tracks = vec![false; self.tracks.len()];
+        let mut detections = detections.into_iter().map(Some).collect::<Vec<_>>();
+        for (_, t, d) in pairs {
+            if matched_tracks[t] || detections[d].is_none() {
+                continue;
+            }
+            matched_tracks[t] = true;
+            self.tracks[t].correct(detections[d].take().unwrap());
+        }
+
+        let mut events = Vec::new();
+        let mut event = |kind, track: &Track| {
+            events.push(TrackEvent {
+                kind,
+                track_id: track.id,
+                trace: trace.clone(),
+                detection: track.estimate(),
+            })
+        };
+
+        // Update the state of the existing tracks
+        for (track, matched) in self.tracks.iter_mut().zip(matched_tracks) {
+            if !matched {
+                track.misses += 1;
+                track.hits = 0;
+            }
+            if !track.confirmed && track.hits >= self.config.min_hits {
+                track.confirmed = true;
+                event(TrackEventKind::Enter, &*track);
+            } else if track.confirmed && track.misses > self.config.max_misses {
+                event(TrackEventKind::Exit, &*track);
+            } else if track.confirmed {
+                event(TrackEventKind::Update, &*track);
+            }
+        }
+        let (min_hits, max_misses) = (self.config.min_hits, self.config.max_misses);
+        self.tracks.retain(|track| {
+            // Unconfirmed tracks are dropped at the first miss
+            track.misses <= max_misses && (track.confirmed || track.misses == 0)
+        });
+
+        // Start new tracks from the unmatched detections
+        for detection in detections.into_iter().flatten() {
+            let mut track = Track::new(self.next_id, detection);
+            self.next_id += 1;
+            if min_hits <= 1 {
+                track.confirmed = true;
+                event(TrackEventKind::Enter, &track);
+            }
+            self.tracks.push(track);
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(class_id: i32, x: i32, y: i32) -> Detection {
+        Detection {
+            class_id,
+            label: String::new(),
+            score: 1.0,
+            x,
+            y,
+            width: 20,
+            height: 20,
+        }
+    }
+
+    fn trace(seq: u64) -> FrameTrace {
+        FrameTrace {
+            seq,
+            ..Default::default()
+        }
+    }
+
+    fn tracker(min_hits: u32, max_misses: u32) -> Tracker {
+        Tracker::new(TrackerConfig {
+            min_iou: 0.3,
+            min_hits,
+            max_misses,
+        })
+    }
+
+    fn kinds(events: &[TrackEvent]) -> Vec<(TrackEventKind, u64)> {
+        events.iter().map(|e| (e.kind, e.track_id)).collect()
+    }
+
+    #[test]
+    fn tracks_are_confirmed_after_min_hits() {
+        let mut tracker = tracker(3, 10);
+        assert!(tracker
+            .update(&trace(1), vec![detection(0, 100, 100)])
+            .is_empty());
+        assert!(tracker
+            .update(&trace(2), vec![detection(0, 102, 100)])
+            .is_empty());
+        assert_eq!(tracker.tracks().count(), 0);
+        let events = tracker.update(&trace(3), vec![detection(0, 104, 100)]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Enter, 1)]);
+        assert_eq!(events[0].trace.seq, 3);
+        let events = tracker.update(&trace(4), vec![detection(0, 106, 100)]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Update, 1)]);
+        assert_eq!(tracker.tracks().count(), 1);
+    }
+
+    #[test]
+    fn min_hits_of_one_confirms_immediately() {
+        let mut tracker = tracker(1, 10);
+        let events = tracker.update(&trace(1), vec![detection(0, 100, 100)]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Enter, 1)]);
+        assert_eq!(events[0].detection, detection(0, 100, 100));
+    }
+
+    #[test]
+    fn unconfirmed_tracks_are_dropped_at_first_miss() {
+        let mut tracker = tracker(2, 10);
+        tracker.update(&trace(1), vec![detection(0, 100, 100)]);
+        assert!(tracker.update(&trace(2), vec![]).is_empty());
+        tracker.update(&trace(3), vec![detection(0, 100, 100)]);
+        let events = tracker.update(&trace(4), vec![detection(0, 100, 100)]);
+        // The detection of the third frame started a new track
+        assert_eq!(kinds(&events), [(TrackEventKind::Enter, 2)]);
+    }
+
+    #[test]
+    fn confirmed_tracks_exit_after_max_misses() {
+        let mut tracker = tracker(1, 2);
+        tracker.update(&trace(1), vec![detection(0, 100, 100)]);
+        // Missed tracks are still updated with their prediction
+        for seq in 2..=3 {
+            let events = tracker.update(&trace(seq), vec![]);
+            assert_eq!(kinds(&events), [(TrackEventKind::Update, 1)]);
+        }
+        let events = tracker.update(&trace(4), vec![]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Exit, 1)]);
+        assert!(tracker.update(&trace(5), vec![]).is_empty());
+        assert_eq!(tracker.tracks().count(), 0);
+
+        // A match resets the misses
+        tracker.update(&trace(6), vec![detection(0, 100, 100)]);
+        tracker.update(&trace(7), vec![]);
+        tracker.update(&trace(8), vec![]);
+        let events = tracker.update(&trace(9), vec![detection(0, 100, 100)]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Update, 2)]);
+    }
+
+    #[test]
+    fn detections_are_associated_by_class_and_overlap() {
+        let mut tracker = tracker(1, 10);
+        let events = tracker.update(
+            &trace(1),
+            vec![
+                detection(0, 100, 100),
+                detection(1, 100, 100),
+                detection(0, 300, 300),
+            ],
+        );
+        assert_eq!(events.len(), 3);
+
+        // Whatever the order of the detections, each track keeps its object
+        let events = tracker.update(
+            &trace(2),
+            vec![
+                detection(0, 302, 300),
+                detection(1, 101, 100),
+                detection(0, 102, 100),
+            ],
+        );
+        let mut updates = events
+            .iter()
+            .map(|e| (e.track_id, e.detection.class_id, e.detection.x / 100))
+            .collect::<Vec<_>>();
+        updates.sort();
+        assert_eq!(updates, [(1, 0, 1), (2, 1, 1), (3, 0, 3)]);
+
+        // A detection overlapping no track starts a new one
+        let events = tracker.update(&trace(3), vec![detection(0, 500, 500)]);
+        assert!(kinds(&events).contains(&(TrackEventKind::Enter, 4)));
+    }
+
+    #[test]
+    fn tracks_are_predicted_over_skipped_frames() {
+        let mut tracker = tracker(1, 10);
+        for seq in 1..=10 {
+            tracker.update(&trace(seq), vec![detection(0, 100 + 5 * seq as i32, 100)]);
+        }
+        // 4 frames later, the object moved by 20 pixels, its whole width
+        let events = tracker.update(&trace(14), vec![detection(0, 170, 100)]);
+        assert_eq!(kinds(&events), [(TrackEventKind::Update, 1)]);
+        let x = tracker.tracks().next().unwrap().bbox().0;
+        assert!((x - 170.0).abs() < 5.0, "estimated at {}", x);
+    }
+}
@@ -0,0 +1,340 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! Basic image transformations, run as stages with [`processor::run`](crate::processor::run).
+
+use opencv::{
+    core::{self, Mat, Rect, Size},
+    imgproc,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{params::StageParams, processor::FrameProcessor, PixelFormat};
+
+/// Resizes the frames.
+pub struct Resize;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ResizeParams {
+    /// Width of the resized frames, 0 to keep the aspect ratio given `height`
+    pub width: i32,
+    /// Height of the resized frames, 0 to keep the aspect ratio given `width`
+    pub height: i32,
+}
+
+impl Default for ResizeParams {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 0,
+        }
+    }
+}
+
+impl ResizeParams {
+    /// Size of a resized frame of `rows` x `cols`.
+    pub fn size(&self, rows: i32, cols: i32) -> Size {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        match (self.width, self.height) {
+            (0, height) => Size::new(cols * height / rows, height),
+            (width, 0) => Size::new(width, rows * width / cols),
+            (width, height) => Size::new(width, height),
+        }
+    }
+}
+
+impl StageParams for ResizeParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.width < 0 || self.height < 0 || (self.width == 0 && self.height == 0) {
+            return Err("width and height must be positive, and not both 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for Resize {
+    type Params = ResizeParams;
+
+    fn name(&self) -> &str {
+        "zresize"
+    }
+
+    fn accepts(&self, format: PixelFormat) -> bool {
+        !format.is_yuv()
+    }
+
+    fn process(&mut self, frame: &mut Mat, params: &ResizeParams) -> zenoh::Result<Option<Mat>> {
+        let size = params.size(frame.rows(), frame.cols());
+        if size == frame.size()? {
+            return Ok(None);
+        }
+        let mut resized = Mat::default();
+        imgproc::resize(&*frame, &mut resized, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+        Ok(Some(resized))
+    }
+}
+
+/// Crops the frames to a region.
+pub struct Crop;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CropParams {
+    pub x: i32,
+    pub y: i32,
+    /// Width of the region, 0 to crop up to the right edge
+    pub width: i32,
+    /// Height of the region, 0 to crop up to the bottom edge
+    pub height: i32,
+}
+
+impl CropParams {
+    /// Region to crop from a frame of `rows` x `cols`, clipped to the frame.
+    pub fn region(&self, rows: i32, cols: i32) -> Result<Rect, String> {
+        let width = match self.width {
+            0 => cols - self.x,
+            width => width.min(cols - self.x),
+        };
+        let height = match self.height {
+            0 => rows - self.y,
+            height => height.min(rows - self.y),
+        };
+        if width <= 0 || height <= 0 {
+            return Err(format!("Crop region is outside of the {cols}x{rows} frame"));
+        }
+        Ok(Rect::new(self.x, self.y, width, height))
+    }
+}
+
+impl StageParams for CropParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.x < 0 || self.y < 0 || self.width < 0 || self.height < 0 {
+            return Err("x, y, width and height must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for Crop {
+    type Params = CropParams;
+
+    fn name(&self) -> &str {
+        "zcrop"
+    }
+
+    fn accepts(&self, format: PixelFormat) -> bool {
+        !format.is_yuv()
+    }
+
+    fn process(&mut self, frame: &mut Mat, params: &CropParams) -> zenoh::Result<Option<Mat>> {
+        let region = params.region(frame.rows(), frame.cols())?;
+        if region.size() == frame.size()? {
+            return Ok(None);
+        }
+        let region = Mat::roi(&*frame, region)?;
+        Ok(Some(region.try_clone()?))
+    }
+}
+
+/// Rotates the frames by a multiple of 90 degrees.
+pub struct Rotate;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RotateParams {
+    /// Clockwise rotation, in degrees: 0, 90, 180 or 270
+    pub angle: i32,
+}
+
+impl StageParams for RotateParams {
+    fn validate(&self) -> Result<(), String> {
+        if ![0, 90, 180, 270].contains(&self.angle) {
+            return Err("angle must be 0, 90, 180 or 270".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for Rotate {
+    type Params = RotateParams;
+
+    fn name(&self) -> &str {
+        "zrotate"
+    }
+
+    fn accepts(&self, format: PixelFormat) -> bool {
+        !format.is_yuv()
+    }
+
+    fn process(&mut self, frame: &mut Mat, params: &RotateParams) -> zenoh::Result<Option<Mat>> {
+        let rotate_code = match params.angle {
+            90 => core::ROTATE_90_CLOCKWISE,
+            180 => core::ROTATE_180,
+            270 => core::ROTATE_90_COUNTERCLOCKWISE,
+            _ => return Ok(None),
+        };
+        let mut rotated = Mat::default();
+        core::rotate(&*frame, &mut rotated, rotate_code)?;
+        Ok(Some(rotated))
+    }
+}
+
+/// Converts the frames to grayscale.
+pub struct Grayscale;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GrayscaleParams {}
+
+impl StageParams for GrayscaleParams {}
+
+impl FrameProcessor for Grayscale {
+    type Params = GrayscaleParams;
+
+    fn name(&self) -> &str {
+        "zgrayscale"
+    }
+
+    fn accepts(&self, format: PixelFormat) -> bool {
+        matches!(format, PixelFormat::Bgr | PixelFormat::Gray)
+    }
+
+    fn process(&mut self, frame: &mut Mat, _: &GrayscaleParams) -> zenoh::Result<Option<Mat>> {
+        if frame.channels() == 1 {
+            return Ok(None);
+        }
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(&*frame, &mut gray, imgproc::COLOR_BGR2GRAY)?;
+        Ok(Some(gray))
+    }
+}
+
+/// Blurs the frames with a Gaussian kernel, in-place.
+pub struct Blur;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BlurParams {
+    /// Size of the kernel, odd
+    pub kernel: i32,
+    /// Standard deviation of the kernel, 0 to compute it from its size
+    pub sigma: f64,
+}
+
+impl Default for BlurParams {
+    fn default() -> Self {
+        Self {
+            kernel: 9,
+            sigma: 0.0,
+        }
+    }
+}
+
+impl StageParams for BlurParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.kernel < 1 || self.kernel % 2 == 0 {
+            return Err("kernel must be odd and positive".to_string());
+        }
+        if self.sigma < 0.0 {
+            return Err("sigma must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for Blur {
+    type Params = BlurParams;
+
+    fn name(&self) -> &str {
+        "zblur"
+    }
+
+    fn accepts(&self, format: PixelFormat) -> bool {
+        !format.is_yuv()
+    }
+
+    fn process(&mut self, frame: &mut Mat, params: &BlurParams) -> zenoh::Result<Option<Mat>> {
+        let source = frame.try_clone()?;
+        imgproc::gaussian_blur_def(
+            &source,
+            frame,
+            Size::new(params.kernel, params.kernel),
+            params.sigma,
+        )?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_keeps_the_aspect_ratio_of_a_missing_dimension() {
+        let resize = |width, height| ResizeParams { width, height };
+        assert_eq!(resize(640, 0).size(720, 1280), Size::new(640, 360));
+        assert_eq!(resize(0, 240).size(480, 640), Size::new(320, 240));
+        assert_eq!(resize(100, 100).size(480, 640), Size::new(100, 100));
+        assert!(resize(0, 0).validate().is_err());
+        assert!(resize(-1, 240).validate().is_err());
+        assert!(ResizeParams::default().validate().is_ok());
+    }
+
+    #[test]
+    fn crop_region_is_clipped_to_the_frame() {
+        let crop = |x, y, width, height| CropParams {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            crop(10, 20, 0, 0).region(480, 640),
+            Ok(Rect::new(10, 20, 630, 460))
+        );
+        assert_eq!(
+            crop(600, 400, 100, 50).region(480, 640),
+            Ok(Rect::new(600, 400, 40, 50))
+        );
+        assert_eq!(
+            CropParams::default().region(480, 640),
+            Ok(Rect::new(0, 0, 640, 480))
+        );
+        assert!(crop(640, 0, 0, 0).region(480, 640).is_err());
+        assert!(crop(0, 500, 10, 10).region(480, 640).is_err());
+        assert!(crop(-1, 0, 0, 0).validate().is_err());
+    }
+
+    #[test]
+    fn rotation_and_blur_params_are_checked() {
+        for angle in [0, 90, 180, 270] {
+            assert!(RotateParams { angle }.validate().is_ok());
+        }
+        assert!(RotateParams { angle: 45 }.validate().is_err());
+        assert!(BlurParams::default().validate().is_ok());
+        let blur = |kernel, sigma| BlurParams { kernel, sigma };
+        assert!(blur(8, 0.0).validate().is_err());
+        assert!(blur(0, 0.0).validate().is_err());
+        assert!(blur(5, -1.0).validate().is_err());
+    }
+
+    #[test]
+    fn yuv_frames_are_converted_first() {
+        assert!(!Resize.accepts(PixelFormat::Nv12));
+        assert!(Resize.accepts(PixelFormat::Rgb));
+        assert!(Grayscale.accepts(PixelFormat::Gray));
+        assert!(!Grayscale.accepts(PixelFormat::Rgb));
+    }
+}
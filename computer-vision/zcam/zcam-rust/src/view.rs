@@ -0,0 +1,300 @@
+//
+// Copyright (c) 2017, 2026 ZettaScale Technology
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+//
+// Contributors:
+//   The Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! The digital pan/tilt/zoom view of `zptz`, moved by commands or following detections.
+
+use opencv::core::{Rect, Size};
+
+use crate::{detection::Detection, params::StageParams};
+
+/// Parameters of `zptz` that can be changed while it runs.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct PtzParams {
+    pub max_zoom: f64,
+    /// Fraction of the view taken by a followed detection
+    pub fill: f64,
+    /// Smoothing of the moves following the detections, from 0 (none) to 1 (frozen)
+    pub smoothing: f64,
+}
+
+impl StageParams for PtzParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.max_zoom < 1.0 {
+            return Err("max_zoom must be at least 1".to_string());
+        }
+        if self.fill <= 0.0 || self.fill > 1.0 {
+            return Err("fill must be in ]0, 1]".to_string());
+        }
+        if !(0.0..1.0).contains(&self.smoothing) {
+            return Err("smoothing must be in [0, 1[".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Commands published on the PTZ key, as JSON: `{"move": {"pan": 0.1, "zoom": 1.5}}`,
+/// `{"goto": {"x": 0.5, "y": 0.5, "zoom": 2.0}}`, `"follow"` or `"reset"`.
+/// Moving the view stops following the detections.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PtzCommand {
+    /// Moves the view by fractions of its size, and multiplies the zoom
+    Move {
+        #[serde(default)]
+        pan: f64,
+        #[serde(default)]
+        tilt: f64,
+        #[serde(default = "no_zoom")]
+        zoom: f64,
+    },
+    /// Centers the view on a point given in fractions of the frame size, with a zoom
+    Goto { x: f64, y: f64, zoom: f64 },
+    /// Follows the largest detection
+    Follow,
+    /// Shows the whole frame
+    Reset,
+}
+
+fn no_zoom() -> f64 {
+    1.0
+}
+
+/// View published by the stage: its center, in fractions of the frame size, and zoom.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+    pub following: bool,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            x: 0.5,
+            y: 0.5,
+            zoom: 1.0,
+            following: false,
+        }
+    }
+}
+
+impl Window {
+    pub fn apply(&mut self, command: PtzCommand, max_zoom: f64) {
+        match command {
+            PtzCommand::Move { pan, tilt, zoom } => {
+                self.x += pan / self.zoom;
+                self.y += tilt / self.zoom;
+                self.zoom *= zoom;
+                self.following = false;
+            }
+            PtzCommand::Goto { x, y, zoom } => {
+                *self = Window {
+                    x,
+                    y,
+                    zoom,
+                    following: false,
+                };
+            }
+            PtzCommand::Follow => self.following = true,
+            PtzCommand::Reset => *self = Window::default(),
+        }
+        self.clamp(max_zoom);
+    }
+
+    /// Moves the view towards `detection`, in a frame of `cols`x`rows`.
+    pub fn follow(&mut self, detection: &Detection, cols: i32, rows: i32, params: &PtzParams) {
+        let (cols, rows) = (cols.max(1) as f64, rows.max(1) as f64);
+        let x = (detection.x as f64 + detection.width as f64 / 2.0) / cols;
+        let y = (detection.y as f64 + detection.height as f64 / 2.0) / rows;
+        let zoom = (params.fill * cols / detection.width.max(1) as f64)
+            .min(params.fill * rows / detection.height.max(1) as f64);
+
+        let s = params.smoothing;
+        self.x = s * self.x + (1.0 - s) * x;
+        self.y = s * self.y + (1.0 - s) * y;
+        self.zoom = s * self.zoom + (1.0 - s) * zoom;
+        self.clamp(params.max_zoom);
+    }
+
+    fn clamp(&mut self, max_zoom: f64) {
+        self.x = self.x.clamp(0.0, 1.0);
+        self.y = self.y.clamp(0.0, 1.0);
+        self.zoom = self.zoom.clamp(1.0, max_zoom);
+    }
+
+    /// Region of a frame of `cols`x`rows` to show in a view of `size`: the largest region of the
+    /// aspect ratio of the view fitting in the frame, scaled down by the zoom and kept inside the frame.
+    pub fn region(&self, cols: i32, rows: i32, size: Size) -> Rect {
+        let aspect = size.width as f64 / size.height as f64;
+        let (mut width, mut height) = (cols as f64, cols as f64 / aspect);
+        if height > rows as f64 {
+            height = rows as f64;
+            width = height * aspect;
+        }
+        let width = ((width / self.zoom).round() as i32).clamp(1, cols);
+        let height = ((height / self.zoom).round() as i32).clamp(1, rows);
+        let x = (self.x * cols as f64).round() as i32 - width / 2;
+        let y = (self.y * rows as f64).round() as i32 - height / 2;
+        Rect::new(
+            x.clamp(0, cols - width),
+            y.clamp(0, rows - height),
+            width,
+            height,
+        )
+    }
+}
+
+/// Size of the view of a frame of `cols`x`rows`, given as `(width, height)` where a 0 keeps the
+/// aspect ratio of the frame.
+pub fn view_size(size: (i32, i32), cols: i32, rows: i32) -> Size {
+    match size {
+        (0, 0) => Size::new(cols, rows),
+        (0, height) => Size::new(cols * height / rows, height),
+        (width, 0) => Size::new(width, rows * width / cols),
+        (width, height) => Size::new(width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: PtzParams = PtzParams {
+        max_zoom: 8.0,
+        fill: 0.5,
+        smoothing: 0.5,
+    };
+
+    fn command(json: &str) -> PtzCommand {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn detection(x: i32, y: i32, width: i32, height: i32) -> Detection {
+        Detection {
+            class_id: 0,
+            label: String::new(),
+            score: 1.0,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn moves_are_relative_to_the_view() {
+        let mut window = Window::default();
+        window.apply(command(r#"{"move": {"pan": 0.25, "zoom": 2.0}}"#), 8.0);
+        assert_eq!((window.x, window.y, window.zoom), (0.75, 0.5, 2.0));
+        // Half of a view zoomed twice is a quarter of the frame
+        window.apply(command(r#"{"move": {"pan": -0.5, "tilt": 0.5}}"#), 8.0);
+        assert_eq!((window.x, window.y, window.zoom), (0.5, 0.75, 2.0));
+    }
+
+    #[test]
+    fn windows_stay_in_the_frame_and_zoom_range() {
+        let mut window = Window::default();
+        window.apply(command(r#"{"move": {"pan": 2.0, "tilt": -2.0}}"#), 8.0);
+        assert_eq!((window.x, window.y), (1.0, 0.0));
+        window.apply(command(r#"{"move": {"zoom": 16.0}}"#), 8.0);
+        assert_eq!(window.zoom, 8.0);
+        window.apply(
+            command(r#"{"goto": {"x": 0.2, "y": 0.3, "zoom": 0.5}}"#),
+            8.0,
+        );
+        assert_eq!((window.x, window.y, window.zoom), (0.2, 0.3, 1.0));
+        window.apply(command(r#""reset""#), 8.0);
+        assert_eq!(window, Window::default());
+    }
+
+    #[test]
+    fn moving_the_window_stops_following() {
+        let mut window = Window::default();
+        window.apply(command(r#""follow""#), 8.0);
+        assert!(window.following);
+        window.apply(command(r#"{"move": {"pan": 0.1}}"#), 8.0);
+        assert!(!window.following);
+        window.apply(command(r#""follow""#), 8.0);
+        window.apply(
+            command(r#"{"goto": {"x": 0.5, "y": 0.5, "zoom": 1.0}}"#),
+            8.0,
+        );
+        assert!(!window.following);
+    }
+
+    #[test]
+    fn following_is_smoothed_and_fills_the_view() {
+        // Detection centered on (0.25, 0.5), filling half of the frame at zoom 2
+        let centered = detection(80, 180, 160, 120);
+        let mut window = Window::default();
+        window.follow(&centered, 640, 480, &PARAMS);
+        assert_eq!((window.x, window.y, window.zoom), (0.375, 0.5, 1.5));
+        window.follow(&centered, 640, 480, &PARAMS);
+        assert_eq!((window.x, window.y, window.zoom), (0.3125, 0.5, 1.75));
+
+        // Tiny detections do not zoom beyond max_zoom
+        let mut window = Window::default();
+        let params = PtzParams {
+            smoothing: 0.0,
+            ..PARAMS
+        };
+        window.follow(&detection(300, 220, 1, 0), 640, 480, &params);
+        assert_eq!(window.zoom, 8.0);
+    }
+
+    #[test]
+    fn regions_keep_the_aspect_ratio_of_the_view() {
+        let mut window = Window::default();
+        let view = Size::new(320, 240);
+        assert_eq!(window.region(640, 480, view), Rect::new(0, 0, 640, 480));
+        assert_eq!(
+            window.region(640, 480, Size::new(1280, 720)),
+            Rect::new(0, 60, 640, 360)
+        );
+        window.zoom = 2.0;
+        assert_eq!(window.region(640, 480, view), Rect::new(160, 120, 320, 240));
+        // Regions at the edges are kept inside the frame
+        window.x = 0.0;
+        window.y = 1.0;
+        assert_eq!(window.region(640, 480, view), Rect::new(0, 240, 320, 240));
+    }
+
+    #[test]
+    fn view_sizes_keep_the_aspect_ratio_of_the_frame() {
+        assert_eq!(view_size((0, 0), 640, 480), Size::new(640, 480));
+        assert_eq!(view_size((0, 240), 640, 480), Size::new(320, 240));
+        assert_eq!(view_size((320, 0), 640, 480), Size::new(320, 240));
+        assert_eq!(view_size((100, 100), 640, 480), Size::new(100, 100));
+    }
+
+    #[test]
+    fn ptz_params_are_bounded() {
+        assert!(PARAMS.validate().is_ok());
+        let check = |max_zoom, fill, smoothing| {
+            PtzParams {
+                max_zoom,
+                fill,
+                smoothing,
+            }
+            .validate()
+        };
+        assert!(check(1.0, 1.0, 0.0).is_ok());
+        assert!(check(0.9, 0.5, 0.5).is_err());
+        assert!(check(8.0, 0.0, 0.5).is_err());
+        assert!(check(8.0, 1.1, 0.5).is_err());
+        assert!(check(8.0, 0.5, 1.0).is_err());
+        assert!(check(8.0, 0.5, -0.1).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+
+use async_trait::async_trait;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
+use types::RecordedData;
+use zenoh_flow::{
+    zenoh_flow_derive::ZFState, Configuration, Context, DataMessage, Node, Sink, State, ZFError,
+    ZFResult,
+};
+
+const DEFAULT_PATH: &str = "recording.bin";
+
+#[derive(Debug, ZFState)]
+pub struct RecordState {
+    writer: BufWriter<std::fs::File>,
+}
+
+/// Records every `Data` message it receives, along with its timestamp, in a file.
+///
+/// The sink does not care about the type of the data: the bincode serialization produced by the
+/// `ZFData` implementation is written as is, so the same library can record `GamepadInput` or
+/// `Twist` messages. The file can then be replayed with `source-replay`.
+pub struct SinkRecord;
+
+impl Node for SinkRecord {
+    fn initialize(&self, configuration: &Option<Configuration>) -> ZFResult<State> {
+        let path = configuration
+            .as_ref()
+            .and_then(|c| c["path"].as_str())
+            .unwrap_or(DEFAULT_PATH);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| ZFError::IOError(format!("Could not open {}: {}", path, e)))?;
+
+        Ok(State::from(RecordState {
+            writer: BufWriter::new(file),
+        }))
+    }
+
+    fn finalize(&self, dyn_state: &mut State) -> ZFResult<()> {
+        let state = dyn_state.try_get::<RecordState>()?;
+        state
+            .writer
+            .flush()
+            .map_err(|e| ZFError::IOError(format!("Could not flush recording: {}", e)))
+    }
+}
+
+#[async_trait]
+impl Sink for SinkRecord {
+    async fn run(
+        &self,
+        _: &mut Context,
+        dyn_state: &mut State,
+        mut input: DataMessage,
+    ) -> ZFResult<()> {
+        let state = dyn_state.try_get::<RecordState>()?;
+
+        let record = RecordedData {
+            timestamp: input.get_timestamp().get_time().to_duration().as_nanos() as u64,
+            payload: input.get_inner_data().try_as_bytes()?.to_vec(),
+        };
+
+        bincode::serialize_into(&mut state.writer, &record)
+            .map_err(|_| ZFError::SerializationError)?;
+
+        // Flush after each message so that the recording is usable even if the flow is killed.
+        state
+            .writer
+            .flush()
+            .map_err(|e| ZFError::IOError(format!("Could not flush recording: {}", e)))
+    }
+}
+
+zenoh_flow::export_sink!(register);
+
+fn register() -> ZFResult<Arc<dyn Sink>> {
+    Ok(Arc::new(SinkRecord) as Arc<dyn Sink>)
+}
@@ -16,7 +16,9 @@ use async_trait::async_trait;
 use std::sync::Arc;
 use types::Twist;
 use zenoh::prelude::ZFuture;
-use zenoh_flow::{zenoh_flow_derive::ZFState, Context, DataMessage, Node, Sink, State, ZFResult};
+use zenoh_flow::{
+    zenoh_flow_derive::ZFState, Context, DataMessage, Node, Sink, State, ZFError, ZFResult,
+};
 
 #[derive(Debug, ZFState)]
 pub struct SinkState {
@@ -30,21 +32,33 @@ pub struct SinkSerialize;
 impl Node for SinkSerialize {
     fn initialize(
         &self,
-        _: &Option<zenoh_flow::Configuration>,
+        configuration: &Option<zenoh_flow::Configuration>,
     ) -> zenoh_flow::ZFResult<zenoh_flow::State> {
-        // FIXME
-        let mut config = zenoh::config::default();
-        config
-            .connect
-            .set_endpoints(
-                vec!["tcp/192.168.86.12:7447"]
+        // The endpoints of the robot, e.g. `["tcp/192.168.86.12:7447"]`. Without any, the session
+        // is opened with the default configuration and no hardware is required (e.g. for replays).
+        let endpoints = configuration
+            .as_ref()
+            .and_then(|c| c["connect"].as_array())
+            .map(|endpoints| {
+                endpoints
                     .iter()
-                    .filter_map(|l| l.parse().ok())
-                    .collect(),
-            )
-            .expect("Could not set locator");
+                    .filter_map(|e| e.as_str())
+                    .filter_map(|e| e.parse().ok())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut config = zenoh::config::default();
+        if !endpoints.is_empty() {
+            config
+                .connect
+                .set_endpoints(endpoints)
+                .map_err(|_| ZFError::IOError("Could not set locator".to_string()))?;
+        }
 
-        let session = zenoh::open(config).wait().expect("Could not open Session.");
+        let session = zenoh::open(config)
+            .wait()
+            .map_err(|e| ZFError::IOError(format!("Could not open Session: {}", e)))?;
         let expr_id = session
             .declare_expr("/rt/cmd_vel")
             .wait()
@@ -0,0 +1,194 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+
+use async_trait::async_trait;
+use std::fs::File;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use types::RecordedData;
+use zenoh_flow::{
+    zenoh_flow_derive::ZFState, Configuration, Context, Data, Node, Source, State, ZFError,
+    ZFResult,
+};
+
+const DEFAULT_PATH: &str = "recording.bin";
+
+#[derive(Debug, ZFState)]
+pub struct ReplayState {
+    reader: BufReader<File>,
+    time_scale: f64,
+    looping: bool,
+    // Timestamp of the first recorded message and instant at which it was replayed.
+    origin: Option<(u64, Instant)>,
+}
+
+impl ReplayState {
+    fn next_record(&mut self) -> ZFResult<Option<RecordedData>> {
+        match bincode::deserialize_from::<_, RecordedData>(&mut self.reader) {
+            Ok(record) => Ok(Some(record)),
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(ref io)
+                    if io.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    Ok(None)
+                }
+                _ => Err(ZFError::DeseralizationError),
+            },
+        }
+    }
+
+    fn rewind(&mut self) -> ZFResult<()> {
+        self.reader
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| ZFError::IOError(format!("Could not rewind recording: {}", e)))?;
+        self.origin = None;
+        Ok(())
+    }
+}
+
+/// Returns the `time-scale` configuration, 1.0 by default.
+fn parse_time_scale(configuration: &Option<Configuration>) -> ZFResult<f64> {
+    let time_scale = match configuration.as_ref().map(|c| &c["time-scale"]) {
+        Some(value) if !value.is_null() => value.as_f64().ok_or_else(|| {
+            ZFError::InvalidData(format!("time-scale must be a number, not {}", value))
+        })?,
+        _ => 1.0,
+    };
+    if !time_scale.is_finite() || time_scale <= 0.0 {
+        return Err(ZFError::InvalidData(format!(
+            "time-scale must be positive, not {}",
+            time_scale
+        )));
+    }
+    Ok(time_scale)
+}
+
+/// Replays a file written by `sink-record`.
+///
+/// Messages are emitted with their original timing, multiplied by the `time-scale` configuration
+/// (e.g. `0.5` replays twice as fast). The source should be declared in the flow with a period
+/// shorter than the smallest interval between two recorded messages, as it sleeps on its own until
+/// the next message is due.
+pub struct ReplaySource;
+
+impl Node for ReplaySource {
+    fn initialize(&self, configuration: &Option<Configuration>) -> ZFResult<State> {
+        let path = configuration
+            .as_ref()
+            .and_then(|c| c["path"].as_str())
+            .unwrap_or(DEFAULT_PATH);
+        let time_scale = parse_time_scale(configuration)?;
+        let looping = configuration
+            .as_ref()
+            .and_then(|c| c["loop"].as_bool())
+            .unwrap_or(false);
+
+        let file = File::open(path)
+            .map_err(|e| ZFError::IOError(format!("Could not open {}: {}", path, e)))?;
+
+        Ok(State::from(ReplayState {
+            reader: BufReader::new(file),
+            time_scale,
+            looping,
+            origin: None,
+        }))
+    }
+
+    fn finalize(&self, _: &mut State) -> ZFResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Source for ReplaySource {
+    async fn run(&self, _: &mut Context, dyn_state: &mut State) -> ZFResult<Data> {
+        let state = dyn_state.try_get::<ReplayState>()?;
+
+        let record = match state.next_record()? {
+            Some(record) => record,
+            None if state.looping => {
+                state.rewind()?;
+                state
+                    .next_record()?
+                    .ok_or_else(|| ZFError::InvalidData("Empty recording".to_string()))?
+            }
+            None => {
+                // Nothing left to replay: never produce anything again.
+                async_std::future::pending::<()>().await;
+                unreachable!()
+            }
+        };
+
+        let (first_timestamp, start) = *state
+            .origin
+            .get_or_insert((record.timestamp, Instant::now()));
+        let offset = Duration::from_nanos(record.timestamp.saturating_sub(first_timestamp))
+            .as_secs_f64()
+            * state.time_scale;
+        let offset = Duration::try_from_secs_f64(offset)
+            .map_err(|e| ZFError::InvalidData(format!("Invalid replay offset: {}", e)))?;
+        let elapsed = start.elapsed();
+        if offset > elapsed {
+            async_std::task::sleep(offset - elapsed).await;
+        }
+
+        Ok(Data::from_bytes(record.payload))
+    }
+}
+
+zenoh_flow::export_source!(register);
+
+fn register() -> ZFResult<Arc<dyn Source>> {
+    Ok(Arc::new(ReplaySource) as Arc<dyn Source>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuration(time_scale: Configuration) -> Option<Configuration> {
+        let mut configuration = Configuration::default();
+        configuration["time-scale"] = time_scale;
+        Some(configuration)
+    }
+
+    #[test]
+    fn time_scale_defaults_to_original_timing() {
+        assert_eq!(parse_time_scale(&None).unwrap(), 1.0);
+        assert_eq!(
+            parse_time_scale(&Some(Configuration::default())).unwrap(),
+            1.0
+        );
+        assert_eq!(
+            parse_time_scale(&configuration(Configuration::from(0.5))).unwrap(),
+            0.5
+        );
+        assert_eq!(
+            parse_time_scale(&configuration(Configuration::from(2))).unwrap(),
+            2.0
+        );
+    }
+
+    #[test]
+    fn time_scale_rejects_invalid_values() {
+        for time_scale in [
+            Configuration::from(0.0),
+            Configuration::from(-1.0),
+            Configuration::from("fast"),
+        ] {
+            assert!(parse_time_scale(&configuration(time_scale)).is_err());
+        }
+    }
+}
@@ -92,3 +92,76 @@ impl From<&GamepadInput> for Twist {
         Self { linear, angular }
     }
 }
+
+/// A `Data` message, as written by `sink-record` and read back by `source-replay`.
+///
+/// The payload is the bincode serialization of the message (see the `ZFData` implementations
+/// above) and the timestamp is the one of the `DataMessage`, in nanoseconds.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RecordedData {
+    pub timestamp: u64,
+    pub payload: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_data_round_trips_through_bincode() {
+        let twist = Twist {
+            linear: 0.1,
+            angular: -1.3,
+        };
+        let record = RecordedData {
+            timestamp: 1_650_000_000_123_456_789,
+            payload: twist.try_serialize().unwrap(),
+        };
+
+        // Records are written back to back in a file, and read until its end
+        let mut file = bincode::serialize(&record).unwrap();
+        file.extend(bincode::serialize(&record).unwrap());
+        let mut reader = file.as_slice();
+        for _ in 0..2 {
+            let read = bincode::deserialize_from::<_, RecordedData>(&mut reader).unwrap();
+            assert_eq!(read.timestamp, record.timestamp);
+            let replayed = Twist::try_deserialize(&read.payload).unwrap();
+            assert_eq!(replayed.linear, twist.linear);
+            assert_eq!(replayed.angular, twist.angular);
+        }
+        assert!(reader.is_empty());
+        assert!(bincode::deserialize_from::<_, RecordedData>(&mut reader).is_err());
+    }
+
+    #[test]
+    fn gamepad_input_converts_to_twist() {
+        let twist = Twist::from(&GamepadInput::default());
+        assert_eq!((twist.linear, twist.angular), (0.0, 0.0));
+
+        // Right trigger goes forward, left trigger backward
+        let forward = Twist::from(&GamepadInput {
+            right_trigger: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(forward.linear, LINEAR_SCALING_FACTOR);
+        let backward = Twist::from(&GamepadInput {
+            left_trigger: 0.5,
+            ..Default::default()
+        });
+        assert_eq!(backward.linear, -0.5 * LINEAR_SCALING_FACTOR);
+        let both = Twist::from(&GamepadInput {
+            left_trigger: 1.0,
+            right_trigger: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(both.linear, 0.0);
+
+        // The stick is swapped: pushing it right turns clockwise
+        let right = Twist::from(&GamepadInput {
+            left_stick_x: 1.0,
+            ..Default::default()
+        });
+        assert_eq!(right.angular, -ANGULAR_SCALING_FACTOR);
+        assert_eq!(right.linear, 0.0);
+    }
+}